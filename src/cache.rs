@@ -0,0 +1,459 @@
+use crate::logger::get_log_dir;
+use crate::shadow;
+use crate::utils::{self, CommandOutput};
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::UNIX_EPOCH;
+
+const VERSION_CACHE_FILE: &str = "versions.json";
+const CONFIG_CACHE_DIR: &str = "config";
+
+/// Arguments which are only used to query the version of a tool.
+/// Only commands made up exclusively of these arguments are cached.
+const VERSION_ARGS: &[&str] = &[
+    "--version",
+    "-version",
+    "version",
+    "-V",
+    "-v",
+    "-vnum",
+    "--numeric-version",
+    "--short",
+    "--scala",
+];
+
+/// Binaries of version managers which run a different version of a tool depending on the
+/// directory or the environment, and are installed under the name of the tool
+/// (e.g. rustup installs `rustc` as a link to itself).
+const PROXY_BINARIES: &[&str] = &["rustup", "rustup-init", "volta-shim", "proto-shim", "mise"];
+
+/// Returns the path of the on-disk version cache.
+pub fn get_version_cache_path() -> PathBuf {
+    get_log_dir().join(VERSION_CACHE_FILE)
+}
+
+/// Returns the directory of the on-disk cache of parsed config files.
+pub fn get_config_cache_dir() -> PathBuf {
+    get_log_dir().join(CONFIG_CACHE_DIR)
+}
+
+/// Returns true if a command with these arguments only asks a tool for its version.
+pub fn is_version_query<U: AsRef<OsStr>>(args: &[U]) -> bool {
+    !args.is_empty()
+        && args
+            .iter()
+            .all(|arg| VERSION_ARGS.iter().any(|v| OsStr::new(v) == arg.as_ref()))
+}
+
+/// Deletes all starship caches.
+pub fn clear() -> io::Result<()> {
+    match fs::remove_file(get_version_cache_path()) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    match fs::remove_dir_all(get_config_cache_dir()) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Cache of the output of version commands, keyed by the resolved path of the binary,
+/// its modification time and size, and the arguments it was called with.
+/// Shims and proxies of version managers are never cached, see [`is_version_proxy`].
+pub struct VersionCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, CommandOutput>>,
+}
+
+impl VersionCache {
+    /// Loads the cache stored at `path`. A missing or unreadable file results in an empty cache.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = utils::read_file(&path)
+            .ok()
+            .and_then(|content| {
+                serde_json::from_str(&content)
+                    .inspect_err(|e| log::debug!("Unable to parse version cache: {e}"))
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Returns the cached output of running `binary` with `args`,
+    /// if the binary has not changed since the output was cached.
+    pub fn get<U: AsRef<OsStr>>(&self, binary: &Path, args: &[U]) -> Option<CommandOutput> {
+        let key = cache_key(&binary_key(binary)?, args);
+        let output = self.entries.lock().ok()?.get(&key).cloned();
+        if output.is_some() {
+            log::trace!("Using cached output for {key:?}");
+        }
+        output
+    }
+
+    /// Stores the output of running `binary` with `args` and writes the cache to disk.
+    /// Entries of previous versions of the binary are dropped.
+    pub fn insert<U: AsRef<OsStr>>(&self, binary: &Path, args: &[U], output: CommandOutput) {
+        let Some(binary_key) = binary_key(binary) else {
+            return;
+        };
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let path_prefix = format!("{}|", binary.to_string_lossy());
+        let current_prefix = format!("{binary_key}|");
+        entries.retain(|key, _| !key.starts_with(&path_prefix) || key.starts_with(&current_prefix));
+        entries.insert(cache_key(&binary_key, args), output);
+
+        let content = match serde_json::to_string(&*entries) {
+            Ok(content) => content,
+            Err(e) => {
+                log::debug!("Unable to serialize version cache: {e}");
+                return;
+            }
+        };
+        if let Some(parent) = self.path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = utils::write_file_atomic(&self.path, content, true) {
+            log::debug!("Unable to write version cache: {e}");
+        }
+    }
+}
+
+/// Cache of parsed config files, stored as JSON which is faster to read than TOML.
+/// Entries hold the table after renamed options were migrated, along with the warnings
+/// about them, so a cached file doesn't need to be migrated again.
+/// Every config file has its own entry, keyed by its path, modification time and size,
+/// and the starship version.
+pub struct ConfigCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedConfig<'a> {
+    key: Cow<'a, str>,
+    config: Cow<'a, toml::Table>,
+    warnings: Cow<'a, [String]>,
+}
+
+/// A migrated config file, with the warnings to log about its renamed options
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigratedConfig {
+    pub config: toml::Table,
+    pub warnings: Vec<String>,
+}
+
+impl ConfigCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Returns the key of the config file at `path`. It must be taken before reading the
+    /// file, so that changes made while it is read are noticed.
+    pub fn key(path: &Path) -> Option<String> {
+        let (modified, len) = file_stamp(path)?;
+        Some(format!(
+            "{}|{}|{modified}|{len}",
+            shadow::PKG_VERSION,
+            path.to_string_lossy()
+        ))
+    }
+
+    /// Returns the cached config of the file at `path`, if it was stored with the same key.
+    pub fn get(&self, path: &Path, key: &str) -> Option<MigratedConfig> {
+        let content = utils::read_file(self.entry_path(path)).ok()?;
+        let cached: CachedConfig = serde_json::from_str(&content)
+            .inspect_err(|e| log::debug!("Unable to parse config cache: {e}"))
+            .ok()?;
+        if cached.key != key {
+            return None;
+        }
+        log::trace!("Using cached config for {key:?}");
+        Some(MigratedConfig {
+            config: cached.config.into_owned(),
+            warnings: cached.warnings.into_owned(),
+        })
+    }
+
+    /// Stores the parsed config of the file at `path`.
+    pub fn insert(&self, path: &Path, key: &str, migrated: &MigratedConfig) {
+        let cached = CachedConfig {
+            key: Cow::Borrowed(key),
+            config: Cow::Borrowed(&migrated.config),
+            warnings: Cow::Borrowed(&migrated.warnings),
+        };
+        let content = match serde_json::to_string(&cached) {
+            Ok(content) => content,
+            Err(e) => {
+                log::debug!("Unable to serialize config cache: {e}");
+                return;
+            }
+        };
+        let _ = fs::create_dir_all(&self.dir);
+        if let Err(e) = utils::write_file_atomic(self.entry_path(path), content, true) {
+            log::debug!("Unable to write config cache: {e}");
+        }
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+}
+
+/// The modification time and size of a file, see [`file_stamp`]
+type FileStamp = (u128, u64);
+
+/// Returns the modification time and size of the file at `path`,
+/// used to detect whether the file has changed since it was last read.
+pub(crate) fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((modified, metadata.len()))
+}
+
+/// Returns true if `binary` may report a different version depending on the directory
+/// or the environment it is run in, like the shims of pyenv, asdf or mise, the proxies of
+/// rustup and volta, or any script wrapping the real tool.
+/// The result is kept for the process, until the binary changes.
+fn is_version_proxy(binary: &Path, stamp: FileStamp) -> bool {
+    static CHECKED: LazyLock<Mutex<HashMap<PathBuf, (FileStamp, bool)>>> =
+        LazyLock::new(Mutex::default);
+
+    if let Ok(checked) = CHECKED.lock()
+        && let Some(&(checked_stamp, is_proxy)) = checked.get(binary)
+        && checked_stamp == stamp
+    {
+        return is_proxy;
+    }
+    let is_proxy = check_version_proxy(binary, stamp);
+    if let Ok(mut checked) = CHECKED.lock() {
+        checked.insert(binary.to_path_buf(), (stamp, is_proxy));
+    }
+    is_proxy
+}
+
+fn check_version_proxy(binary: &Path, stamp: FileStamp) -> bool {
+    if binary
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|dir| dir == "shims")
+    {
+        return true;
+    }
+
+    // Symlinks to a proxy binary, e.g. `~/.volta/bin/node`
+    let proxy_name = |path: &Path| {
+        path.file_stem()
+            .and_then(OsStr::to_str)
+            .is_some_and(|name| PROXY_BINARIES.contains(&name))
+    };
+    if dunce::canonicalize(binary).is_ok_and(|target| proxy_name(&target)) {
+        return true;
+    }
+
+    // Hard links or copies of a proxy binary next to it, e.g. `~/.cargo/bin/rustc`
+    if let Some(dir) = binary.parent()
+        && PROXY_BINARIES.iter().any(|proxy| {
+            let proxy = dir.join(proxy).with_extension(env::consts::EXE_EXTENSION);
+            proxy != binary && file_stamp(&proxy) == Some(stamp)
+        })
+    {
+        return true;
+    }
+
+    // Scripts can pick the tool to run in any way
+    let mut start = [0; 2];
+    fs::File::open(binary)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok_and(|()| &start == b"#!")
+}
+
+/// The part of the cache keys identifying the current version of `binary`, made up of its path,
+/// modification time and size. `None` if its version shouldn't be cached.
+fn binary_key(binary: &Path) -> Option<String> {
+    let (modified, len) = file_stamp(binary)?;
+    if is_version_proxy(binary, (modified, len)) {
+        log::trace!("Not caching the version of {binary:?}, which is a version manager proxy");
+        return None;
+    }
+    Some(format!("{}|{modified}|{len}", binary.to_string_lossy()))
+}
+
+fn cache_key<U: AsRef<OsStr>>(binary_key: &str, args: &[U]) -> String {
+    let args = args
+        .iter()
+        .map(|arg| arg.as_ref().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{binary_key}|{args}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, FileTimes};
+    use std::time::{Duration, SystemTime};
+
+    fn output(stdout: &str) -> CommandOutput {
+        CommandOutput {
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        }
+    }
+
+    #[test]
+    fn version_query_detection() {
+        assert!(is_version_query(&["--version"]));
+        assert!(is_version_query(&["version", "--short"]));
+        assert!(!is_version_query::<&str>(&[]));
+        assert!(!is_version_query(&["status", "--json"]));
+        assert!(!is_version_query(&["-e", "printf q#%vd#,$^V;"]));
+    }
+
+    #[test]
+    fn cache_round_trip() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let binary = dir.path().join("tool");
+        fs::write(&binary, "binary")?;
+        let cache_path = dir.path().join(VERSION_CACHE_FILE);
+
+        let cache = VersionCache::load(cache_path.clone());
+        assert_eq!(cache.get(&binary, &["--version"]), None);
+        cache.insert(&binary, &["--version"], output("1.2.3\n"));
+        assert_eq!(cache.get(&binary, &["--version"]), Some(output("1.2.3\n")));
+        assert_eq!(cache.get(&binary, &["-V"]), None);
+
+        // A freshly loaded cache reads the entries back from disk
+        let cache = VersionCache::load(cache_path);
+        assert_eq!(cache.get(&binary, &["--version"]), Some(output("1.2.3\n")));
+        dir.close()
+    }
+
+    #[test]
+    fn version_proxies_are_not_cached() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = VersionCache::load(dir.path().join(VERSION_CACHE_FILE));
+
+        let shims = dir.path().join("shims");
+        fs::create_dir(&shims)?;
+        let shim = shims.join("python");
+        fs::write(&shim, "binary")?;
+
+        let script = dir.path().join("node");
+        fs::write(&script, "#!/bin/sh\nexec node-20 \"$@\"\n")?;
+
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin)?;
+        fs::write(bin.join("rustup"), "rustup")?;
+        let rustc = bin.join("rustc");
+        fs::hard_link(bin.join("rustup"), &rustc)?;
+        let cargo = bin.join("cargo");
+        fs::write(&cargo, "cargo")?;
+
+        for binary in [&shim, &script, &rustc] {
+            cache.insert(binary, &["--version"], output("1.0.0\n"));
+            assert_eq!(cache.get(binary, &["--version"]), None, "{binary:?}");
+        }
+        cache.insert(&cargo, &["--version"], output("1.0.0\n"));
+        assert_eq!(cache.get(&cargo, &["--version"]), Some(output("1.0.0\n")));
+        dir.close()
+    }
+
+    #[test]
+    fn config_cache_round_trip() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_path = dir.path().join("starship.toml");
+        fs::write(&config_path, "format = '$all'")?;
+        let mut config = toml::toml! {
+            format = "$all"
+            scan_timeout = 10
+            [time]
+            utc_time_offset = "-5"
+            [palettes.dark]
+            blue = "#1e66f5"
+        };
+        config.insert(
+            "date".to_string(),
+            toml::Value::Datetime("2024-01-01T00:00:00Z".parse().unwrap()),
+        );
+
+        let migrated = MigratedConfig {
+            config,
+            warnings: vec!["`old` was renamed to `new`".to_string()],
+        };
+
+        let cache = ConfigCache::new(dir.path().join(CONFIG_CACHE_DIR));
+        let key = ConfigCache::key(&config_path).unwrap();
+        assert_eq!(cache.get(&config_path, &key), None);
+        cache.insert(&config_path, &key, &migrated);
+        assert_eq!(cache.get(&config_path, &key), Some(migrated));
+
+        // Editing the file changes the key
+        fs::write(&config_path, "format = '$directory'")?;
+        let key = ConfigCache::key(&config_path).unwrap();
+        assert_eq!(cache.get(&config_path, &key), None);
+        dir.close()
+    }
+
+    #[test]
+    fn cache_invalidated_when_binary_changes() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let binary = dir.path().join("tool");
+        fs::write(&binary, "binary")?;
+
+        let cache = VersionCache::load(dir.path().join(VERSION_CACHE_FILE));
+        cache.insert(&binary, &["--version"], output("1.2.3\n"));
+
+        // Same size, different modification time
+        fs::write(&binary, "BINARY")?;
+        File::options().write(true).open(&binary)?.set_times(
+            FileTimes::new().set_modified(SystemTime::now() + Duration::from_secs(60)),
+        )?;
+        assert_eq!(cache.get(&binary, &["--version"]), None);
+
+        // Different size
+        cache.insert(&binary, &["--version"], output("1.2.4\n"));
+        fs::write(&binary, "a larger binary")?;
+        assert_eq!(cache.get(&binary, &["--version"]), None);
+
+        // Only the entries of the current binary are kept
+        cache.insert(&binary, &["--version"], output("1.2.5\n"));
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+        assert_eq!(cache.get(&binary, &["--version"]), Some(output("1.2.5\n")));
+        dir.close()
+    }
+
+    #[test]
+    fn corrupt_cache_is_ignored() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let binary = dir.path().join("tool");
+        fs::write(&binary, "binary")?;
+        let cache_path = dir.path().join(VERSION_CACHE_FILE);
+        fs::write(&cache_path, "not json")?;
+
+        let cache = VersionCache::load(cache_path);
+        assert_eq!(cache.get(&binary, &["--version"]), None);
+        dir.close()
+    }
+}
@@ -1,9 +1,12 @@
-use crate::configs::Palette;
-use crate::context::Context;
+use crate::configs::{ColorMode, Palette, default_profiles};
+use crate::context::{Context, Env, Shell};
 
+use crate::cache::{ConfigCache, MigratedConfig};
+use crate::config_migrate::{Migration, migrate_document};
 use crate::utils;
 use crate::utils::serde::{ValueDeserializer, ValueRef};
 use nu_ansi_term::Color;
+use path_slash::PathExt;
 use serde::{
     Deserialize, Deserializer, Serialize, de::Error as SerdeError, de::value::Error as ValueError,
 };
@@ -13,8 +16,13 @@ use std::clone::Clone;
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use toml::Value;
+use toml_edit::DocumentMut;
 
 /// Root config of a module.
 pub trait ModuleConfig<'a, E>
@@ -78,7 +86,7 @@ pub enum Either<A, B> {
 
 /// A wrapper around `Vec<T>` that implements `ModuleConfig`, and either
 /// accepts a value of type `T` or a list of values of type `T`.
-#[derive(Clone, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct VecOr<T>(pub Vec<T>);
 
 impl<'de, T> Deserialize<'de> for VecOr<T>
@@ -116,30 +124,71 @@ where
     }
 }
 
+/// Name of the project-local config file, discovered from the current directory upward
+pub const PROJECT_CONFIG_FILE: &str = ".starship.toml";
+
 /// Root config of starship.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct StarshipConfig {
     pub config: Option<toml::Table>,
+    /// Files the config was read from, in the order they were merged
+    pub files: Vec<PathBuf>,
+    /// Index into `files` of the file each value was read from, by the path of its key
+    pub origins: HashMap<Vec<String>, usize>,
+    /// `[[when]]` blocks which haven't been applied yet, with the index of their file
+    pub when: Vec<(usize, toml::Table)>,
+    /// Environment variables which override config values, by the path of the value
+    pub env_overrides: HashMap<Vec<String>, String>,
+    /// Directory of the cache of parsed config files, if they should be cached
+    pub cache_dir: Option<PathBuf>,
 }
 
 impl StarshipConfig {
-    /// Initialize the Config struct
-    pub fn initialize(config_file_path: Option<&OsStr>) -> Self {
-        Self::config_from_file(config_file_path)
-            .map(|config| Self {
-                config: Some(config),
-            })
-            .unwrap_or_default()
+    /// Initialize the Config struct, caching the parsed config files in `cache_dir` if set
+    pub fn initialize(config_file_path: Option<&OsStr>, cache_dir: Option<PathBuf>) -> Self {
+        let mut config = Self {
+            cache_dir,
+            ..Default::default()
+        };
+        if let Some(path) = config_file_path {
+            config.merge_file(Path::new(path), &mut Vec::new());
+        } else {
+            // Logs why there is no config
+            Self::read_config_content_as_str(None);
+        }
+        config
     }
 
-    /// Create a config from a starship configuration file
-    fn config_from_file(config_file_path: Option<&OsStr>) -> Option<toml::Table> {
+    /// Create a config from a starship configuration file and migrate its renamed options,
+    /// or read it from the cache in `cache_dir` if the file hasn't changed since it was cached
+    fn config_from_file(
+        config_file_path: Option<&OsStr>,
+        cache_dir: Option<&Path>,
+    ) -> Option<MigratedConfig> {
+        let cached = cache_dir.zip(config_file_path).and_then(|(dir, path)| {
+            let path = Path::new(path);
+            Some((
+                ConfigCache::new(dir.to_path_buf()),
+                path,
+                ConfigCache::key(path)?,
+            ))
+        });
+        if let Some((cache, path, key)) = &cached
+            && let Some(migrated) = cache.get(path, key)
+        {
+            return Some(migrated);
+        }
+
         let toml_content = Self::read_config_content_as_str(config_file_path)?;
 
-        match toml::from_str(&toml_content) {
+        match toml_content.parse::<DocumentMut>() {
             Ok(parsed) => {
-                log::debug!("Config parsed: {parsed:?}");
-                Some(parsed)
+                let migrated = migrate_config(parsed);
+                log::debug!("Config parsed: {:?}", migrated.config);
+                if let Some((cache, path, key)) = &cached {
+                    cache.insert(path, key, &migrated);
+                }
+                Some(migrated)
             }
             Err(error) => {
                 log::error!("Unable to parse the config file: {error}");
@@ -148,6 +197,294 @@ impl StarshipConfig {
         }
     }
 
+    /// Reads a config file and the files listed in its `include` option,
+    /// and merges them into this config. Values of the file itself take precedence
+    /// over the included ones, which take precedence over each other in order.
+    fn merge_file(&mut self, path: &Path, including: &mut Vec<PathBuf>) {
+        let canonical = dunce::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if including.contains(&canonical) {
+            log::warn!("Ignoring circular include of {}", path.display());
+            return;
+        }
+        let Some(MigratedConfig {
+            config: mut table,
+            warnings,
+        }) = Self::config_from_file(Some(path.as_os_str()), self.cache_dir.as_deref())
+        else {
+            return;
+        };
+        // The logger only writes each message once per session
+        for warning in warnings {
+            log::warn!("{}: {warning}", path.display());
+        }
+
+        let includes = match table.remove("include") {
+            Some(Value::String(include)) => vec![include],
+            Some(Value::Array(includes)) => includes
+                .into_iter()
+                .filter_map(|include| match include {
+                    Value::String(include) => Some(include),
+                    other => {
+                        log::warn!("Ignoring include of {other}, expected a path");
+                        None
+                    }
+                })
+                .collect(),
+            Some(other) => {
+                log::warn!("Ignoring include of {other}, expected a path or a list of paths");
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        including.push(canonical);
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in includes {
+            let include = expand_home(&include);
+            self.merge_file(&base.join(include), including);
+        }
+        including.pop();
+
+        let index = self.files.len();
+        self.files.push(path.to_path_buf());
+        self.take_when_blocks(&mut table, index);
+        let config = self.config.get_or_insert_default();
+        merge_tables(config, table, &mut Vec::new(), index, &mut self.origins);
+    }
+
+    /// Moves the `[[when]]` blocks out of a config file, to be applied once the
+    /// environment is known.
+    fn take_when_blocks(&mut self, table: &mut toml::Table, index: usize) {
+        let blocks = match table.remove("when") {
+            Some(Value::Array(blocks)) => blocks,
+            Some(other) => {
+                log::warn!(
+                    "Ignoring `when` of type {}, expected [[when]] tables",
+                    other.type_str()
+                );
+                return;
+            }
+            None => return,
+        };
+        for block in blocks {
+            match block {
+                Value::Table(block) => self.when.push((index, block)),
+                other => log::warn!("Ignoring `when` entry {other}, expected a table"),
+            }
+        }
+    }
+
+    /// Deep-merges the contents of every `[[when]]` block whose conditions match the
+    /// environment into the config, in the order they were defined.
+    pub fn apply_when_blocks(&mut self, facts: &WhenFacts) {
+        for (index, mut block) in std::mem::take(&mut self.when) {
+            let conditions = WHEN_CONDITIONS
+                .iter()
+                .filter_map(|&key| {
+                    // Modules with the same name as a condition are configured with tables
+                    let condition = block.get(key).filter(|value| !value.is_table())?.clone();
+                    block.remove(key);
+                    Some((key, condition))
+                })
+                .collect::<Vec<_>>();
+
+            if facts.matches_all(&conditions) {
+                log::debug!("Applying [[when]] block matching {conditions:?}");
+                let config = self.config.get_or_insert_default();
+                merge_tables(config, block, &mut Vec::new(), index, &mut self.origins);
+            }
+        }
+    }
+
+    /// Merges the nearest project-local config above `dir`, if it is in a trusted directory.
+    pub fn merge_project_config(&mut self, dir: &Path) {
+        let trusted = self.trusted_project_dirs();
+        if trusted.is_empty() {
+            return;
+        }
+        let Some(path) = find_project_config(dir) else {
+            return;
+        };
+        if !is_trusted_project_config(&path, &trusted) {
+            log::debug!("Ignoring untrusted project config {}", path.display());
+            return;
+        }
+
+        let mut project = Self {
+            cache_dir: self.cache_dir.clone(),
+            ..Default::default()
+        };
+        project.merge_file(&path, &mut Vec::new());
+        let Some(mut table) = project.config else {
+            return;
+        };
+        // A project must not be able to extend the trust to other projects
+        if table.remove("trusted_project_configs").is_some() {
+            log::warn!(
+                "Ignoring `trusted_project_configs` in project config {}",
+                path.display()
+            );
+        }
+
+        let offset = self.files.len();
+        self.files.extend(project.files);
+        self.when.extend(
+            project
+                .when
+                .into_iter()
+                .map(|(index, block)| (offset + index, block)),
+        );
+        let config = self.config.get_or_insert_default();
+        let mut origins = HashMap::new();
+        merge_tables(config, table, &mut Vec::new(), 0, &mut origins);
+        // Keep the files the project values were read from, which includes its own includes
+        for path in origins.into_keys() {
+            let index = project.origins.get(&path).map_or(offset, |i| offset + i);
+            self.origins.insert(path, index);
+        }
+    }
+
+    /// Directories from the `trusted_project_configs` option
+    pub fn trusted_project_dirs(&self) -> Vec<PathBuf> {
+        self.get_config(&["trusted_project_configs"])
+            .and_then(Value::as_array)
+            .map(|dirs| {
+                dirs.iter()
+                    .filter_map(Value::as_str)
+                    .map(expand_home)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Finds the profile of the first `[[profile_rules]]` entry whose conditions all match the
+    /// environment. The conditions are the same as those of `[[when]]` blocks.
+    pub fn match_profile_rules(&self, facts: &WhenFacts) -> Option<String> {
+        let rules = self.get_config(&["profile_rules"])?.as_array()?;
+        let profiles = self.get_config(&["profiles"]).and_then(Value::as_table);
+        for rule in rules {
+            let Some(rule) = rule.as_table() else {
+                log::warn!("Ignoring `profile_rules` entry {rule}, expected a table");
+                continue;
+            };
+            let Some(profile) = rule.get(PROFILE_RULE_PROFILE).and_then(Value::as_str) else {
+                log::warn!("Ignoring [[profile_rules]] entry without a `{PROFILE_RULE_PROFILE}`");
+                continue;
+            };
+            if !profiles.is_some_and(|profiles| profiles.contains_key(profile))
+                && !default_profiles().contains_key(profile)
+            {
+                log::warn!("Ignoring [[profile_rules]] entry for unknown profile '{profile}'");
+                continue;
+            }
+
+            let conditions = rule
+                .iter()
+                .filter(|(key, _)| *key != PROFILE_RULE_PROFILE)
+                .map(|(key, condition)| (key.as_str(), condition.clone()))
+                .collect::<Vec<_>>();
+            if facts.matches_all(&conditions) {
+                log::debug!("Selected profile '{profile}' matching {conditions:?}");
+                return Some(profile.to_owned());
+            }
+        }
+        None
+    }
+
+    /// Deep-merges the config of a profile, and of the profiles it `extends`, into the config,
+    /// so that its formats and module overrides apply. Does nothing for unknown profiles.
+    /// A profile that is only a format string sets `format`. The `right_format` and
+    /// `add_newline` of the root config are not inherited, as a profile is a prompt of its own.
+    pub fn apply_profile(&mut self, name: &str) {
+        let profiles = self
+            .get_config(&["profiles"])
+            .and_then(Value::as_table)
+            .cloned()
+            .unwrap_or_default();
+        if !profiles.contains_key(name) && !default_profiles().contains_key(name) {
+            return;
+        }
+        let chain = match resolve_profile(&profiles, name) {
+            Ok(chain) => chain,
+            Err(error) => {
+                log::warn!("Unable to apply profile '{name}': {error}");
+                return;
+            }
+        };
+
+        let config = self.config.get_or_insert_default();
+        for (key, value) in [
+            ("right_format", Value::String(String::new())),
+            ("add_newline", Value::Boolean(false)),
+        ] {
+            config.insert(key.to_owned(), value);
+            self.origins.remove(&vec![key.to_owned()]);
+        }
+
+        for (profile, table) in chain {
+            let prefix = ["profiles".to_string(), profile];
+            let origin = self
+                .origins
+                .iter()
+                .filter(|(path, _)| path.starts_with(&prefix))
+                .map(|(_, &index)| index)
+                .max()
+                .unwrap_or_else(|| self.files.len().saturating_sub(1));
+            log::debug!("Applying profile '{}'", prefix[1]);
+            let config = self.config.get_or_insert_default();
+            merge_tables(config, table, &mut Vec::new(), origin, &mut self.origins);
+        }
+    }
+
+    /// Applies `STARSHIP__<KEY>__<KEY>=<value>` environment variables on top of the config.
+    /// Keys match existing keys regardless of case, and are lowercased otherwise. Values are
+    /// parsed like in `starship config <key> <value>`.
+    pub fn apply_env_overrides(&mut self, env: &Env) {
+        let mut names = env
+            .names()
+            .into_iter()
+            .filter(|name| name.starts_with(ENV_OVERRIDE_PREFIX))
+            .collect::<Vec<_>>();
+        names.sort();
+
+        for name in names {
+            let Some(value) = env.get_env(&name) else {
+                continue;
+            };
+            let keys = name[ENV_OVERRIDE_PREFIX.len()..]
+                .split("__")
+                .collect::<Vec<_>>();
+            if keys.iter().any(|key| key.is_empty()) {
+                log::warn!("Ignoring ${name}: empty config keys are not supported");
+                continue;
+            }
+
+            let value = toml_value(parse_config_value(&value));
+            let config = self.config.get_or_insert_default();
+            let path = env_override_path(config, &keys);
+            if let Err(error) = set_config_value(config, &path, value) {
+                log::warn!("Ignoring ${name}: {error}");
+                continue;
+            }
+            log::debug!("Config value {} overridden by ${name}", path.join("."));
+            self.origins.retain(|other, _| !other.starts_with(&path));
+            self.env_overrides.insert(path, name);
+        }
+    }
+
+    /// Returns the file or environment variable the value at `path` was read from,
+    /// if it was set in the config
+    pub fn origin(&self, path: &[String]) -> Option<String> {
+        let env_override = (1..=path.len()).find_map(|len| self.env_overrides.get(&path[..len]));
+        if let Some(name) = env_override {
+            return Some(format!("${name}"));
+        }
+        self.origins
+            .get(path)
+            .and_then(|&index| self.files.get(index))
+            .map(|file| file.display().to_string())
+    }
+
     pub fn read_config_content_as_str(config_file_path: Option<&OsStr>) -> Option<String> {
         if config_file_path.is_none() {
             log::debug!(
@@ -249,6 +586,351 @@ impl StarshipConfig {
     }
 }
 
+/// Deep-merges `overlay` into `base`, recording `origin` for every value set by `overlay`.
+fn merge_tables(
+    base: &mut toml::Table,
+    overlay: toml::Table,
+    path: &mut Vec<String>,
+    origin: usize,
+    origins: &mut HashMap<Vec<String>, usize>,
+) {
+    for (key, value) in overlay {
+        path.push(key.clone());
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => {
+                merge_tables(base, overlay, path, origin, origins);
+            }
+            (_, value) => {
+                // A replaced table loses the origins of its values
+                origins.retain(|other, _| !other.starts_with(path));
+                record_origins(&value, path, origin, origins);
+                base.insert(key, value);
+            }
+        }
+        path.pop();
+    }
+}
+
+fn record_origins(
+    value: &Value,
+    path: &mut Vec<String>,
+    origin: usize,
+    origins: &mut HashMap<Vec<String>, usize>,
+) {
+    if let Value::Table(table) = value {
+        for (key, value) in table {
+            path.push(key.clone());
+            record_origins(value, path, origin, origins);
+            path.pop();
+        }
+    } else {
+        origins.insert(path.clone(), origin);
+    }
+}
+
+/// Prefix of environment variables which override config values
+pub const ENV_OVERRIDE_PREFIX: &str = "STARSHIP__";
+
+/// Parses a config value given as text, like in `starship config <key> <value>`.
+/// Valid TOML values such as `true`, `10` or `['a', 'b']` keep their type,
+/// anything else is taken as a string.
+pub fn parse_config_value(value: &str) -> toml_edit::Value {
+    toml_edit::Value::from_str(value).unwrap_or_else(|_| value.into())
+}
+
+/// Converts a value parsed with `toml_edit` to a `toml` value
+fn toml_value(value: toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(string) => Value::String(string.into_value()),
+        toml_edit::Value::Integer(integer) => Value::Integer(integer.into_value()),
+        toml_edit::Value::Float(float) => Value::Float(float.into_value()),
+        toml_edit::Value::Boolean(boolean) => Value::Boolean(boolean.into_value()),
+        toml_edit::Value::Datetime(datetime) => Value::Datetime(datetime.into_value()),
+        toml_edit::Value::Array(array) => Value::Array(array.into_iter().map(toml_value).collect()),
+        toml_edit::Value::InlineTable(table) => Value::Table(
+            table
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), toml_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts a table parsed with `toml_edit` to a `toml` table
+fn toml_table(table: toml_edit::Table) -> toml::Table {
+    table
+        .into_iter()
+        .filter_map(|(key, item)| {
+            let value = match item {
+                toml_edit::Item::None => return None,
+                toml_edit::Item::Value(value) => toml_value(value),
+                toml_edit::Item::Table(table) => Value::Table(toml_table(table)),
+                toml_edit::Item::ArrayOfTables(tables) => Value::Array(
+                    tables
+                        .into_iter()
+                        .map(|table| Value::Table(toml_table(table)))
+                        .collect(),
+                ),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Sets the value at `path`, creating the tables leading to it
+/// The config path an environment override with `keys` sets. Each key matches the existing key
+/// of the same name regardless of case, like the names of palettes or custom modules, and keys
+/// that don't exist yet are lowercased.
+fn env_override_path(config: &toml::Table, keys: &[&str]) -> Vec<String> {
+    let mut table = Some(config);
+    keys.iter()
+        .map(|key| {
+            let existing = table.and_then(|table| {
+                table.get_key_value(&key.to_lowercase()).or_else(|| {
+                    table
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                })
+            });
+            table = existing.and_then(|(_, value)| value.as_table());
+            existing.map_or_else(|| key.to_lowercase(), |(name, _)| name.clone())
+        })
+        .collect()
+}
+
+fn set_config_value(config: &mut toml::Table, path: &[String], value: Value) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        return Err("Empty table keys are not supported".to_owned());
+    };
+    let mut table = config;
+    for key in parents {
+        table = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("`{key}` is not a table"))?;
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Keys of a `[[when]]` block which are conditions rather than config
+pub const WHEN_CONDITIONS: &[&str] = &["hostname", "os", "shell", "directory", "env"];
+
+/// The key of a `[[profile_rules]]` entry naming the profile it selects, the other keys being
+/// conditions like those of `[[when]]` blocks
+pub const PROFILE_RULE_PROFILE: &str = "profile";
+
+/// The environment `[[when]]` blocks and `[[profile_rules]]` are matched against
+pub struct WhenFacts<'a> {
+    pub shell: Shell,
+    pub current_dir: &'a Path,
+    pub logical_dir: &'a Path,
+    pub env: &'a Env<'a>,
+    /// The hostname and the OS type, looked up by the first condition that needs them
+    hostname: OnceLock<Option<String>>,
+    os_type: OnceLock<String>,
+}
+
+impl<'a> WhenFacts<'a> {
+    pub fn new(
+        shell: Shell,
+        current_dir: &'a Path,
+        logical_dir: &'a Path,
+        env: &'a Env<'a>,
+    ) -> Self {
+        Self {
+            shell,
+            current_dir,
+            logical_dir,
+            env,
+            hostname: OnceLock::new(),
+            os_type: OnceLock::new(),
+        }
+    }
+
+    /// Whether every condition matches, where each condition is a pattern or a list of patterns
+    fn matches_all(&self, conditions: &[(&str, Value)]) -> bool {
+        conditions.iter().all(|(key, condition)| {
+            let patterns = match condition {
+                Value::Array(patterns) => patterns.iter().filter_map(Value::as_str).collect(),
+                other => other.as_str().into_iter().collect::<Vec<_>>(),
+            };
+            patterns.iter().any(|pattern| self.matches(key, pattern))
+        })
+    }
+
+    fn matches(&self, condition: &str, pattern: &str) -> bool {
+        match condition {
+            "hostname" => self
+                .hostname
+                .get_or_init(|| {
+                    whoami::hostname()
+                        .inspect_err(|e| log::warn!("Failed to get hostname: {e}"))
+                        .ok()
+                        .map(|hostname| hostname.to_lowercase())
+                })
+                .as_ref()
+                .is_some_and(|hostname| utils::glob_match(&pattern.to_lowercase(), hostname)),
+            // The same names as the `type` variable of the `os` module
+            "os" => self
+                .os_type
+                .get_or_init(|| format!("{:?}", os_info::get().os_type()))
+                .eq_ignore_ascii_case(pattern),
+            "shell" => format!("{:?}", self.shell).eq_ignore_ascii_case(pattern),
+            "directory" => {
+                let pattern = expand_home(pattern).to_slash_lossy().into_owned();
+                [self.current_dir, self.logical_dir].iter().any(|dir| {
+                    let dir = dir.to_slash_lossy();
+                    // `dir/**` also matches `dir` itself
+                    utils::glob_match(&pattern, &dir)
+                        || pattern
+                            .strip_suffix("/**")
+                            .is_some_and(|parent| utils::glob_match(parent, &dir))
+                })
+            }
+            "env" => match pattern.split_once('=') {
+                Some((name, value)) => self
+                    .env
+                    .get_env(name)
+                    .is_some_and(|actual| utils::glob_match(value, &actual)),
+                None => self
+                    .env
+                    .get_env(pattern)
+                    .is_some_and(|value| !value.is_empty()),
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Migrates the renamed options of a parsed config file, and describes them
+fn migrate_config(mut doc: DocumentMut) -> MigratedConfig {
+    let warnings = migrate_document(&mut doc)
+        .into_iter()
+        .map(|migration| match migration {
+            Migration::Deprecated { .. } => migration.to_string(),
+            _ => format!("{migration}, run `starship config migrate` to update the file"),
+        })
+        .collect();
+    MigratedConfig {
+        config: toml_table(doc.into_table()),
+        warnings,
+    }
+}
+
+/// Measures how long it takes to parse and migrate the config `files`, and to read them
+/// from the cache of parsed config files in `cache_dir` instead. Both are done once before
+/// they are measured, so that they read the files from the same, warm OS cache.
+/// Returns `None` if some of the files aren't cached.
+pub fn measure_config_cache(files: &[PathBuf], cache_dir: &Path) -> Option<(Duration, Duration)> {
+    let cache = ConfigCache::new(cache_dir.to_path_buf());
+    let parse = |path: &Path| StarshipConfig::config_from_file(Some(path.as_os_str()), None);
+    let read_cached = |path: &Path| cache.get(path, &ConfigCache::key(path)?);
+
+    let mut parsed = Duration::ZERO;
+    let mut cached = Duration::ZERO;
+    for path in files {
+        parse(path)?;
+        read_cached(path)?;
+
+        let start = Instant::now();
+        parse(path)?;
+        parsed += start.elapsed();
+
+        let start = Instant::now();
+        read_cached(path)?;
+        cached += start.elapsed();
+    }
+    Some((parsed, cached))
+}
+
+/// The key of a profile table that names the profile it builds upon
+pub const PROFILE_EXTENDS: &str = "extends";
+
+/// Collects the config of a profile and of the profiles it `extends`, starting with the
+/// furthest base. A profile that is only a format string is treated like a table with
+/// just a `format`, and the built-in profiles can be extended as well.
+pub fn resolve_profile(
+    profiles: &toml::Table,
+    name: &str,
+) -> Result<Vec<(String, toml::Table)>, String> {
+    let mut chain: Vec<(String, toml::Table)> = Vec::new();
+    let mut current = name.to_owned();
+    loop {
+        if chain.iter().any(|(profile, _)| *profile == current) {
+            let cycle = chain
+                .iter()
+                .map(|(profile, _)| profile.as_str())
+                .collect::<Vec<_>>();
+            return Err(format!(
+                "Circular reference: {} -> {current}",
+                cycle.join(" -> ")
+            ));
+        }
+        let mut table = match profiles.get(&current) {
+            Some(Value::Table(table)) => table.clone(),
+            Some(Value::String(format)) => {
+                toml::Table::from_iter([("format".to_owned(), Value::String(format.clone()))])
+            }
+            Some(other) => {
+                return Err(format!(
+                    "Expected profile '{current}' to be a format string or a table, found {}",
+                    other.type_str()
+                ));
+            }
+            None => match default_profiles().get(&current) {
+                Some(format) => {
+                    toml::Table::from_iter([("format".to_owned(), Value::String(format.clone()))])
+                }
+                None => return Err(format!("Could not find profile '{current}'")),
+            },
+        };
+        let base = table.remove(PROFILE_EXTENDS);
+        chain.push((current, table));
+        match base {
+            Some(Value::String(base)) => current = base,
+            Some(other) => {
+                return Err(format!(
+                    "Expected `{PROFILE_EXTENDS}` to be the name of a profile, found {}",
+                    other.type_str()
+                ));
+            }
+            None => break,
+        }
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Finds the nearest project-local config file in `dir` or one of its parents
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Returns whether a project-local config file is inside one of the trusted directories
+pub fn is_trusted_project_config(path: &Path, trusted: &[PathBuf]) -> bool {
+    let dir = path.parent().unwrap_or(path);
+    let dir = dunce::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    trusted.iter().any(|trusted| {
+        let trusted = dunce::canonicalize(trusted).unwrap_or_else(|_| trusted.clone());
+        dir.starts_with(trusted)
+    })
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => utils::home_dir()
+            .map_or_else(
+                || PathBuf::from(path),
+                |home| home.join(rest.trim_start_matches(['/', '\\'])),
+            ),
+        _ => PathBuf::from(path),
+    }
+}
+
 /// Deserialize a style string in the starship format with serde
 pub fn deserialize_style<'de, D>(de: D) -> Result<Style, D::Error>
 where
@@ -265,16 +947,309 @@ enum PrevColor {
     Bg,
 }
 
+/// Whether the terminal has a light or a dark background
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Detects the background of the terminal from `STARSHIP_THEME`, the answer to the query
+    /// of the init scripts in `STARSHIP_TERMINAL_THEME` and `COLORFGBG`, in that order
+    pub fn detect(context: &Context) -> Option<Self> {
+        ["STARSHIP_THEME", "STARSHIP_TERMINAL_THEME"]
+            .iter()
+            .find_map(|name| {
+                context
+                    .get_env(name)
+                    .and_then(|theme| Self::from_name(&theme))
+            })
+            .or_else(|| {
+                context
+                    .get_env("COLORFGBG")
+                    .and_then(|colors| Self::from_colorfgbg(&colors))
+            })
+    }
+
+    /// Asks the terminal for its background color, if `palette_light` or `palette_dark` is set
+    pub fn query(context: &Context) -> Option<Self> {
+        let root_config = &context.root_config;
+        if root_config.palette_light.is_none() && root_config.palette_dark.is_none() {
+            return None;
+        }
+        utils::terminal::query_background_color(Duration::from_millis(100))
+            .map(Self::from_background)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            "" => None,
+            name => {
+                log::warn!("Unknown theme {name:?}, expected \"light\" or \"dark\"");
+                None
+            }
+        }
+    }
+
+    /// `COLORFGBG` holds the ANSI colors of the terminal as `fg;bg` or `fg;default;bg`
+    fn from_colorfgbg(colors: &str) -> Option<Self> {
+        let background = colors.rsplit(';').next()?.parse::<u8>().ok()?;
+        Some(Self::from_background(ansi256_to_rgb(background)))
+    }
+
+    /// Decides on the theme from the luminance of the background color
+    pub fn from_background((r, g, b): (u8, u8, u8)) -> Self {
+        let luminance = 0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b);
+        if luminance > 127.5 {
+            Self::Light
+        } else {
+            Self::Dark
+        }
+    }
+}
+
+impl std::fmt::Display for Theme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        })
+    }
+}
+
+/// The colors a terminal is able to display
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorLevel {
+    /// No styling at all, e.g. when `NO_COLOR` is set
+    None,
+    /// The 16 basic ANSI colors
+    Ansi16,
+    /// The xterm 256 color palette
+    Ansi256,
+    /// 24-bit RGB colors
+    #[default]
+    TrueColor,
+}
+
+impl ColorLevel {
+    /// Detects the colors supported by the terminal from `NO_COLOR`, the `color_mode` option,
+    /// `COLORTERM` and `TERM`, in that order.
+    ///
+    /// Terminals which don't set `TERM` (e.g. on Windows) or whose `TERM` isn't known to be
+    /// limited are assumed to support all colors. `color_mode` overrides the detection for
+    /// terminals which support more colors than they report, e.g. over SSH.
+    pub fn detect(context: &Context) -> Self {
+        if context.get_env("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Self::None;
+        }
+
+        match context.root_config.color_mode {
+            ColorMode::Auto => {}
+            ColorMode::TrueColor => return Self::TrueColor,
+            ColorMode::Ansi256 => return Self::Ansi256,
+            ColorMode::Ansi16 => return Self::Ansi16,
+            ColorMode::None => return Self::None,
+        }
+
+        if context
+            .get_env("COLORTERM")
+            .is_some_and(|colorterm| matches!(colorterm.as_str(), "truecolor" | "24bit"))
+        {
+            return Self::TrueColor;
+        }
+
+        let Some(term) = context.get_env("TERM") else {
+            return Self::TrueColor;
+        };
+        if term.ends_with("-direct") || term.ends_with("-truecolor") {
+            Self::TrueColor
+        } else if term.ends_with("-256color")
+            || term.starts_with("screen")
+            || term.starts_with("tmux")
+        {
+            Self::Ansi256
+        } else if matches!(
+            term.split('-').next(),
+            Some("linux" | "vt100" | "vt220" | "ansi" | "cygwin")
+        ) {
+            Self::Ansi16
+        } else {
+            Self::TrueColor
+        }
+    }
+
+    /// Replaces the colors of `style` with the closest ones this level supports
+    fn downgrade(self, style: nu_ansi_term::Style) -> nu_ansi_term::Style {
+        match self {
+            Self::TrueColor => style,
+            Self::None => nu_ansi_term::Style::default(),
+            Self::Ansi256 | Self::Ansi16 => nu_ansi_term::Style {
+                foreground: style.foreground.map(|color| self.downgrade_color(color)),
+                background: style.background.map(|color| self.downgrade_color(color)),
+                ..style
+            },
+        }
+    }
+
+    fn downgrade_color(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::Ansi256, Color::Rgb(r, g, b)) => Color::Fixed(nearest_ansi256(r, g, b)),
+            (Self::Ansi16, Color::Rgb(r, g, b)) => ansi16_color(nearest_ansi16(r, g, b)),
+            (Self::Ansi16, Color::Fixed(n)) if n >= 16 => {
+                let (r, g, b) = ansi256_to_rgb(n);
+                ansi16_color(nearest_ansi16(r, g, b))
+            }
+            (Self::Ansi16, Color::Fixed(n)) => ansi16_color(n),
+            _ => color,
+        }
+    }
+}
+
+/// The default colors of the 16 basic ANSI colors in xterm
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each channel in the 6x6x6 color cube of the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Returns the index of the closest color to `(r, g, b)` in the 256 color palette,
+/// using either the color cube or the grayscale ramp
+fn nearest_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(value))
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let cube_index = 16 + 36 * ri + 6 * gi + bi;
+
+    let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray_step = (average.saturating_sub(8) / 10).min(23);
+    let gray_value = (8 + 10 * gray_step) as u8;
+    let gray = (gray_value, gray_value, gray_value);
+
+    if distance(gray, (r, g, b)) < distance(cube, (r, g, b)) {
+        232 + gray_step as u8
+    } else {
+        cube_index as u8
+    }
+}
+
+/// Returns the index of the closest of the 16 basic ANSI colors to `(r, g, b)`
+fn nearest_ansi16(r: u8, g: u8, b: u8) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance(ANSI16_RGB[usize::from(i)], (r, g, b)))
+        .unwrap_or(0)
+}
+
+/// Returns the RGB value of a color of the 256 color palette
+fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16_RGB[usize::from(n)],
+        16..=231 => {
+            let n = usize::from(n - 16);
+            (
+                CUBE_LEVELS[n / 36],
+                CUBE_LEVELS[(n / 6) % 6],
+                CUBE_LEVELS[n % 6],
+            )
+        }
+        232..=255 => {
+            let value = 8 + 10 * (n - 232);
+            (value, value, value)
+        }
+    }
+}
+
+/// Returns the named color for an index of the 16 basic ANSI colors
+fn ansi16_color(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Purple,
+        6 => Color::Cyan,
+        7 => Color::White,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightPurple,
+        14 => Color::LightCyan,
+        _ => Color::LightGray,
+    }
+}
+
+/// Returns the RGB value of a color, using the xterm defaults for the ANSI colors
+fn color_to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let ansi16 = |n: usize| Some(ANSI16_RGB[n]);
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Fixed(n) => Some(ansi256_to_rgb(n)),
+        Color::Black => ansi16(0),
+        Color::Red => ansi16(1),
+        Color::Green => ansi16(2),
+        Color::Yellow => ansi16(3),
+        Color::Blue => ansi16(4),
+        Color::Purple | Color::Magenta => ansi16(5),
+        Color::Cyan => ansi16(6),
+        Color::White => ansi16(7),
+        Color::DarkGray => ansi16(8),
+        Color::LightRed => ansi16(9),
+        Color::LightGreen => ansi16(10),
+        Color::LightYellow => ansi16(11),
+        Color::LightBlue => ansi16(12),
+        Color::LightPurple | Color::LightMagenta => ansi16(13),
+        Color::LightCyan => ansi16(14),
+        Color::LightGray => ansi16(15),
+        Color::Default => None,
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 /// Wrapper for `nu_ansi_term::Style` that supports referencing the previous style's foreground/background color.
 pub struct Style {
     style: nu_ansi_term::Style,
     bg: Option<PrevColor>,
     fg: Option<PrevColor>,
+    /// The colors supported by the terminal the style is printed to
+    level: ColorLevel,
 }
 
 impl Style {
     pub fn to_ansi_style(&self, prev: Option<&nu_ansi_term::Style>) -> nu_ansi_term::Style {
+        self.level.downgrade(self.resolve_prev(prev))
+    }
+
+    fn resolve_prev(&self, prev: Option<&nu_ansi_term::Style>) -> nu_ansi_term::Style {
         let Some(prev_style) = prev else {
             return self.style;
         };
@@ -355,9 +1330,24 @@ impl From<nu_ansi_term::Color> for Style {
  - '<color>'       (see the `parse_color_string` doc for valid color strings)
 */
 pub fn parse_style_string(style_string: &str, context: Option<&Context>) -> Option<Style> {
+    let style_string = match context {
+        Some(context) => {
+            let (expanded, errors) =
+                expand_style_classes(style_string, &context.root_config.styles);
+            for error in errors {
+                log::warn!("{error} in style string '{style_string}'");
+            }
+            expanded
+        }
+        None => Cow::Borrowed(style_string),
+    };
+    let initial = Style {
+        level: context.map_or_else(ColorLevel::default, Context::color_level),
+        ..Default::default()
+    };
     style_string
         .split_whitespace()
-        .try_fold(Style::default(), |style, token| {
+        .try_fold(initial, |style, token| {
             let token = token.to_lowercase();
 
             // Check for FG/BG identifiers and strip them off if appropriate
@@ -393,15 +1383,13 @@ pub fn parse_style_string(style_string: &str, context: Option<&Context>) -> Opti
                         None // fg:none yields no style.
                     } else {
                         // Either bg or valid color or both.
-                        let parsed = parse_color_string(
-                            color_string,
-                            context.and_then(|x| {
-                                get_palette(
-                                    &x.root_config.palettes,
-                                    x.root_config.palette.as_deref(),
-                                )
-                            }),
-                        );
+                        let parsed =
+                            parse_color_string(color_string, context.and_then(Context::palette));
+                        if parsed.is_none() && color_string != "none" {
+                            log::warn!(
+                                "Unknown color '{color_string}' in style string '{style_string}'"
+                            );
+                        }
                         // bg + invalid color = reset the background to default.
                         if !col_fg && parsed.is_none() {
                             let mut new_style = style;
@@ -423,6 +1411,97 @@ pub fn parse_style_string(style_string: &str, context: Option<&Context>) -> Opti
         })
 }
 
+/// Whether an option holds a style string, judging by its name
+pub fn is_style_option(path: &[&str]) -> bool {
+    match path {
+        [.., "styles", _] => true,
+        [.., key] => *key == "style" || key.ends_with("_style") || key.starts_with("style_"),
+        [] => false,
+    }
+}
+
+/// Replaces the `@name` tokens of a style string with the styles of those names in `[styles]`,
+/// which can use other classes themselves. Unknown and circular classes are left out and
+/// returned as errors.
+pub fn expand_style_classes<'a>(
+    style_string: &'a str,
+    styles: &HashMap<String, String>,
+) -> (Cow<'a, str>, Vec<String>) {
+    fn expand<'s>(
+        style_string: &'s str,
+        styles: &'s HashMap<String, String>,
+        visiting: &mut Vec<&'s str>,
+        tokens: &mut Vec<&'s str>,
+        errors: &mut Vec<String>,
+    ) {
+        for token in style_string.split_whitespace() {
+            let Some(name) = token.strip_prefix('@') else {
+                tokens.push(token);
+                continue;
+            };
+            match styles.get_key_value(name) {
+                Some(_) if visiting.contains(&name) => errors.push(format!(
+                    "Circular reference: @{} -> @{name}",
+                    visiting.join(" -> @")
+                )),
+                Some((name, style)) => {
+                    visiting.push(name);
+                    expand(style, styles, visiting, tokens, errors);
+                    visiting.pop();
+                }
+                None => errors.push(format!("Unknown style class '{token}'")),
+            }
+        }
+    }
+
+    if !style_string.contains('@') {
+        return (Cow::Borrowed(style_string), Vec::new());
+    }
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    expand(
+        style_string,
+        styles,
+        &mut Vec::new(),
+        &mut tokens,
+        &mut errors,
+    );
+    (Cow::Owned(tokens.join(" ")), errors)
+}
+
+/// Check that every token of a style string is a known attribute or color,
+/// returning an error message for the first one that is not.
+pub fn validate_style_string(style_string: &str, palette: Option<&Palette>) -> Result<(), String> {
+    style_string.split_whitespace().try_for_each(|token| {
+        let lowercase = token.to_lowercase();
+        let color = lowercase
+            .strip_prefix("fg:")
+            .or_else(|| lowercase.strip_prefix("bg:"))
+            .unwrap_or(&lowercase);
+
+        let valid = matches!(
+            color,
+            "underline"
+                | "bold"
+                | "italic"
+                | "dimmed"
+                | "inverted"
+                | "blink"
+                | "hidden"
+                | "strikethrough"
+                | "prev_fg"
+                | "prev_bg"
+                | "none"
+        ) || parse_color_string(color, palette).is_some();
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Unknown color or style attribute '{token}'"))
+        }
+    })
+}
+
 /** Parse a string that represents a color setting, returning None if this fails
  There are three valid color formats:
   - #RRGGBB      (a hash followed by an RGB hex)
@@ -454,58 +1533,310 @@ fn parse_color_string(
         return Some(Color::Fixed(ansi_color_num));
     }
 
-    // Check palette for a matching user-defined color
-    if let Some(palette_color) = palette.as_ref().and_then(|x| x.get(color_string)) {
-        log::trace!("Read user-defined color string: {color_string} defined as {palette_color}");
-        return parse_color_string(palette_color, None);
-    }
+    // Check palette for a matching user-defined color
+    if let Some(palette_color) = palette.as_ref().and_then(|x| x.get(color_string)) {
+        log::trace!("Read user-defined color string: {color_string} defined as {palette_color}");
+        return parse_color_string(palette_color, None);
+    }
+
+    // Check for any predefined color strings
+    // There are no predefined enums for bright colors, so we use Color::Fixed
+    let predefined_color = match color_string.to_lowercase().as_str() {
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "purple" => Some(Color::Purple),
+        "cyan" => Some(Color::Cyan),
+        "white" => Some(Color::White),
+        "bright-black" => Some(Color::DarkGray), // "bright-black" is dark grey
+        "bright-red" => Some(Color::LightRed),
+        "bright-green" => Some(Color::LightGreen),
+        "bright-yellow" => Some(Color::LightYellow),
+        "bright-blue" => Some(Color::LightBlue),
+        "bright-purple" => Some(Color::LightPurple),
+        "bright-cyan" => Some(Color::LightCyan),
+        "bright-white" => Some(Color::LightGray),
+        _ => None,
+    };
+
+    if predefined_color.is_some() {
+        log::trace!("Read predefined color: {color_string}");
+    } else {
+        log::debug!("Could not parse color in string: {color_string}");
+    }
+    predefined_color
+}
+
+/// Looks up a palette by name and resolves its colors, logging any problems with it
+pub fn get_palette(
+    palettes: &HashMap<String, Palette>,
+    palette_name: Option<&str>,
+) -> Option<Palette> {
+    let Some(palette_name) = palette_name else {
+        log::trace!("No color palette specified, using defaults");
+        return None;
+    };
+
+    match resolve_palette(palettes, palette_name) {
+        Ok(resolved) => {
+            log::trace!("Found color palette: {palette_name}");
+            for error in resolved.errors {
+                log::warn!("{error}");
+            }
+            Some(resolved.colors)
+        }
+        Err(error) => {
+            log::warn!("{error}");
+            None
+        }
+    }
+}
+
+/// The key of a palette that names the palette it inherits colors from
+pub const PALETTE_EXTENDS: &str = "extends";
+
+/// A palette with every color resolved to a color `parse_color_string` accepts
+#[derive(Debug)]
+pub struct ResolvedPalette {
+    pub colors: Palette,
+    /// Colors that couldn't be resolved, and are left out of `colors`
+    pub errors: Vec<PaletteError>,
+}
+
+/// A problem with a palette, or with one of its colors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteError {
+    pub palette: String,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.key {
+            Some(key) => write!(
+                f,
+                "Error in palette '{}' at '{key}': {}",
+                self.palette, self.message
+            ),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/** Resolve the colors of a palette, including those of the palettes it `extends`.
+ Besides the formats of `parse_color_string`, a color can be:
+  - the name of another color of the palette, resolved in the extending palette
+  - `lighten(color, 20%)` or `darken(color, 20%)`
+  - `mix(color, color)` or `mix(color, color, 25%)`, where the amount is the part of the second color
+*/
+pub fn resolve_palette(
+    palettes: &HashMap<String, Palette>,
+    name: &str,
+) -> Result<ResolvedPalette, PaletteError> {
+    // The palette and the palettes it extends, in order
+    let mut chain: Vec<(&str, &Palette)> = Vec::new();
+    let mut current = name;
+    loop {
+        let Some(palette) = palettes.get(current) else {
+            return Err(match chain.last() {
+                Some((child, _)) => PaletteError {
+                    palette: (*child).to_owned(),
+                    key: Some(PALETTE_EXTENDS.to_owned()),
+                    message: format!("Could not find color palette '{current}'"),
+                },
+                None => PaletteError {
+                    palette: name.to_owned(),
+                    key: None,
+                    message: format!("Could not find color palette '{name}'"),
+                },
+            });
+        };
+        if chain.iter().any(|(palette, _)| *palette == current) {
+            let cycle = chain
+                .iter()
+                .map(|(palette, _)| *palette)
+                .collect::<Vec<_>>();
+            return Err(PaletteError {
+                palette: cycle.last().copied().unwrap_or(name).to_owned(),
+                key: Some(PALETTE_EXTENDS.to_owned()),
+                message: format!("Circular reference: {} -> {current}", cycle.join(" -> ")),
+            });
+        }
+        chain.push((current, palette));
+        match palette.get(PALETTE_EXTENDS) {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+
+    let mut entries = HashMap::new();
+    for (palette_name, palette) in chain.iter().rev() {
+        for (key, value) in palette.iter() {
+            if key != PALETTE_EXTENDS {
+                entries.insert(key.as_str(), (value.as_str(), *palette_name));
+            }
+        }
+    }
+
+    let mut keys = entries.keys().copied().collect::<Vec<_>>();
+    keys.sort_unstable();
+    let mut resolver = PaletteResolver {
+        entries,
+        colors: Palette::new(),
+        failed: Vec::new(),
+        visiting: Vec::new(),
+        errors: Vec::new(),
+    };
+    for key in keys {
+        resolver.resolve_key(key);
+    }
+
+    Ok(ResolvedPalette {
+        colors: resolver.colors,
+        errors: resolver.errors,
+    })
+}
+
+struct PaletteResolver<'a> {
+    /// The value of each color, and the palette it was defined in
+    entries: HashMap<&'a str, (&'a str, &'a str)>,
+    colors: Palette,
+    failed: Vec<&'a str>,
+    /// The colors being resolved, to detect circular references
+    visiting: Vec<&'a str>,
+    errors: Vec<PaletteError>,
+}
+
+impl<'a> PaletteResolver<'a> {
+    fn resolve_key(&mut self, key: &'a str) -> Option<String> {
+        if let Some(color) = self.colors.get(key) {
+            return Some(color.clone());
+        }
+        if self.failed.contains(&key) {
+            return None;
+        }
+
+        let (value, palette) = self.entries[key];
+        self.visiting.push(key);
+        let result = self.evaluate(value);
+        self.visiting.pop();
+
+        match result {
+            Ok(color) => {
+                self.colors.insert(key.to_owned(), color.clone());
+                Some(color)
+            }
+            Err(message) => {
+                self.failed.push(key);
+                self.errors.push(PaletteError {
+                    palette: palette.to_owned(),
+                    key: Some(key.to_owned()),
+                    message,
+                });
+                None
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expression: &'a str) -> Result<String, String> {
+        let expression = expression.trim();
 
-    // Check for any predefined color strings
-    // There are no predefined enums for bright colors, so we use Color::Fixed
-    let predefined_color = match color_string.to_lowercase().as_str() {
-        "black" => Some(Color::Black),
-        "red" => Some(Color::Red),
-        "green" => Some(Color::Green),
-        "yellow" => Some(Color::Yellow),
-        "blue" => Some(Color::Blue),
-        "purple" => Some(Color::Purple),
-        "cyan" => Some(Color::Cyan),
-        "white" => Some(Color::White),
-        "bright-black" => Some(Color::DarkGray), // "bright-black" is dark grey
-        "bright-red" => Some(Color::LightRed),
-        "bright-green" => Some(Color::LightGreen),
-        "bright-yellow" => Some(Color::LightYellow),
-        "bright-blue" => Some(Color::LightBlue),
-        "bright-purple" => Some(Color::LightPurple),
-        "bright-cyan" => Some(Color::LightCyan),
-        "bright-white" => Some(Color::LightGray),
-        _ => None,
-    };
+        if let Some((function, arguments)) = expression
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+        {
+            let arguments = split_arguments(arguments);
+            let (r, g, b) = match (function.trim(), arguments.as_slice()) {
+                ("lighten", [color, amount]) => mix_rgb(
+                    self.evaluate_rgb(color)?,
+                    (255, 255, 255),
+                    parse_amount(amount)?,
+                ),
+                ("darken", [color, amount]) => {
+                    mix_rgb(self.evaluate_rgb(color)?, (0, 0, 0), parse_amount(amount)?)
+                }
+                ("mix", [first, second]) => {
+                    mix_rgb(self.evaluate_rgb(first)?, self.evaluate_rgb(second)?, 0.5)
+                }
+                ("mix", [first, second, amount]) => mix_rgb(
+                    self.evaluate_rgb(first)?,
+                    self.evaluate_rgb(second)?,
+                    parse_amount(amount)?,
+                ),
+                (function @ ("lighten" | "darken" | "mix"), _) => {
+                    return Err(format!("Wrong number of arguments for '{function}'"));
+                }
+                (function, _) => return Err(format!("Unknown color function '{function}'")),
+            };
+            return Ok(format!("#{r:02x}{g:02x}{b:02x}"));
+        }
 
-    if predefined_color.is_some() {
-        log::trace!("Read predefined color: {color_string}");
-    } else {
-        log::debug!("Could not parse color in string: {color_string}");
+        // A color can be named after a predefined color it is defined as, like `red = "red"`
+        let is_cycle = self.visiting.contains(&expression);
+        if self.entries.contains_key(expression)
+            && !(is_cycle && parse_color_string(expression, None).is_some())
+        {
+            if is_cycle {
+                return Err(format!(
+                    "Circular reference: {} -> {expression}",
+                    self.visiting.join(" -> ")
+                ));
+            }
+            return self
+                .resolve_key(expression)
+                .ok_or_else(|| format!("Invalid color '{expression}'"));
+        }
+
+        parse_color_string(expression, None)
+            .map(|_| expression.to_owned())
+            .ok_or_else(|| format!("Unknown color '{expression}'"))
+    }
+
+    fn evaluate_rgb(&mut self, expression: &'a str) -> Result<(u8, u8, u8), String> {
+        let color = self.evaluate(expression)?;
+        parse_color_string(&color, None)
+            .and_then(color_to_rgb)
+            .ok_or_else(|| format!("Color '{expression}' has no RGB value"))
     }
-    predefined_color
 }
 
-fn get_palette<'a>(
-    palettes: &'a HashMap<String, Palette>,
-    palette_name: Option<&str>,
-) -> Option<&'a Palette> {
-    if let Some(palette_name) = palette_name {
-        let palette = palettes.get(palette_name);
-        if palette.is_some() {
-            log::trace!("Found color palette: {palette_name}");
-        } else {
-            log::warn!("Could not find color palette: {palette_name}");
+/// Splits the arguments of a color function at the commas that aren't in a nested call
+fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut split = Vec::new();
+    for (index, c) in arguments.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(arguments[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
         }
-        palette
-    } else {
-        log::trace!("No color palette specified, using defaults");
-        None
     }
+    split.push(arguments[start..].trim());
+    split
+}
+
+/// Parses a percentage like `20%` into a fraction
+fn parse_amount(amount: &str) -> Result<f64, String> {
+    amount
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .map(|percent| percent / 100.0)
+        .ok_or_else(|| format!("Invalid percentage '{amount}'"))
+}
+
+fn mix_rgb((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8), amount: f64) -> (u8, u8, u8) {
+    let mix = |a: u8, b: u8| (f64::from(a) * (1.0 - amount) + f64::from(b) * amount).round() as u8;
+    (mix(r1, r2), mix(g1, g2), mix(b1, b2))
 }
 
 #[cfg(test)]
@@ -1043,6 +2374,778 @@ mod tests {
         assert!(get_palette(&palettes, None).is_none());
     }
 
+    fn palettes(palettes: &[(&str, &[(&str, &str)])]) -> HashMap<String, Palette> {
+        palettes
+            .iter()
+            .map(|(name, colors)| {
+                let colors = colors
+                    .iter()
+                    .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                    .collect();
+                ((*name).to_string(), colors)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_palette_extends() {
+        let palettes = palettes(&[
+            (
+                "base",
+                &[("blue", "#0000ff"), ("accent", "blue"), ("text", "white")],
+            ),
+            ("dark", &[("extends", "base"), ("blue", "#000080")]),
+            ("darker", &[("extends", "dark"), ("text", "bright-black")]),
+        ]);
+
+        let resolved = resolve_palette(&palettes, "darker").unwrap();
+        assert!(resolved.errors.is_empty());
+        assert_eq!(
+            resolved.colors,
+            Palette::from([
+                ("blue".to_string(), "#000080".to_string()),
+                // References are resolved in the extending palette
+                ("accent".to_string(), "#000080".to_string()),
+                ("text".to_string(), "bright-black".to_string()),
+            ])
+        );
+
+        let resolved = resolve_palette(&palettes, "base").unwrap();
+        assert_eq!(resolved.colors["accent"], "#0000ff");
+    }
+
+    #[test]
+    fn resolve_palette_derived_colors() {
+        let palettes = palettes(&[(
+            "theme",
+            &[
+                ("base", "#804020"),
+                ("light", "lighten(base, 50%)"),
+                ("dark", "darken(base, 50%)"),
+                ("mixed", "mix(base, #ffffff)"),
+                ("tint", "mix(red, blue, 25%)"),
+                ("nested", "darken(lighten(base, 50%), 100%)"),
+                ("fixed", "lighten(16, 0%)"),
+                ("red", "red"),
+            ],
+        )]);
+
+        let resolved = resolve_palette(&palettes, "theme").unwrap();
+        assert!(resolved.errors.is_empty(), "{:?}", resolved.errors);
+        assert_eq!(resolved.colors["light"], "#c0a090");
+        assert_eq!(resolved.colors["dark"], "#402010");
+        assert_eq!(resolved.colors["mixed"], "#c0a090");
+        // 3/4 of the xterm red (205, 0, 0) and 1/4 of the xterm blue (0, 0, 238)
+        assert_eq!(resolved.colors["tint"], "#9a003c");
+        assert_eq!(resolved.colors["nested"], "#000000");
+        assert_eq!(resolved.colors["fixed"], "#000000");
+        assert_eq!(resolved.colors["red"], "red");
+    }
+
+    #[test]
+    fn resolve_palette_errors() {
+        let palettes = palettes(&[
+            (
+                "theme",
+                &[
+                    ("a", "b"),
+                    ("b", "a"),
+                    ("c", "a"),
+                    ("unknown", "blu"),
+                    ("function", "brighten(red, 10%)"),
+                    ("amount", "lighten(red, 120%)"),
+                    ("arguments", "mix(red)"),
+                    ("ok", "green"),
+                ],
+            ),
+            ("missing", &[("extends", "nothing")]),
+            ("cycle", &[("extends", "loop")]),
+            ("loop", &[("extends", "cycle")]),
+        ]);
+
+        let resolved = resolve_palette(&palettes, "theme").unwrap();
+        assert_eq!(
+            resolved.colors,
+            Palette::from([("ok".to_string(), "green".to_string())])
+        );
+        let errors = resolved
+            .errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "Error in palette 'theme' at 'b': Circular reference: a -> b -> a",
+                "Error in palette 'theme' at 'a': Invalid color 'b'",
+                "Error in palette 'theme' at 'amount': Invalid percentage '120%'",
+                "Error in palette 'theme' at 'arguments': Wrong number of arguments for 'mix'",
+                "Error in palette 'theme' at 'c': Invalid color 'a'",
+                "Error in palette 'theme' at 'function': Unknown color function 'brighten'",
+                "Error in palette 'theme' at 'unknown': Unknown color 'blu'",
+            ]
+        );
+
+        assert_eq!(
+            resolve_palette(&palettes, "missing")
+                .unwrap_err()
+                .to_string(),
+            "Error in palette 'missing' at 'extends': Could not find color palette 'nothing'"
+        );
+        assert_eq!(
+            resolve_palette(&palettes, "cycle").unwrap_err().to_string(),
+            "Error in palette 'loop' at 'extends': Circular reference: cycle -> loop -> cycle"
+        );
+        assert_eq!(
+            resolve_palette(&palettes, "other").unwrap_err().to_string(),
+            "Could not find color palette 'other'"
+        );
+    }
+
+    #[test]
+    fn detect_color_level() {
+        let detect = |env: &[(&'static str, &str)], color_mode: Option<&str>| {
+            let mut context = Context::default();
+            for (key, value) in env {
+                context.env.insert(key, (*value).to_string());
+            }
+            if let Some(color_mode) = color_mode {
+                context = context.set_config(toml::toml! { color_mode = color_mode });
+            }
+            ColorLevel::detect(&context)
+        };
+
+        assert_eq!(detect(&[], None), ColorLevel::TrueColor);
+        for term in ["xterm", "xterm-kitty", "xterm-direct", "alacritty"] {
+            assert_eq!(detect(&[("TERM", term)], None), ColorLevel::TrueColor);
+        }
+        for term in ["xterm-256color", "tmux-256color", "screen", "tmux"] {
+            assert_eq!(detect(&[("TERM", term)], None), ColorLevel::Ansi256);
+        }
+        for colorterm in ["truecolor", "24bit"] {
+            assert_eq!(
+                detect(&[("TERM", "tmux-256color"), ("COLORTERM", colorterm)], None),
+                ColorLevel::TrueColor
+            );
+        }
+        assert_eq!(
+            detect(&[("TERM", "screen"), ("COLORTERM", "1")], None),
+            ColorLevel::Ansi256
+        );
+        assert_eq!(
+            detect(&[("TERM", "screen-256color")], Some("truecolor")),
+            ColorLevel::TrueColor
+        );
+        assert_eq!(detect(&[("TERM", "linux")], None), ColorLevel::Ansi16);
+        assert_eq!(detect(&[("TERM", "vt100")], None), ColorLevel::Ansi16);
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color")], Some("16")),
+            ColorLevel::Ansi16
+        );
+        assert_eq!(
+            detect(&[("TERM", "linux")], Some("truecolor")),
+            ColorLevel::TrueColor
+        );
+        assert_eq!(
+            detect(&[("NO_COLOR", "1")], Some("truecolor")),
+            ColorLevel::None
+        );
+        assert_eq!(detect(&[("NO_COLOR", "")], None), ColorLevel::TrueColor);
+    }
+
+    #[test]
+    fn detect_theme() {
+        let detect = |env: &[(&'static str, &str)]| {
+            let mut context = Context::default();
+            for (key, value) in env {
+                context.env.insert(key, (*value).to_string());
+            }
+            Theme::detect(&context)
+        };
+
+        assert_eq!(detect(&[]), None);
+        assert_eq!(detect(&[("STARSHIP_THEME", "Light")]), Some(Theme::Light));
+        assert_eq!(
+            detect(&[("STARSHIP_TERMINAL_THEME", "dark")]),
+            Some(Theme::Dark)
+        );
+        assert_eq!(detect(&[("COLORFGBG", "0;15")]), Some(Theme::Light));
+        assert_eq!(detect(&[("COLORFGBG", "15;default;0")]), Some(Theme::Dark));
+        assert_eq!(detect(&[("COLORFGBG", "0;default")]), None);
+        assert_eq!(
+            detect(&[
+                ("STARSHIP_THEME", "dark"),
+                ("STARSHIP_TERMINAL_THEME", "light"),
+                ("COLORFGBG", "0;15")
+            ]),
+            Some(Theme::Dark)
+        );
+        assert_eq!(
+            detect(&[("STARSHIP_THEME", ""), ("COLORFGBG", "0;15")]),
+            Some(Theme::Light)
+        );
+
+        assert_eq!(Theme::from_background((255, 255, 255)), Theme::Light);
+        assert_eq!(Theme::from_background((30, 30, 46)), Theme::Dark);
+    }
+
+    #[test]
+    fn query_theme_without_themed_palettes() {
+        // The terminal is only asked if the config has a palette for its background
+        let context = Context::default().set_config(toml::toml! { palette = "custom" });
+        assert_eq!(Theme::query(&context), None);
+    }
+
+    #[test]
+    fn themed_palettes() {
+        let style = |env: &[(&'static str, &str)]| {
+            let mut context = Context::default().set_config(toml::toml! {
+                palette = "default"
+                palette_light = "light"
+                palette_dark = "dark"
+                [palettes.default]
+                text = "#808080"
+                [palettes.light]
+                text = "#000000"
+                [palettes.dark]
+                text = "#ffffff"
+            });
+            for (key, value) in env {
+                context.env.insert(key, (*value).to_string());
+            }
+            parse_style_string("text", Some(&context)).map(|style| style.to_ansi_style(None))
+        };
+
+        assert_eq!(style(&[]), Some(Color::Rgb(128, 128, 128).normal()));
+        assert_eq!(
+            style(&[("STARSHIP_THEME", "light")]),
+            Some(Color::Rgb(0, 0, 0).normal())
+        );
+        assert_eq!(
+            style(&[("COLORFGBG", "15;0")]),
+            Some(Color::Rgb(255, 255, 255).normal())
+        );
+    }
+
+    #[test]
+    fn style_classes() {
+        let styles = HashMap::from([
+            ("danger".to_string(), "bold fg:red".to_string()),
+            ("loud".to_string(), "@danger underline".to_string()),
+            ("a".to_string(), "@b".to_string()),
+            ("b".to_string(), "italic @a".to_string()),
+        ]);
+
+        assert_eq!(
+            expand_style_classes("@loud bg:blue", &styles),
+            (
+                Cow::Owned("bold fg:red underline bg:blue".to_string()),
+                vec![]
+            )
+        );
+        assert_eq!(
+            expand_style_classes("bold red", &styles),
+            (Cow::Borrowed("bold red"), vec![])
+        );
+        assert_eq!(
+            expand_style_classes("@nope red", &styles),
+            (
+                Cow::Owned("red".to_string()),
+                vec!["Unknown style class '@nope'".to_string()]
+            )
+        );
+        assert_eq!(
+            expand_style_classes("@a", &styles),
+            (
+                Cow::Owned("italic".to_string()),
+                vec!["Circular reference: @a -> @b -> @a".to_string()]
+            )
+        );
+
+        let context = Context::default().set_config(toml::toml! {
+            [styles]
+            danger = "bold fg:red"
+        });
+        assert_eq!(
+            parse_style_string("@danger underline fg:blue", Some(&context))
+                .map(|style| style.to_ansi_style(None)),
+            Some(Color::Blue.bold().underline())
+        );
+    }
+
+    #[test]
+    fn downgrade_colors() {
+        assert_eq!(nearest_ansi256(255, 0, 0), 196);
+        assert_eq!(nearest_ansi256(0, 0, 0), 16);
+        assert_eq!(nearest_ansi256(255, 255, 255), 231);
+        assert_eq!(nearest_ansi256(128, 128, 128), 244);
+        assert_eq!(nearest_ansi256(175, 135, 0), 136);
+
+        let style = AnsiStyle::new()
+            .fg(Color::Rgb(215, 0, 0))
+            .on(Color::Fixed(17))
+            .bold();
+        assert_eq!(ColorLevel::TrueColor.downgrade(style), style);
+        assert_eq!(
+            ColorLevel::Ansi256.downgrade(style),
+            AnsiStyle::new()
+                .fg(Color::Fixed(160))
+                .on(Color::Fixed(17))
+                .bold()
+        );
+        assert_eq!(
+            ColorLevel::Ansi16.downgrade(style),
+            AnsiStyle::new().fg(Color::Red).on(Color::Black).bold()
+        );
+        assert_eq!(ColorLevel::None.downgrade(style), AnsiStyle::new());
+    }
+
+    #[test]
+    fn parse_style_string_downgrades_colors() {
+        let context = Context::default().set_config(toml::toml! { color_mode = "256" });
+        let style = parse_style_string("bold fg:#ff0000 bg:blue", Some(&context)).unwrap();
+        assert_eq!(
+            style.to_ansi_style(None),
+            AnsiStyle::new()
+                .fg(Color::Fixed(196))
+                .on(Color::Blue)
+                .bold()
+        );
+
+        // The terminal is detected once per context
+        let mut context = Context::default();
+        context.env.insert("NO_COLOR", "1".to_string());
+        let style = parse_style_string("bold fg:#ff0000 bg:blue", Some(&context)).unwrap();
+        assert_eq!(style.to_ansi_style(None), AnsiStyle::new());
+    }
+
+    #[test]
+    fn config_includes() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("shared"))?;
+        std::fs::write(
+            dir.path().join("shared/base.toml"),
+            "add_newline = false\ninclude = '../starship.toml'\n[git_branch]\nsymbol = 'b'\nstyle = 'red'\n",
+        )?;
+        std::fs::write(
+            dir.path().join("starship.toml"),
+            "include = ['shared/base.toml']\n[git_branch]\nstyle = 'blue'\n",
+        )?;
+
+        let config =
+            StarshipConfig::initialize(Some(dir.path().join("starship.toml").as_os_str()), None);
+        let expected = toml::toml! {
+            add_newline = false
+            [git_branch]
+            symbol = "b"
+            style = "blue"
+        };
+        assert_eq!(config.config, Some(expected));
+        // The circular include of the main file is skipped
+        assert_eq!(
+            config.files,
+            [
+                dir.path().join("shared/base.toml"),
+                dir.path().join("starship.toml")
+            ]
+        );
+        let origin = |path: &[&str]| {
+            let path = path.iter().map(ToString::to_string).collect::<Vec<_>>();
+            config.origin(&path)
+        };
+        assert_eq!(
+            origin(&["git_branch", "symbol"]),
+            Some(config.files[0].display().to_string())
+        );
+        assert_eq!(
+            origin(&["git_branch", "style"]),
+            Some(config.files[1].display().to_string())
+        );
+        assert_eq!(origin(&["format"]), None);
+        dir.close()
+    }
+
+    #[test]
+    fn config_migrates_renamed_options() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("starship.toml"),
+            "[character]\nvicmd_symbol = 'V'\n[custom.foo]\nfiles = ['foo']\n",
+        )?;
+
+        let config =
+            StarshipConfig::initialize(Some(dir.path().join("starship.toml").as_os_str()), None);
+        let expected = toml::toml! {
+            [character]
+            vimcmd_symbol = "V"
+            [custom.foo]
+            detect_files = ["foo"]
+        };
+        assert_eq!(config.config, Some(expected));
+        dir.close()
+    }
+
+    #[test]
+    fn config_cache_keeps_migrated_files() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("starship.toml");
+        std::fs::write(&path, "[character]\nvicmd_symbol = 'V'\n")?;
+        let cache_dir = dir.path().join("cache");
+
+        let config = StarshipConfig::initialize(Some(path.as_os_str()), Some(cache_dir.clone()));
+        let cached = ConfigCache::new(cache_dir.clone())
+            .get(&path, &ConfigCache::key(&path).unwrap())
+            .unwrap();
+        assert_eq!(Some(cached.config), config.config);
+        assert_eq!(cached.warnings.len(), 1);
+
+        let from_cache = StarshipConfig::initialize(Some(path.as_os_str()), Some(cache_dir));
+        assert_eq!(from_cache.config, config.config);
+        dir.close()
+    }
+
+    #[test]
+    fn project_config_requires_trust() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(project.join("src"))?;
+        std::fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            "trusted_project_configs = ['/']\n[git_branch]\nsymbol = 'p'\n",
+        )?;
+        let user_config = |trusted: &Path| {
+            let mut config = StarshipConfig {
+                config: Some(toml::toml! {
+                    trusted_project_configs = [(trusted.to_string_lossy().to_string())]
+                    [git_branch]
+                    symbol = "u"
+                }),
+                files: vec![dir.path().join("starship.toml")],
+                ..Default::default()
+            };
+            config.merge_project_config(&project.join("src"));
+            config
+        };
+
+        let untrusted = user_config(&dir.path().join("elsewhere"));
+        assert_eq!(
+            untrusted.get_config(&["git_branch", "symbol"]),
+            Some(&Value::from("u"))
+        );
+
+        let trusted = user_config(dir.path());
+        assert_eq!(
+            trusted.get_config(&["git_branch", "symbol"]),
+            Some(&Value::from("p"))
+        );
+        assert_eq!(
+            trusted.origin(&["git_branch".to_string(), "symbol".to_string()]),
+            Some(project.join(PROJECT_CONFIG_FILE).display().to_string())
+        );
+        // The project can't extend the trust
+        assert_eq!(trusted.trusted_project_dirs(), [dir.path().to_path_buf()]);
+        dir.close()
+    }
+
+    #[test]
+    fn when_blocks() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_path = dir.path().join("starship.toml");
+        std::fs::write(
+            &config_path,
+            r"
+format = '$all'
+
+[[when]]
+shell = ['zsh', 'bash']
+env = 'CI'
+format = '$directory'
+
+[when.directory]
+style = 'red'
+
+[[when]]
+directory = '/srv/**'
+env = 'STAGE=prod*'
+right_format = '$time'
+
+[[when]]
+shell = 'fish'
+add_newline = false
+",
+        )?;
+
+        let apply = |shell, current_dir: &str, env: &[(&'static str, &str)]| {
+            let mut env_vars = Env::default();
+            for (name, value) in env {
+                env_vars.insert(name, value.to_string());
+            }
+            let mut config = StarshipConfig::initialize(Some(config_path.as_os_str()), None);
+            config.apply_when_blocks(&WhenFacts::new(
+                shell,
+                Path::new(current_dir),
+                Path::new(current_dir),
+                &env_vars,
+            ));
+            config.config.unwrap()
+        };
+
+        let config = apply(Shell::Zsh, "/home", &[("CI", "true")]);
+        let expected = toml::toml! {
+            format = "$directory"
+            [directory]
+            style = "red"
+        };
+        assert_eq!(config, expected);
+
+        let config = apply(Shell::Zsh, "/srv", &[("STAGE", "production")]);
+        let expected = toml::toml! {
+            format = "$all"
+            right_format = "$time"
+        };
+        assert_eq!(config, expected);
+
+        let config = apply(Shell::Bash, "/srv/app", &[("STAGE", "dev"), ("CI", "")]);
+        assert_eq!(config, toml::toml! { format = "$all" });
+        dir.close()
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut env = Env::default();
+        env.insert("STARSHIP__FORMAT", "$directory$character".to_string());
+        env.insert("STARSHIP__GIT_STATUS__DISABLED", "true".to_string());
+        env.insert("STARSHIP__CMD_DURATION__MIN_TIME", "500".to_string());
+        env.insert("STARSHIP__DIRECTORY__STYLE", "'bold red'".to_string());
+        env.insert("STARSHIP__ADD_NEWLINE__NESTED", "true".to_string());
+        env.insert(
+            "STARSHIP__GIT_BRANCH__IGNORE_BRANCHES",
+            "['main', 'dev']".to_string(),
+        );
+        env.insert(
+            "STARSHIP__PALETTES",
+            "{ dark = { blue = '#1e66f5' } }".to_string(),
+        );
+        env.insert("STARSHIP__", "ignored".to_string());
+        env.insert("STARSHIP_SHELL", "zsh".to_string());
+        // Existing keys match regardless of case
+        env.insert("STARSHIP__CUSTOM__MYTOOL__DISABLED", "true".to_string());
+        env.insert("STARSHIP__CUSTOM__OTHER__WHEN", "false".to_string());
+
+        let mut config = StarshipConfig {
+            config: Some(toml::toml! {
+                add_newline = false
+                [git_status]
+                style = "red"
+                [custom.MyTool]
+                command = "echo hi"
+            }),
+            ..Default::default()
+        };
+        config.apply_env_overrides(&env);
+
+        let expected = toml::toml! {
+            add_newline = false
+            format = "$directory$character"
+            [git_status]
+            style = "red"
+            disabled = true
+            [cmd_duration]
+            min_time = 500
+            [directory]
+            style = "bold red"
+            [git_branch]
+            ignore_branches = ["main", "dev"]
+            [palettes.dark]
+            blue = "#1e66f5"
+            [custom.MyTool]
+            command = "echo hi"
+            disabled = true
+            [custom.other]
+            when = false
+        };
+        assert_eq!(config.config, Some(expected));
+        assert_eq!(
+            config.origin(&["git_status".to_string(), "disabled".to_string()]),
+            Some("$STARSHIP__GIT_STATUS__DISABLED".to_string())
+        );
+        assert_eq!(
+            config.origin(&["git_status".to_string(), "style".to_string()]),
+            None
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let profiles = toml::toml! {
+            short = "$directory$character"
+            [base]
+            add_newline = false
+            right_format = "$time"
+            [base.directory]
+            style = "blue"
+            truncation_length = 1
+            [work]
+            extends = "base"
+            [work.directory]
+            style = "green"
+            [narrow]
+            extends = "short"
+            right_format = ""
+        };
+        let apply = |name| {
+            let mut config = StarshipConfig {
+                config: Some(toml::toml! {
+                    format = "$all"
+                    [directory]
+                    style = "red"
+                    read_only = "ro"
+                }),
+                ..Default::default()
+            };
+            config
+                .config
+                .as_mut()
+                .unwrap()
+                .insert("profiles".to_string(), Value::Table(profiles.clone()));
+            config.apply_profile(name);
+            let mut config = config.config.unwrap();
+            config.remove("profiles");
+            config
+        };
+
+        let expected = toml::toml! {
+            format = "$all"
+            add_newline = false
+            right_format = "$time"
+            [directory]
+            style = "green"
+            read_only = "ro"
+            truncation_length = 1
+        };
+        assert_eq!(apply("work"), expected);
+
+        // The root `right_format` and `add_newline` are not inherited
+        let expected = toml::toml! {
+            format = "$directory$character"
+            right_format = ""
+            add_newline = false
+            [directory]
+            style = "red"
+            read_only = "ro"
+        };
+        assert_eq!(apply("narrow"), expected);
+
+        let unchanged = toml::toml! {
+            format = "$all"
+            [directory]
+            style = "red"
+            read_only = "ro"
+        };
+        assert_eq!(apply("missing"), unchanged);
+    }
+
+    #[test]
+    fn profile_rules() {
+        let config = StarshipConfig {
+            config: Some(toml::toml! {
+                [profiles]
+                minimal = "$character"
+                [profiles.remote]
+                format = "$hostname$directory$character"
+
+                [[profile_rules]]
+                profile = "unknown"
+
+                [[profile_rules]]
+                profile = "minimal"
+                directory = "~/scratch/**"
+
+                [[profile_rules]]
+                profile = "remote"
+                env = ["SSH_CONNECTION", "SSH_TTY"]
+
+                [[profile_rules]]
+                profile = "claude-code"
+                env = "CLAUDECODE=1"
+            }),
+            ..Default::default()
+        };
+        let select = |current_dir: &str, env: &[(&'static str, &str)]| {
+            let mut env_vars = Env::default();
+            for (name, value) in env {
+                env_vars.insert(name, value.to_string());
+            }
+            config.match_profile_rules(&WhenFacts::new(
+                Shell::Zsh,
+                Path::new(current_dir),
+                Path::new(current_dir),
+                &env_vars,
+            ))
+        };
+
+        let scratch = utils::home_dir().unwrap().join("scratch/notes");
+        let scratch = scratch.to_str().unwrap();
+        assert_eq!(
+            select(scratch, &[("SSH_TTY", "/dev/pts/1")]).as_deref(),
+            Some("minimal")
+        );
+        assert_eq!(
+            select("/srv", &[("SSH_TTY", "/dev/pts/1")]).as_deref(),
+            Some("remote")
+        );
+        assert_eq!(
+            select("/srv", &[("CLAUDECODE", "1")]).as_deref(),
+            Some("claude-code")
+        );
+        assert_eq!(select("/srv", &[]), None);
+    }
+
+    #[test]
+    fn resolve_profile_errors() {
+        let profiles = toml::toml! {
+            [a]
+            extends = "b"
+            [b]
+            extends = "a"
+            [c]
+            extends = "nothing"
+            [d]
+            extends = 1
+            [e]
+            extends = "claude-code"
+        };
+        assert_eq!(
+            resolve_profile(&profiles, "a"),
+            Err("Circular reference: a -> b -> a".to_string())
+        );
+        assert_eq!(
+            resolve_profile(&profiles, "c"),
+            Err("Could not find profile 'nothing'".to_string())
+        );
+        assert_eq!(
+            resolve_profile(&profiles, "d"),
+            Err("Expected `extends` to be the name of a profile, found integer".to_string())
+        );
+        // The built-in profiles can be extended
+        let chain = resolve_profile(&profiles, "e").unwrap();
+        assert_eq!(chain[0].0, "claude-code");
+        assert!(chain[0].1.contains_key("format"));
+    }
+
+    #[test]
+    fn validate_style_strings() {
+        let palette = Palette::from([("mustard".to_string(), "#af8700".to_string())]);
+        assert!(validate_style_string("bold fg:#ff0000 bg:none", None).is_ok());
+        assert!(validate_style_string("fg:prev_bg bg:prev_fg", None).is_ok());
+        assert!(validate_style_string("Bright-Red 208 mustard", Some(&palette)).is_ok());
+        assert_eq!(
+            validate_style_string("bold mustard", None),
+            Err("Unknown color or style attribute 'mustard'".to_string())
+        );
+        assert!(validate_style_string("bg:#ff00", None).is_err());
+    }
+
     #[test]
     fn read_config_no_config_file_path_provided() {
         assert_eq!(
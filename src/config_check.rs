@@ -0,0 +1,1071 @@
+//! Validation of the configuration file for `starship config check`.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use pest::error::InputLocation;
+use serde::Deserialize;
+use toml::{Table, Value};
+use toml_edit::{Document, Item};
+
+use crate::config::{
+    self, PROFILE_EXTENDS, PROFILE_RULE_PROFILE, StarshipConfig, WHEN_CONDITIONS,
+    expand_style_classes, resolve_palette, resolve_profile, validate_style_string,
+};
+use crate::configs::custom::CustomConfig;
+use crate::configs::env_var::EnvVarConfig;
+use crate::configs::{FullConfig, Palette, StarshipRootConfig, default_profiles};
+use crate::context::Context;
+use crate::formatter::string_formatter::StringFormatterError;
+use crate::formatter::{StringFormatter, parse_literal_styles};
+use crate::module::ALL_MODULES;
+use crate::utils::serde::ValueDeserializer;
+
+/// A problem found in the configuration file
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Checks the configuration file, along with the files it includes and the project-local
+/// config, and prints every problem found. Returns false if there were any.
+pub fn check_configuration(context: &Context) -> bool {
+    let Some(config_path) = context.get_config_path_os() else {
+        eprintln!("Unable to determine the location of the configuration file");
+        return false;
+    };
+
+    let files = &context.config.files;
+    if files.is_empty() {
+        let display_path = Path::new(&config_path).display();
+        println!("No configuration file found at {display_path}, using the default configuration");
+        return true;
+    }
+    // Options may refer to palettes, styles and profiles defined in the other files
+    let merged = context.config.config.clone().unwrap_or_default();
+
+    let mut count = 0;
+    for file in files {
+        let Some(content) = StarshipConfig::read_config_content_as_str(Some(file.as_os_str()))
+        else {
+            continue;
+        };
+        let diagnostics = check_config_file(&content, Some(&merged));
+        for diagnostic in &diagnostics {
+            eprintln!("{}:{diagnostic}", file.display());
+        }
+        count += diagnostics.len();
+    }
+
+    let checked = match files.as_slice() {
+        [file] => file.display().to_string(),
+        files => format!("{} configuration files", files.len()),
+    };
+    match count {
+        0 => {
+            println!("No problems found in {checked}");
+            true
+        }
+        1 => {
+            eprintln!("Found 1 problem in {checked}");
+            false
+        }
+        count => {
+            eprintln!("Found {count} problems in {checked}");
+            false
+        }
+    }
+}
+
+/// Checks the content of a configuration file
+pub fn check_config_str(content: &str) -> Vec<Diagnostic> {
+    check_config_file(content, None)
+}
+
+/// Checks the content of one of the files of a layered configuration. Palettes, styles and
+/// profiles are looked up in `merged`, the configuration all the files make up, if set.
+pub fn check_config_file(content: &str, merged: Option<&Table>) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        content,
+        doc: match Document::parse(content) {
+            Ok(doc) => doc,
+            Err(error) => {
+                let offset = error.span().map_or(0, |span| span.start);
+                let message = error.message().trim_end().to_string();
+                return vec![diagnostic_at(content, offset, message)];
+            }
+        },
+        palette: None,
+        styles: HashMap::new(),
+        profiles: Table::new(),
+        diagnostics: Vec::new(),
+    };
+    let config: Table = match toml::from_str(content) {
+        Ok(config) => config,
+        Err(error) => {
+            let offset = error.span().map_or(0, |span| span.start);
+            return vec![diagnostic_at(content, offset, error.message().to_string())];
+        }
+    };
+    let merged = merged.unwrap_or(&config);
+
+    // Unknown keys are reported below, so don't warn about them here
+    let root =
+        StarshipRootConfig::deserialize(ValueDeserializer::new(merged).with_allow_unknown_keys())
+            .unwrap_or_default();
+    // Styles are checked against one palette, assuming the light and dark ones define the same colors
+    checker.palette = [&root.palette, &root.palette_dark, &root.palette_light]
+        .into_iter()
+        .find_map(Option::as_ref)
+        .and_then(|name| resolve_palette(&root.palettes, name).ok())
+        .map(|resolved| resolved.colors);
+
+    checker.styles = root.styles.clone();
+    checker.profiles = merged
+        .get("profiles")
+        .and_then(Value::as_table)
+        .cloned()
+        .unwrap_or_default();
+
+    checker.check_table(&[], &config);
+    checker.check_palettes(&root);
+
+    checker.diagnostics
+}
+
+/// A step into a TOML value, used to find its location in the file
+#[derive(Debug, Clone, Copy)]
+enum Step<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+struct Checker<'a> {
+    content: &'a str,
+    doc: Document<&'a str>,
+    palette: Option<Palette>,
+    styles: HashMap<String, String>,
+    /// The profiles of the whole configuration, which `extends` and `[[profile_rules]]` refer to
+    profiles: Table,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, offset: usize, message: String) {
+        self.diagnostics
+            .push(diagnostic_at(self.content, offset, message));
+    }
+
+    /// Finds the item at `path`, along with the offset of the last key of the path
+    /// that exists in the file.
+    fn locate(&self, path: &[Step]) -> (usize, Option<&Item>) {
+        let mut item = self.doc.as_item();
+        let mut offset = 0;
+        for step in path {
+            let next = match step {
+                Step::Key(key) => item
+                    .as_table_like()
+                    .and_then(|table| table.get_key_value(key))
+                    .map(|(key, item)| {
+                        offset = key.span().map_or(offset, |span| span.start);
+                        item
+                    }),
+                Step::Index(index) => item.get(*index).inspect(|item| {
+                    offset = item.span().map_or(offset, |span| span.start);
+                }),
+            };
+            match next {
+                Some(next) => item = next,
+                None => return (offset, None),
+            }
+        }
+        (offset, Some(item))
+    }
+
+    fn key_offset(&self, path: &[Step]) -> usize {
+        self.locate(path).0
+    }
+
+    /// Checks the options and modules of the root table or of a `[[when]]` block
+    fn check_table(&mut self, prefix: &[Step], config: &Table) {
+        for (key, value) in config {
+            let path = [prefix, &[Step::Key(key)]].concat();
+            match key.as_str() {
+                "custom" => self.check_named_modules(&path, value, |config| {
+                    CustomConfig::deserialize(ValueDeserializer::new(config)).map(|_| ())
+                }),
+                "env_var" => self.check_env_var(&path, value),
+                "when" if prefix.is_empty() => self.check_when(value),
+                "profiles" => self.check_profiles(&path, value),
+                "profile_rules" if prefix.is_empty() => self.check_profile_rules(value),
+                module if ALL_MODULES.contains(&module) => {
+                    self.check_keys(&path, value, |config| {
+                        let config =
+                            Table::from_iter([(key.clone(), Value::Table(config.clone()))]);
+                        FullConfig::deserialize(ValueDeserializer::new(&config))
+                            .map(|_| ())
+                            .map_err(|error| {
+                                // Drop the location of the module inside `FullConfig`
+                                let error = error.to_string();
+                                let prefix = format!(" at '{key}': ");
+                                error
+                                    .split_once(&prefix)
+                                    .map_or_else(|| error.clone(), |(_, error)| error.to_string())
+                            })
+                    });
+                }
+                _ => self.check_root_key(&path, key, value),
+            }
+            if !matches!(key.as_str(), "when" | "profiles" | "profile_rules") {
+                self.check_strings(&mut path.clone(), value);
+            }
+        }
+    }
+
+    /// Checks that the selected palette exists, and that the colors of every palette resolve.
+    /// Only the options and palettes set in this file are reported.
+    fn check_palettes(&mut self, root: &StarshipRootConfig) {
+        let mut names = root.palettes.keys().collect::<Vec<_>>();
+        names.sort();
+        let mut errors = Vec::new();
+        for name in names {
+            let resolved = resolve_palette(&root.palettes, name);
+            for error in resolved.map_or_else(|error| vec![error], |resolved| resolved.errors) {
+                if !errors.contains(&error) {
+                    errors.push(error);
+                }
+            }
+        }
+        for (option, name) in [
+            ("palette", &root.palette),
+            ("palette_light", &root.palette_light),
+            ("palette_dark", &root.palette_dark),
+        ] {
+            if let Some(name) = name
+                && self.locate(&[Step::Key(option)]).1.is_some()
+                && let Err(error) = resolve_palette(&root.palettes, name)
+                && error.key.is_none()
+            {
+                self.report(self.key_offset(&[Step::Key(option)]), error.to_string());
+            }
+        }
+
+        for error in errors {
+            let mut path = vec![Step::Key("palettes"), Step::Key(&error.palette)];
+            if self.locate(&path).1.is_none() {
+                continue;
+            }
+            path.extend(error.key.as_deref().map(Step::Key));
+            self.report(self.key_offset(&path), error.to_string());
+        }
+    }
+
+    /// Checks the conditions and the contents of `[[when]]` blocks
+    fn check_when(&mut self, value: &Value) {
+        let Value::Array(blocks) = value else {
+            let message = format!("Expected [[when]] tables, found {}", value.type_str());
+            self.report(self.key_offset(&[Step::Key("when")]), message);
+            return;
+        };
+        for (index, block) in blocks.iter().enumerate() {
+            let prefix = [Step::Key("when"), Step::Index(index)];
+            let Value::Table(block) = block else {
+                let message = format!("Expected a table, found {}", block.type_str());
+                self.report(self.locate(&prefix).0, message);
+                continue;
+            };
+            let (conditions, contents): (Table, Table) =
+                block.clone().into_iter().partition(|(key, value)| {
+                    WHEN_CONDITIONS.contains(&key.as_str()) && !value.is_table()
+                });
+            for (key, condition) in &conditions {
+                self.check_condition(&[&prefix[..], &[Step::Key(key)]].concat(), key, condition);
+            }
+            self.check_table(&prefix, &contents);
+        }
+    }
+
+    /// Checks that `[[profile_rules]]` select existing profiles, with valid conditions
+    fn check_profile_rules(&mut self, value: &Value) {
+        let Value::Array(rules) = value else {
+            let message = format!(
+                "Expected [[profile_rules]] tables, found {}",
+                value.type_str()
+            );
+            self.report(self.key_offset(&[Step::Key("profile_rules")]), message);
+            return;
+        };
+        for (index, rule) in rules.iter().enumerate() {
+            let prefix = [Step::Key("profile_rules"), Step::Index(index)];
+            let Value::Table(rule) = rule else {
+                let message = format!("Expected a table, found {}", rule.type_str());
+                self.report(self.locate(&prefix).0, message);
+                continue;
+            };
+            match rule.get(PROFILE_RULE_PROFILE) {
+                Some(Value::String(profile)) => {
+                    if !self.profiles.contains_key(profile)
+                        && !default_profiles().contains_key(profile)
+                    {
+                        let path = [&prefix[..], &[Step::Key(PROFILE_RULE_PROFILE)]].concat();
+                        let (offset, item) = self.locate(&path);
+                        let offset = item.and_then(Item::span).map_or(offset, |span| span.start);
+                        self.report(offset, format!("Could not find profile '{profile}'"));
+                    }
+                }
+                Some(other) => {
+                    let path = [&prefix[..], &[Step::Key(PROFILE_RULE_PROFILE)]].concat();
+                    let message =
+                        format!("Expected the name of a profile, found {}", other.type_str());
+                    self.report(self.key_offset(&path), message);
+                }
+                None => {
+                    let message = format!("Missing `{PROFILE_RULE_PROFILE}` to select");
+                    self.report(self.locate(&prefix).0, message);
+                }
+            }
+            for (key, condition) in rule {
+                let path = [&prefix[..], &[Step::Key(key)]].concat();
+                if key == PROFILE_RULE_PROFILE {
+                    continue;
+                } else if WHEN_CONDITIONS.contains(&key.as_str()) {
+                    self.check_condition(&path, key, condition);
+                } else {
+                    let message = format!(
+                        "Unknown condition '{key}'{}",
+                        did_you_mean(key, WHEN_CONDITIONS.iter().copied())
+                    );
+                    self.report(self.key_offset(&path), message);
+                }
+            }
+        }
+    }
+
+    /// Checks that a condition is a pattern or a list of patterns
+    fn check_condition(&mut self, path: &[Step], key: &str, condition: &Value) {
+        let valid = match condition {
+            Value::String(_) => true,
+            Value::Array(patterns) => patterns.iter().all(Value::is_str),
+            _ => false,
+        };
+        if !valid {
+            let message = format!(
+                "The `{key}` condition expects a pattern or a list of patterns, found {}",
+                condition.type_str()
+            );
+            self.report(self.key_offset(path), message);
+        }
+    }
+
+    /// Checks that profiles are format strings or tables, and checks the contents of the tables
+    /// like the root table
+    fn check_profiles(&mut self, path: &[Step], value: &Value) {
+        let Value::Table(profiles) = value else {
+            let message = format!("Expected a table, found {}", value.type_str());
+            self.report(self.key_offset(path), message);
+            return;
+        };
+        let mut errors = Vec::new();
+        for (name, profile) in profiles {
+            let prefix = [path, &[Step::Key(name)]].concat();
+            match profile {
+                Value::String(_) => self.check_strings(&mut prefix.clone(), profile),
+                Value::Table(table) => {
+                    // Profiles of `[[when]]` blocks may extend the ones of the root table
+                    if path.len() == 1
+                        && let Err(error) = resolve_profile(&self.profiles, name)
+                        && !errors.contains(&error)
+                    {
+                        let extends = [&prefix[..], &[Step::Key(PROFILE_EXTENDS)]].concat();
+                        self.report(self.key_offset(&extends), error.clone());
+                        errors.push(error);
+                    }
+                    let mut contents = table.clone();
+                    contents.remove(PROFILE_EXTENDS);
+                    self.check_table(&prefix, &contents);
+                }
+                other => {
+                    let message = format!(
+                        "Expected a format string or a table, found {}",
+                        other.type_str()
+                    );
+                    self.report(self.key_offset(&prefix), message);
+                }
+            }
+        }
+    }
+
+    /// Deserializes each key of a table on its own, so that every invalid key is reported
+    fn check_keys<E: fmt::Display>(
+        &mut self,
+        path: &[Step],
+        value: &Value,
+        deserialize: impl Fn(&Table) -> Result<(), E>,
+    ) {
+        let Value::Table(table) = value else {
+            let message = format!("Expected a table, found {}", value.type_str());
+            self.report(self.key_offset(path), message);
+            return;
+        };
+        for (key, value) in table {
+            let config = Table::from_iter([(key.clone(), value.clone())]);
+            if let Err(error) = deserialize(&config) {
+                let offset = self.key_offset(&[path, &[Step::Key(key)]].concat());
+                self.report(offset, error.to_string());
+            }
+        }
+    }
+
+    /// Checks tables of modules with user chosen names, like `[custom.foo]`
+    fn check_named_modules<E: fmt::Display>(
+        &mut self,
+        path: &[Step],
+        value: &Value,
+        deserialize: impl Fn(&Table) -> Result<(), E> + Copy,
+    ) {
+        let Value::Table(modules) = value else {
+            let message = format!("Expected a table, found {}", value.type_str());
+            self.report(self.key_offset(path), message);
+            return;
+        };
+        for (name, config) in modules {
+            let path = [path, &[Step::Key(name)]].concat();
+            self.check_keys(&path, config, deserialize);
+        }
+    }
+
+    /// `[env_var]` either configures a single module or contains named ones
+    fn check_env_var(&mut self, path: &[Step], value: &Value) {
+        let Value::Table(table) = value else {
+            let message = format!("Expected a table, found {}", value.type_str());
+            self.report(self.key_offset(path), message);
+            return;
+        };
+        let (named, options): (Table, Table) = table
+            .clone()
+            .into_iter()
+            .partition(|(_, value)| value.is_table());
+        let deserialize =
+            |config: &Table| EnvVarConfig::deserialize(ValueDeserializer::new(config)).map(|_| ());
+        self.check_named_modules(path, &Value::Table(named), deserialize);
+        self.check_keys(path, &Value::Table(options), deserialize);
+    }
+
+    fn check_root_key(&mut self, path: &[Step], key: &str, value: &Value) {
+        let config = Table::from_iter([(key.to_string(), value.clone())]);
+        let Err(error) = StarshipRootConfig::deserialize(ValueDeserializer::new(&config)) else {
+            return;
+        };
+
+        let offset = self.key_offset(path);
+        let message = if value.is_table() && error.to_string().contains("Unknown key") {
+            let candidates = ALL_MODULES
+                .iter()
+                .copied()
+                .chain(["custom", "env_var", "palettes", "profiles"]);
+            format!("Unknown module '{key}'{}", did_you_mean(key, candidates))
+        } else {
+            error.to_string()
+        };
+        self.report(offset, message);
+    }
+
+    /// Parses all format strings and styles below `path`
+    fn check_strings<'v>(&mut self, path: &mut Vec<Step<'v>>, value: &'v Value) {
+        match value {
+            Value::Table(table) => {
+                // Palettes only contain colors, which are checked where they are used
+                if matches!(config_path(path), [Step::Key("palettes")]) {
+                    return;
+                }
+                for (key, value) in table {
+                    path.push(Step::Key(key));
+                    self.check_strings(path, value);
+                    path.pop();
+                }
+            }
+            Value::Array(array) => {
+                for (index, value) in array.iter().enumerate() {
+                    path.push(Step::Index(index));
+                    self.check_strings(path, value);
+                    path.pop();
+                }
+            }
+            Value::String(string) => {
+                if is_format_option(path) {
+                    match StringFormatter::new(string) {
+                        Ok(_) => self.check_format_styles(path, string),
+                        Err(error) => self.report_format_error(path, string, &error),
+                    }
+                } else if is_style_option(path) {
+                    let (offset, item) = self.locate(path);
+                    let offset = item.and_then(Item::span).map_or(offset, |span| span.start);
+                    for message in self.style_errors(string) {
+                        self.report(offset, message);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks the styles of the text groups of a format string. Styles containing
+    /// variables are only known once the prompt is rendered.
+    fn check_format_styles(&mut self, path: &[Step], format: &str) {
+        let Ok(styles) = parse_literal_styles(format) else {
+            return;
+        };
+        let (key_offset, item) = self.locate(path);
+        let span = item.and_then(Item::span);
+        let start = span
+            .clone()
+            .and_then(|span| string_start(self.content, span, format));
+        for (style, range) in styles {
+            let offset = match (&span, start) {
+                (_, Some(start)) => start + range.start,
+                (Some(span), None) => span.start,
+                (None, None) => key_offset,
+            };
+            for message in self.style_errors(style) {
+                self.report(offset, message);
+            }
+        }
+    }
+
+    /// Expands the style classes of a style string and validates the result
+    fn style_errors(&self, style: &str) -> Vec<String> {
+        let (style, mut errors) = expand_style_classes(style, &self.styles);
+        if let Err(message) = validate_style_string(&style, self.palette.as_ref()) {
+            errors.push(message);
+        }
+        errors
+    }
+
+    fn report_format_error(&mut self, path: &[Step], format: &str, error: &StringFormatterError) {
+        let (key_offset, item) = self.locate(path);
+        let span = item.and_then(Item::span);
+        let (message, position) = match error {
+            StringFormatterError::Parse(error) => {
+                let position = match error.location {
+                    InputLocation::Pos(position) | InputLocation::Span((position, _)) => position,
+                };
+                (
+                    format!("Invalid format string: {}", error.variant.message()),
+                    Some(position),
+                )
+            }
+            StringFormatterError::Custom(error) => {
+                (format!("Invalid format string: {error}"), None)
+            }
+        };
+
+        let offset = match (span, position) {
+            (Some(span), Some(position)) => string_start(self.content, span.clone(), format)
+                .map_or(span.start, |start| start + position),
+            (Some(span), None) => span.start,
+            (None, _) => key_offset,
+        };
+        self.report(offset, message);
+    }
+}
+
+/// Returns the offset of the content of a string value in the file, if it can be found
+/// without interpreting escape sequences
+fn string_start(content: &str, span: Range<usize>, string: &str) -> Option<usize> {
+    let raw = content.get(span.clone())?;
+    // Skip the quotes and the newline directly following the quotes of multi-line strings
+    [1, 3, 4, 5]
+        .into_iter()
+        .find(|&prefix| raw.get(prefix..prefix + string.len()) == Some(string))
+        .map(|prefix| span.start + prefix)
+}
+
+/// Strips the `[[when]]` block and the table profile from a path, if any
+fn config_path<'p, 'a>(path: &'p [Step<'a>]) -> &'p [Step<'a>] {
+    let path = match path {
+        [Step::Key("when"), Step::Index(_), rest @ ..] => rest,
+        path => path,
+    };
+    match path {
+        [Step::Key("profiles"), Step::Key(_), rest @ ..] if !rest.is_empty() => rest,
+        path => path,
+    }
+}
+
+fn is_format_option(path: &[Step]) -> bool {
+    match config_path(path) {
+        [Step::Key("continuation_prompt" | "render_timeout_placeholder")]
+        | [Step::Key("profiles"), Step::Key(_)] => true,
+        [.., Step::Key(key)] => key.ends_with("format") && *key != "time_format",
+        _ => false,
+    }
+}
+
+fn is_style_option(path: &[Step]) -> bool {
+    if let Some(Step::Index(_)) = path.last() {
+        return false;
+    }
+    let keys = path
+        .iter()
+        .filter_map(|step| match step {
+            Step::Key(key) => Some(*key),
+            Step::Index(_) => None,
+        })
+        .collect::<Vec<_>>();
+    config::is_style_option(&keys)
+}
+
+fn did_you_mean<'s>(name: &str, candidates: impl Iterator<Item = &'s str>) -> String {
+    candidates
+        .filter_map(|candidate| {
+            let score = strsim::jaro_winkler(name, candidate);
+            (score > 0.8).then_some((score, candidate))
+        })
+        .max_by(|(score_a, _), (score_b, _)| {
+            score_a.partial_cmp(score_b).unwrap_or(Ordering::Equal)
+        })
+        .map(|(_, candidate)| format!(" (Did you mean '{candidate}'?)"))
+        .unwrap_or_default()
+}
+
+fn diagnostic_at(content: &str, offset: usize, message: String) -> Diagnostic {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Diagnostic {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(content: &str) -> Vec<(usize, usize, String)> {
+        check_config_str(content)
+            .into_iter()
+            .map(
+                |Diagnostic {
+                     line,
+                     column,
+                     message,
+                 }| (line, column, message),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let content = r##"
+format = "$all"
+palette = "custom"
+
+[palettes.custom]
+mustard = "#af8700"
+
+[git_branch]
+symbol = " "
+style = "bold mustard"
+priority = 10
+
+[custom.foo]
+command = "echo foo"
+when = true
+format = "[$output]($style) "
+
+[env_var.SHELL]
+variable = "SHELL"
+
+[time]
+time_format = "%T"
+"##;
+        assert_eq!(check(content), vec![]);
+    }
+
+    #[test]
+    fn invalid_color_mode() {
+        assert_eq!(check("color_mode = '256'\n"), vec![]);
+        assert_eq!(
+            check("color_mode = 'full'\n"),
+            vec![(
+                1,
+                1,
+                "unknown variant `full`, expected one of `auto`, `truecolor`, `256`, `16`, `none`"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_toml() {
+        let diagnostics = check("[git_branch\nsymbol = 1\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].0, diagnostics[0].1), (1, 12));
+    }
+
+    #[test]
+    fn unknown_keys_and_modules() {
+        let content = r#"
+add_newlin = false
+
+[git_branch]
+symbl = " "
+truncation_length = "four"
+
+[git_brnch]
+symbol = " "
+
+[custom.foo]
+comand = "echo foo"
+"#;
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    2,
+                    1,
+                    "Error in 'StarshipRoot' at 'add_newlin': Unknown key (Did you mean 'add_newline'?)"
+                        .to_string()
+                ),
+                (
+                    5,
+                    1,
+                    "Error in 'GitBranch' at 'symbl': Unknown key (Did you mean 'symbol'?)"
+                        .to_string()
+                ),
+                (
+                    6,
+                    1,
+                    "Error in 'GitBranch' at 'truncation_length': invalid type: string \"four\", expected i64"
+                        .to_string()
+                ),
+                (
+                    8,
+                    2,
+                    "Unknown module 'git_brnch' (Did you mean 'git_branch'?)".to_string()
+                ),
+                (
+                    12,
+                    1,
+                    "Error in 'Custom' at 'comand': Unknown key (Did you mean 'command'?)".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_var_options() {
+        let content = "[env_var]\nvariable = \"SHELL\"\nvariabel = \"SHELL\"\n";
+        assert_eq!(
+            check(content),
+            vec![(
+                3,
+                1,
+                "Error in 'EnvVar' at 'variabel': Unknown key (Did you mean 'variable'?)"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_format_strings() {
+        let content = "[directory]\nformat = \"[$path]($style\"\n\n[profiles]\nshort = '''\n$directory[\n'''\n";
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    2,
+                    25,
+                    "Invalid format string: expected variable, filter, or string".to_string()
+                ),
+                (
+                    7,
+                    1,
+                    "Invalid format string: expected variable, string, textgroup, conditional, or if_else"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn when_blocks() {
+        let content = r"
+[[when]]
+shell = 'zsh'
+hostname = 1
+format = '$directory['
+
+[when.git_branch]
+symbl = 'x'
+
+[when.os]
+disabled = false
+";
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    4,
+                    1,
+                    "The `hostname` condition expects a pattern or a list of patterns, found integer"
+                        .to_string()
+                ),
+                (
+                    5,
+                    22,
+                    "Invalid format string: expected variable, string, textgroup, conditional, or if_else"
+                        .to_string()
+                ),
+                (
+                    8,
+                    1,
+                    "Error in 'GitBranch' at 'symbl': Unknown key (Did you mean 'symbol'?)"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_styles() {
+        let content = "[character]\nsuccess_symbol = \"[❯](bold green)\"\n\n[git_status]\nstyle = \"bold mustard\"\n";
+        assert_eq!(
+            check(content),
+            vec![(
+                5,
+                9,
+                "Unknown color or style attribute 'mustard'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_styles_in_format_strings() {
+        let content = concat!(
+            "[git_branch]\n",
+            "format = '[$branch](bold mustard) [$symbol]($style) ([x](fg:red))'\n",
+            "[directory]\n",
+            "format = '$if($read_only)[ro](bg:blu)$else[rw](green)'\n",
+        );
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    2,
+                    21,
+                    "Unknown color or style attribute 'mustard'".to_string()
+                ),
+                (
+                    4,
+                    31,
+                    "Unknown color or style attribute 'bg:blu'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn style_classes() {
+        let content = concat!(
+            "[styles]\n",
+            "danger = 'bold fg:red'\n",
+            "muted = 'dimmed mustard'\n",
+            "[git_branch]\n",
+            "style = '@danger @dangr'\n",
+        );
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    3,
+                    9,
+                    "Unknown color or style attribute 'mustard'".to_string()
+                ),
+                (5, 9, "Unknown style class '@dangr'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let content = concat!(
+            "[profiles]\n",
+            "short = '$directory['\n",
+            "[profiles.work]\n",
+            "extends = 'missing'\n",
+            "right_format = '$time('\n",
+            "[profiles.work.directory]\n",
+            "styl = 'red'\n",
+            "[profiles.work.git_brnch]\n",
+            "[profiles.other]\n",
+            "extends = 'short'\n",
+            "add_newline = false\n",
+        );
+        let invalid =
+            "Invalid format string: expected variable, string, textgroup, conditional, or if_else";
+        assert_eq!(
+            check(content),
+            vec![
+                (2, 21, invalid.to_string()),
+                (4, 1, "Could not find profile 'missing'".to_string()),
+                (5, 23, invalid.to_string()),
+                (
+                    7,
+                    1,
+                    "Error in 'Directory' at 'styl': Unknown key (Did you mean 'style'?)"
+                        .to_string()
+                ),
+                (
+                    8,
+                    16,
+                    "Unknown module 'git_brnch' (Did you mean 'git_branch'?)".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_rules() {
+        let content = concat!(
+            "[profiles]\n",
+            "minimal = '$character'\n",
+            "[[profile_rules]]\n",
+            "profile = 'minimal'\n",
+            "directory = '~/scratch/**'\n",
+            "[[profile_rules]]\n",
+            "profile = 'verbose'\n",
+            "hostnam = 'work-*'\n",
+            "[[profile_rules]]\n",
+            "env = 1\n",
+            "[[profile_rules]]\n",
+            "profile = 'claude-code'\n",
+        );
+        assert_eq!(
+            check(content),
+            vec![
+                (7, 11, "Could not find profile 'verbose'".to_string()),
+                (
+                    8,
+                    1,
+                    "Unknown condition 'hostnam' (Did you mean 'hostname'?)".to_string()
+                ),
+                (9, 1, "Missing `profile` to select".to_string()),
+                (
+                    10,
+                    1,
+                    "The `env` condition expects a pattern or a list of patterns, found integer"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn palettes() {
+        let content = concat!(
+            "palette = 'dark'\n",
+            "[git_status]\n",
+            "style = 'bold accent'\n",
+            "[git_branch]\n",
+            "style = 'bold mustard'\n",
+            "[palettes.base]\n",
+            "accent = 'lighten(blu, 10%)'\n",
+            "[palettes.dark]\n",
+            "extends = 'base'\n",
+            "blu = '#000080'\n",
+            "[palettes.light]\n",
+            "extends = 'nothing'\n",
+        );
+        assert_eq!(
+            check(content),
+            vec![
+                (
+                    5,
+                    9,
+                    "Unknown color or style attribute 'mustard'".to_string()
+                ),
+                (
+                    7,
+                    1,
+                    "Error in palette 'base' at 'accent': Unknown color 'blu'".to_string()
+                ),
+                (
+                    12,
+                    1,
+                    "Error in palette 'light' at 'extends': Could not find color palette 'nothing'"
+                        .to_string()
+                ),
+            ]
+        );
+
+        let content = "palette = 'dark'\npalette_light = 'light'\n";
+        assert_eq!(
+            check(content),
+            vec![
+                (1, 1, "Could not find color palette 'dark'".to_string()),
+                (2, 1, "Could not find color palette 'light'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn layered_files() {
+        let main = concat!(
+            "palette = 'dark'\n",
+            "[palettes.dark]\n",
+            "accent = '#ff8700'\n",
+            "[profiles.work]\n",
+            "format = '$directory'\n",
+        );
+        let project = concat!(
+            "[[profile_rules]]\n",
+            "profile = 'work'\n",
+            "[profiles.deploy]\n",
+            "extends = 'work'\n",
+            "[directory]\n",
+            "style = 'bold accent'\n",
+            "truncation_lenght = 1\n",
+        );
+        let merged = toml::toml! {
+            palette = "dark"
+            [palettes.dark]
+            accent = "#ff8700"
+            [profiles.work]
+            format = "$directory"
+            [profiles.deploy]
+            extends = "work"
+            [[profile_rules]]
+            profile = "work"
+            [directory]
+            style = "bold accent"
+            truncation_lenght = 1
+        };
+
+        // Names defined in the other files resolve, and the locations are those in each file
+        assert_eq!(
+            check_config_file(project, Some(&merged)),
+            vec![Diagnostic {
+                line: 7,
+                column: 1,
+                message: "Error in 'Directory' at 'truncation_lenght': Unknown key (Did you mean 'truncation_length'?)".to_string(),
+            }]
+        );
+        assert_eq!(check_config_file(main, Some(&merged)), Vec::new());
+
+        // On its own, the project file refers to unknown names
+        assert_eq!(check(project).len(), 4);
+    }
+}
@@ -0,0 +1,553 @@
+//! Renamed and deprecated options, applied when loading the configuration
+//! and written back to the configuration file by `starship config migrate`.
+
+use std::path::PathBuf;
+
+use toml_edit::{DocumentMut, Item, Key, TableLike};
+
+use crate::context::Context;
+use crate::utils;
+
+/// An option that was moved to another key, possibly in another table.
+/// A `*` matches any key, like the name of a custom module, and is kept at
+/// the same position in the new key.
+struct Rename {
+    from: &'static str,
+    to: &'static str,
+}
+
+/// An option that can't be migrated automatically
+struct Deprecation {
+    key: &'static str,
+    hint: &'static str,
+}
+
+const RENAMES: &[Rename] = &[
+    Rename {
+        from: "character.vicmd_symbol",
+        to: "character.vimcmd_symbol",
+    },
+    Rename {
+        from: "custom.*.files",
+        to: "custom.*.detect_files",
+    },
+    Rename {
+        from: "custom.*.extensions",
+        to: "custom.*.detect_extensions",
+    },
+    Rename {
+        from: "custom.*.directories",
+        to: "custom.*.detect_folders",
+    },
+    Rename {
+        from: "fennel.detect_extentions",
+        to: "fennel.detect_extensions",
+    },
+];
+
+const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        key: "jobs.threshold",
+        hint: "use `symbol_threshold` and `number_threshold` instead",
+    },
+    Deprecation {
+        key: "kubernetes.context_aliases",
+        hint: "use `contexts` with `context_alias` instead",
+    },
+    Deprecation {
+        key: "kubernetes.user_aliases",
+        hint: "use `contexts` with `user_alias` instead",
+    },
+];
+
+/// A change made to the configuration, or a deprecated option that was found
+#[derive(Debug, PartialEq, Eq)]
+pub enum Migration {
+    Renamed { from: String, to: String },
+    Shadowed { from: String, to: String },
+    Deprecated { key: String, hint: &'static str },
+}
+
+impl std::fmt::Display for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Renamed { from, to } => write!(f, "`{from}` was renamed to `{to}`"),
+            Self::Shadowed { from, to } => {
+                write!(f, "`{from}` was renamed to `{to}`, which is already set")
+            }
+            Self::Deprecated { key, hint } => write!(f, "`{key}` is deprecated, {hint}"),
+        }
+    }
+}
+
+impl Migration {
+    /// Prefixes the keys with the path of the table they were found in, like a `[[when]]` block
+    fn prefixed(self, path: &str) -> Self {
+        let prefix = |key: String| format!("{path}.{key}");
+        match self {
+            Self::Renamed { from, to } => Self::Renamed {
+                from: prefix(from),
+                to: prefix(to),
+            },
+            Self::Shadowed { from, to } => Self::Shadowed {
+                from: prefix(from),
+                to: prefix(to),
+            },
+            Self::Deprecated { key, hint } => Self::Deprecated {
+                key: prefix(key),
+                hint,
+            },
+        }
+    }
+}
+
+/// Moves renamed options of a config file to their new keys, including those in `[[when]]`
+/// blocks and `[profiles.<name>]` tables, and keeps the formatting, comments and order of the
+/// file. Options set under both names keep the new one.
+pub fn migrate_document(doc: &mut DocumentMut) -> Vec<Migration> {
+    let table = doc.as_table_mut();
+    let mut migrations = migrate_table_like(table);
+
+    let mut migrate_nested = |path: String, table: &mut dyn TableLike| {
+        let nested = migrate_table_like(table).into_iter();
+        migrations.extend(nested.map(|migration| migration.prefixed(&path)));
+    };
+    match table.get_mut("when") {
+        Some(Item::ArrayOfTables(blocks)) => {
+            for (index, block) in blocks.iter_mut().enumerate() {
+                migrate_nested(format!("when[{index}]"), block);
+            }
+        }
+        Some(Item::Value(toml_edit::Value::Array(blocks))) => {
+            for (index, block) in blocks.iter_mut().enumerate() {
+                if let Some(block) = block.as_inline_table_mut() {
+                    migrate_nested(format!("when[{index}]"), block);
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(profiles) = table.get_mut("profiles").and_then(Item::as_table_like_mut) {
+        for (name, profile) in profiles.iter_mut() {
+            // Profiles which are just a format string have no options to migrate
+            if let Some(profile) = profile.as_table_like_mut() {
+                migrate_nested(format!("profiles.{}", name.get()), profile);
+            }
+        }
+    }
+
+    migrations
+}
+
+fn migrate_table_like(table: &mut dyn TableLike) -> Vec<Migration> {
+    let mut migrations = Vec::new();
+
+    for rename in RENAMES {
+        let paths = expand_path(rename.from, |path| {
+            table_like_at(table, path)
+                .map(|table| table.iter().map(|(key, _)| key.to_owned()).collect())
+        });
+        for from in paths {
+            let to = renamed_path(rename, &from);
+            let (from_key, from_parent) = from.split_last().unwrap();
+            let (to_key, to_parent) = to.split_last().unwrap();
+
+            if table_like_at(table, to_parent).is_some_and(|parent| parent.contains_key(to_key)) {
+                table_like_at_mut(table, from_parent).and_then(|parent| parent.remove(from_key));
+                migrations.push(Migration::Shadowed {
+                    from: from.join("."),
+                    to: to.join("."),
+                });
+                continue;
+            }
+
+            if from_parent == to_parent {
+                rename_key(
+                    table_like_at_mut(table, from_parent).unwrap(),
+                    from_key,
+                    to_key,
+                );
+            } else {
+                let item = table_like_at_mut(table, from_parent)
+                    .and_then(|parent| parent.remove(from_key))
+                    .unwrap();
+                if let Some(parent) = create_table_like_at(table, to_parent) {
+                    parent.insert(to_key, item);
+                }
+            }
+            migrations.push(Migration::Renamed {
+                from: from.join("."),
+                to: to.join("."),
+            });
+        }
+    }
+
+    for deprecation in DEPRECATIONS {
+        let paths = expand_path(deprecation.key, |path| {
+            table_like_at(table, path)
+                .map(|table| table.iter().map(|(key, _)| key.to_owned()).collect())
+        });
+        migrations.extend(paths.into_iter().map(|key| Migration::Deprecated {
+            key: key.join("."),
+            hint: deprecation.hint,
+        }));
+    }
+
+    migrations
+}
+
+/// Renames a key in place, keeping its position and comments
+fn rename_key(table: &mut dyn TableLike, from: &str, to: &str) {
+    let keys = table
+        .iter()
+        .map(|(key, _)| key.to_owned())
+        .skip_while(|key| key != from)
+        .collect::<Vec<_>>();
+    let entries = keys
+        .iter()
+        .filter_map(|key| Some((table.key(key)?.clone(), table.remove(key)?)))
+        .collect::<Vec<_>>();
+
+    for (key, item) in entries {
+        let key = if key.get() == from {
+            Key::new(to)
+                .with_leaf_decor(key.leaf_decor().clone())
+                .with_dotted_decor(key.dotted_decor().clone())
+        } else {
+            key
+        };
+        table.entry_format(&key).or_insert(item);
+    }
+}
+
+/// The existing paths that match a pattern with `*` segments, given the keys
+/// of the table at a path
+fn expand_path(
+    pattern: &str,
+    keys_at: impl Fn(&[String]) -> Option<Vec<String>>,
+) -> Vec<Vec<String>> {
+    pattern.split('.').fold(vec![Vec::new()], |paths, segment| {
+        paths
+            .into_iter()
+            .flat_map(|path| {
+                keys_at(&path)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|key| segment == "*" || key == segment)
+                    .map(|key| {
+                        let mut path = path.clone();
+                        path.push(key);
+                        path
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    })
+}
+
+fn renamed_path(rename: &Rename, from: &[String]) -> Vec<String> {
+    rename
+        .to
+        .split('.')
+        .enumerate()
+        .map(|(i, segment)| match (segment, from.get(i)) {
+            ("*", Some(key)) => key.clone(),
+            (segment, _) => segment.to_owned(),
+        })
+        .collect()
+}
+
+fn table_like_at<'a>(table: &'a dyn TableLike, path: &[String]) -> Option<&'a dyn TableLike> {
+    path.iter()
+        .try_fold(table, |table, key| table.get(key)?.as_table_like())
+}
+
+fn table_like_at_mut<'a>(
+    table: &'a mut dyn TableLike,
+    path: &[String],
+) -> Option<&'a mut dyn TableLike> {
+    path.iter()
+        .try_fold(table, |table, key| table.get_mut(key)?.as_table_like_mut())
+}
+
+fn create_table_like_at<'a>(
+    table: &'a mut dyn TableLike,
+    path: &[String],
+) -> Option<&'a mut dyn TableLike> {
+    path.iter().try_fold(table, |table, key| {
+        table
+            .entry(key)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+    })
+}
+
+/// Rewrites the configuration file, the files it includes and the project config of
+/// the current directory with renamed options moved to their new keys.
+/// Returns false if a file couldn't be migrated.
+pub fn migrate_configuration(context: &Context) -> bool {
+    let Some(config_path) = context.get_config_path_os() else {
+        eprintln!("Unable to determine the location of the configuration file");
+        return false;
+    };
+    let config_path = PathBuf::from(config_path);
+    if !config_path.exists() {
+        println!("No configuration file found at {}", config_path.display());
+        return true;
+    }
+
+    // The main file may not have been loaded if it is invalid
+    let mut files = vec![config_path];
+    for file in &context.config.files {
+        let canonical = dunce::canonicalize(file).ok();
+        let known = files
+            .iter()
+            .any(|known| known == file || dunce::canonicalize(known).ok() == canonical);
+        if !known {
+            files.push(file.clone());
+        }
+    }
+    migrate_files(&files)
+}
+
+/// Rewrites each of the `files` with renamed options moved to their new keys.
+/// Returns false if one of them couldn't be migrated.
+fn migrate_files(files: &[PathBuf]) -> bool {
+    files.iter().fold(true, |migrated, path| {
+        let display_path = path.display();
+        let content = match utils::read_file(path) {
+            Ok(content) => content,
+            Err(error) => {
+                eprintln!("Unable to read {display_path}: {error}");
+                return false;
+            }
+        };
+        let mut doc = match content.parse::<DocumentMut>() {
+            Ok(doc) => doc,
+            Err(error) => {
+                eprintln!("Unable to parse {display_path}: {error}");
+                return false;
+            }
+        };
+
+        let migrations = migrate_document(&mut doc);
+        for migration in &migrations {
+            println!("{display_path}: {migration}");
+        }
+
+        if migrations
+            .iter()
+            .all(|migration| matches!(migration, Migration::Deprecated { .. }))
+        {
+            println!("No options to migrate in {display_path}");
+        } else if let Err(error) = utils::write_file_atomic(path, doc.to_string(), true) {
+            eprintln!("Unable to write {display_path}: {error}");
+            return false;
+        } else {
+            println!("Updated {display_path}");
+        }
+        migrated
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Migrates a config file, returning the migrated config and the descriptions of the changes
+    fn migrate(content: &str) -> (toml::Table, Vec<String>) {
+        let mut doc = content.parse::<DocumentMut>().unwrap();
+        let migrations = migrate_document(&mut doc);
+        (
+            toml::from_str(&doc.to_string()).unwrap(),
+            migrations.iter().map(ToString::to_string).collect(),
+        )
+    }
+
+    #[test]
+    fn migrate_renamed_options() {
+        let (table, migrations) = migrate(
+            r#"
+            [character]
+            vicmd_symbol = "V"
+
+            [custom.foo]
+            command = "echo foo"
+            files = ["foo"]
+            extensions = ["bar"]
+
+            [custom.bar]
+            directories = ["baz"]
+            detect_folders = ["qux"]
+
+            [[when]]
+            shell = "fish"
+            [when.fennel]
+            detect_extentions = ["fnl"]
+
+            [profiles.short]
+            format = "$character"
+            character.vicmd_symbol = "v"
+            "#,
+        );
+
+        let expected = toml::toml! {
+            [character]
+            vimcmd_symbol = "V"
+
+            [custom.foo]
+            command = "echo foo"
+            detect_files = ["foo"]
+            detect_extensions = ["bar"]
+
+            [custom.bar]
+            detect_folders = ["qux"]
+
+            [[when]]
+            shell = "fish"
+            [when.fennel]
+            detect_extensions = ["fnl"]
+
+            [profiles.short]
+            format = "$character"
+            character.vimcmd_symbol = "v"
+        };
+        assert_eq!(table, expected);
+        assert_eq!(
+            migrations,
+            vec![
+                "`character.vicmd_symbol` was renamed to `character.vimcmd_symbol`",
+                "`custom.foo.files` was renamed to `custom.foo.detect_files`",
+                "`custom.foo.extensions` was renamed to `custom.foo.detect_extensions`",
+                "`custom.bar.directories` was renamed to `custom.bar.detect_folders`, which is already set",
+                "`when[0].fennel.detect_extentions` was renamed to `when[0].fennel.detect_extensions`",
+                "`profiles.short.character.vicmd_symbol` was renamed to `profiles.short.character.vimcmd_symbol`",
+            ]
+        );
+    }
+
+    #[test]
+    fn migrate_deprecated_options() {
+        let mut doc = "[jobs]\nthreshold = 4\n".parse::<DocumentMut>().unwrap();
+
+        let migrations = migrate_document(&mut doc);
+
+        assert_eq!(doc["jobs"]["threshold"].as_integer(), Some(4));
+        assert_eq!(
+            migrations,
+            vec![Migration::Deprecated {
+                key: "jobs.threshold".to_owned(),
+                hint: "use `symbol_threshold` and `number_threshold` instead",
+            }]
+        );
+    }
+
+    #[test]
+    fn migrate_keeps_formatting() {
+        let mut doc = concat!(
+            "# My prompt\n",
+            "format = '$all'\n",
+            "\n",
+            "[custom.foo] # foo\n",
+            "command = 'echo foo'\n",
+            "files = ['foo'] # the files\n",
+            "# the style\n",
+            "style = 'red'\n",
+            "\n",
+            "[character]\n",
+            "vicmd_symbol = 'V'\n",
+        )
+        .parse::<DocumentMut>()
+        .unwrap();
+
+        let migrations = migrate_document(&mut doc);
+
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "# My prompt\n",
+                "format = '$all'\n",
+                "\n",
+                "[custom.foo] # foo\n",
+                "command = 'echo foo'\n",
+                "detect_files = ['foo'] # the files\n",
+                "# the style\n",
+                "style = 'red'\n",
+                "\n",
+                "[character]\n",
+                "vimcmd_symbol = 'V'\n",
+            )
+        );
+    }
+
+    #[test]
+    fn migrate_document_when_blocks() {
+        let mut doc = concat!(
+            "[[when]]\n",
+            "shell = 'fish'\n",
+            "character.vicmd_symbol = 'V'\n",
+        )
+        .parse::<DocumentMut>()
+        .unwrap();
+
+        let migrations = migrate_document(&mut doc);
+
+        assert_eq!(
+            migrations,
+            vec![Migration::Renamed {
+                from: "when[0].character.vicmd_symbol".to_owned(),
+                to: "when[0].character.vimcmd_symbol".to_owned(),
+            }]
+        );
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "[[when]]\n",
+                "shell = 'fish'\n",
+                "character.vimcmd_symbol = 'V'\n",
+            )
+        );
+    }
+
+    #[test]
+    fn migrate_every_file() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let main = dir.path().join("starship.toml");
+        let included = dir.path().join("base.toml");
+        std::fs::write(
+            &main,
+            "include = 'base.toml'\n[character]\nvicmd_symbol = 'V'\n",
+        )?;
+        std::fs::write(&included, "[custom.foo]\nfiles = ['foo']\n")?;
+
+        assert!(migrate_files(&[main.clone(), included.clone()]));
+        assert_eq!(
+            utils::read_file(&main)?,
+            "include = 'base.toml'\n[character]\nvimcmd_symbol = 'V'\n"
+        );
+        assert_eq!(
+            utils::read_file(&included)?,
+            "[custom.foo]\ndetect_files = ['foo']\n"
+        );
+        dir.close()
+    }
+
+    #[test]
+    fn expand_path_wildcards() {
+        let doc = "[custom.foo]\nfiles = []\n[custom.bar]\ncommand = ''\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        let keys_at = |path: &[String]| {
+            table_like_at(doc.as_table(), path)
+                .map(|table| table.iter().map(|(key, _)| key.to_owned()).collect())
+        };
+
+        assert_eq!(
+            expand_path("custom.*.files", keys_at),
+            vec![vec!["custom", "foo", "files"]]
+        );
+        assert_eq!(expand_path("custom.*", keys_at).len(), 2);
+        assert!(expand_path("character.symbol", keys_at).is_empty());
+    }
+}
@@ -18,6 +18,7 @@ pub struct CustomConfig<'a> {
     pub shell: VecOr<&'a str>,
     pub description: &'a str,
     pub style: &'a str,
+    pub link: &'a str,
     pub disabled: bool,
     #[serde(alias = "files")]
     pub detect_files: Vec<&'a str>,
@@ -44,6 +45,7 @@ impl Default for CustomConfig<'_> {
             shell: VecOr::default(),
             description: "<custom config>",
             style: "green bold",
+            link: "",
             disabled: false,
             detect_files: Vec::default(),
             detect_extensions: Vec::default(),
@@ -113,6 +113,9 @@ pub mod zig;
 
 pub use starship_root::*;
 
+/// Options accepted by every module, used to lay out the prompt
+pub const MODULE_LAYOUT_OPTIONS: &[&str] = &["priority", "min_width"];
+
 #[derive(Serialize, Deserialize, Clone, Default)]
 #[cfg_attr(
     feature = "config-schema",
@@ -1,3 +1,4 @@
+use crate::config::VecOr;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -24,20 +25,75 @@ pub struct StarshipRootConfig {
     pub continuation_prompt: String,
     pub scan_timeout: u64,
     pub command_timeout: u64,
+    pub render_timeout: u64,
+    pub render_timeout_placeholder: String,
     pub add_newline: bool,
     pub follow_symlinks: bool,
+    pub version_cache: bool,
+    pub semantic_prompt: bool,
+    pub color_mode: ColorMode,
+    #[serde(skip_serializing)]
+    pub include: VecOr<String>,
+    pub trusted_project_configs: Vec<String>,
+    #[serde(skip_serializing)]
+    #[cfg_attr(
+        feature = "config-schema",
+        schemars(with = "Vec<serde_json::Map<String, serde_json::Value>>")
+    )]
+    pub when: Vec<toml::Table>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub palette: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_light: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_dark: Option<String>,
     pub palettes: HashMap<String, Palette>,
+    pub styles: HashMap<String, String>,
     #[serde(rename = "profiles")]
     #[cfg_attr(feature = "config-schema", schemars(default = "default_profiles"))]
-    pub user_profiles: IndexMap<String, String>,
+    pub user_profiles: IndexMap<String, Profile>,
     #[serde(skip)]
     pub internal_profiles: IndexMap<String, String>,
+    #[cfg_attr(
+        feature = "config-schema",
+        schemars(with = "Vec<serde_json::Map<String, serde_json::Value>>")
+    )]
+    pub profile_rules: Vec<toml::Table>,
 }
 
 pub type Palette = HashMap<String, String>;
 
+/// The colors supported by the terminal, detected from the environment with `auto`
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+    None,
+}
+
+/// A profile of `[profiles]`, either just the format of the prompt or a table of root
+/// options and module overrides, which may `extends` another profile
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum Profile {
+    Format(String),
+    Config(
+        #[cfg_attr(
+            feature = "config-schema",
+            schemars(with = "serde_json::Map<String, serde_json::Value>")
+        )]
+        toml::Table,
+    ),
+}
+
 // List of default prompt order
 // NOTE: If this const value is changed then Default prompt order subheading inside
 // prompt heading of config docs needs to be updated according to changes made here.
@@ -159,12 +215,24 @@ impl Default for StarshipRootConfig {
             continuation_prompt: "[∙](bright-black) ".to_string(),
             user_profiles: IndexMap::new(),
             internal_profiles: default_profiles(),
+            profile_rules: Vec::new(),
             scan_timeout: 30,
             command_timeout: 500,
+            render_timeout: 0,
+            render_timeout_placeholder: "[…](bright-black) ".to_string(),
             add_newline: true,
             follow_symlinks: true,
+            version_cache: false,
+            semantic_prompt: false,
+            color_mode: ColorMode::Auto,
+            include: VecOr::default(),
+            trusted_project_configs: Vec::new(),
+            when: Vec::new(),
             palette: None,
+            palette_light: None,
+            palette_dark: None,
             palettes: HashMap::default(),
+            styles: HashMap::default(),
         }
     }
 }
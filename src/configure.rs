@@ -1,15 +1,20 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Write as _;
 use std::process;
 use std::process::Stdio;
-use std::str::FromStr;
 
 use crate::config::ModuleConfig;
-use crate::config::StarshipConfig;
+use crate::config::{
+    StarshipConfig, expand_style_classes, find_project_config, is_style_option, parse_config_value,
+};
 use crate::configs::PROMPT_ORDER;
 use crate::context::Context;
+use crate::print::Preset;
+use crate::shadow;
 use crate::utils;
 use std::path::PathBuf;
-use toml_edit::DocumentMut;
+use toml_edit::{DocumentMut, Item, Key, Table, TableLike};
 
 #[cfg(not(windows))]
 const STD_EDITOR: &str = "vi";
@@ -28,7 +33,7 @@ pub fn update_configuration(context: &Context, name: &str, value: &str) {
     }
 }
 
-fn handle_update_configuration(
+pub(crate) fn handle_update_configuration(
     doc: &mut DocumentMut,
     name: &str,
     value: &str,
@@ -61,8 +66,7 @@ fn handle_update_configuration(
         current_item = table.get_mut(key).unwrap();
     }
 
-    let mut new_value = toml_edit::Value::from_str(value)
-        .map_or_else(|_| toml_edit::value(value), toml_edit::Item::Value);
+    let mut new_value = toml_edit::Item::Value(parse_config_value(value));
 
     if let Some(value) = current_item.as_value() {
         *new_value.as_value_mut().unwrap().decor_mut() = value.decor().clone();
@@ -74,22 +78,19 @@ fn handle_update_configuration(
 }
 
 pub fn print_configuration(context: &Context, use_default: bool, paths: &[String]) -> String {
+    let starship_config = &context.config;
     let config = if use_default {
-        // Get default config
-        let default_config = crate::configs::FullConfig::default();
-        // Convert back to Value because toml can't serialize FullConfig directly
-        toml::value::Value::try_from(default_config).unwrap()
+        default_configuration()
     } else {
-        // Get config as toml::Value
-        let user_config = get_configuration(context);
-        // Convert into FullConfig and fill in default values
-        let user_config = crate::configs::FullConfig::load(&user_config);
-        // Convert back to Value because toml can't serialize FullConfig directly
-        toml::value::Value::try_from(user_config).unwrap()
+        effective_configuration(starship_config)
     };
 
     println!("# Warning: This config does not include keys that have an unset value\n");
 
+    if !use_default {
+        print_config_files(context, starship_config);
+    }
+
     // These are only used for format specifiers so don't print them if we aren't showing formats.
     if paths.is_empty()
         || paths
@@ -119,18 +120,246 @@ pub fn print_configuration(context: &Context, use_default: bool, paths: &[String
         }
     }
 
+    let styles = config
+        .get("styles")
+        .and_then(toml::Value::as_table)
+        .map(|styles| {
+            styles
+                .iter()
+                .filter_map(|(name, style)| Some((name.clone(), style.as_str()?.to_owned())))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+
     let print_config = if paths.is_empty() {
         config
     } else {
         extract_toml_paths(config, paths)
     };
 
-    let string_config = toml::to_string_pretty(&print_config).unwrap();
+    let mut string_config = toml::to_string_pretty(&print_config).unwrap();
+    if !use_default && (shows_origins(starship_config) || !styles.is_empty()) {
+        string_config = annotate_values(&string_config, starship_config, &styles);
+    }
 
     println!("{string_config}");
     string_config
 }
 
+/// Prints the value of a single key, with default values filled in
+pub fn print_configuration_value(context: &Context, name: &str) {
+    let config = effective_configuration(&context.config);
+
+    match format_configuration_value(&config, name) {
+        Ok(value) => println!("{value}"),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+}
+
+fn format_configuration_value(config: &toml::Value, name: &str) -> Result<String, String> {
+    let value = name
+        .split('.')
+        .try_fold(config, |value, key| value.get(key))
+        .ok_or_else(|| format!("Given config key '{name}' does not exist"))?;
+
+    Ok(match value {
+        // Print strings without quotes so they can be used in scripts
+        toml::Value::String(string) => string.clone(),
+        toml::Value::Table(table) => toml::to_string_pretty(table).unwrap(),
+        value => value.to_string(),
+    })
+}
+
+/// Prints every key of the config as `path = value`, optionally only those that differ from the defaults
+pub fn list_configuration(context: &Context, changed: bool) -> String {
+    let starship_config = &context.config;
+    let config = effective_configuration(starship_config);
+    let default_config = changed.then(|| {
+        let mut default_config = default_configuration();
+        add_named_module_defaults(&config, &mut default_config);
+        default_config
+    });
+
+    let mut entries = Vec::new();
+    flatten_configuration(
+        &config,
+        default_config.as_ref(),
+        &mut Vec::new(),
+        &mut entries,
+    );
+
+    let show_origins = shows_origins(starship_config);
+    let list = entries
+        .into_iter()
+        .fold(String::new(), |mut output, (path, value)| {
+            let key = path
+                .iter()
+                .map(|key| {
+                    toml_edit::Key::new(key.as_str())
+                        .display_repr()
+                        .into_owned()
+                })
+                .collect::<Vec<_>>()
+                .join(".");
+            let _ = write!(output, "{key} = {value}");
+            if let Some(origin) = show_origins
+                .then(|| starship_config.origin(&path))
+                .flatten()
+            {
+                let _ = write!(output, " # {origin}");
+            }
+            output.push('\n');
+            output
+        });
+
+    print!("{list}");
+    list
+}
+
+fn flatten_configuration(
+    value: &toml::Value,
+    default: Option<&toml::Value>,
+    path: &mut Vec<String>,
+    entries: &mut Vec<(Vec<String>, toml::Value)>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key.clone());
+                // Keys without a default are always listed
+                let default = default.and_then(|default| default.get(key));
+                flatten_configuration(value, default, path, entries);
+                path.pop();
+            }
+        }
+        value if default != Some(value) => entries.push((path.clone(), value.clone())),
+        _ => {}
+    }
+}
+
+/// Adds the defaults of the user's `custom` and `env_var` modules, which have none in `FullConfig`
+fn add_named_module_defaults(config: &toml::Value, default_config: &mut toml::Value) {
+    let named_modules = [
+        (
+            "custom",
+            toml::Value::try_from(crate::configs::custom::CustomConfig::default()).unwrap(),
+        ),
+        (
+            "env_var",
+            toml::Value::try_from(crate::configs::env_var::EnvVarConfig::default()).unwrap(),
+        ),
+    ];
+
+    for (module, module_default) in named_modules {
+        let (Some(modules), Some(default_modules)) = (
+            config.get(module).and_then(toml::Value::as_table),
+            default_config
+                .get_mut(module)
+                .and_then(toml::Value::as_table_mut),
+        ) else {
+            continue;
+        };
+        for name in modules.keys() {
+            default_modules
+                .entry(name)
+                .or_insert_with(|| module_default.clone());
+        }
+    }
+}
+
+/// The config of all modules, with the user's values and defaults for the rest
+pub(crate) fn effective_configuration(starship_config: &StarshipConfig) -> toml::Value {
+    // Get config as toml::Value
+    let user_config = starship_config.config.clone().unwrap_or_default();
+    // Convert into FullConfig and fill in default values
+    let user_config = crate::configs::FullConfig::load(&user_config);
+    // Convert back to Value because toml can't serialize FullConfig directly
+    toml::value::Value::try_from(user_config).unwrap()
+}
+
+fn default_configuration() -> toml::Value {
+    toml::value::Value::try_from(crate::configs::FullConfig::default()).unwrap()
+}
+
+/// Origins are only worth printing if the config doesn't come from a single file
+fn shows_origins(starship_config: &StarshipConfig) -> bool {
+    starship_config.files.len() > 1 || !starship_config.env_overrides.is_empty()
+}
+
+/// Lists the files and environment variables the config was read from,
+/// and a project config that was skipped
+fn print_config_files(context: &Context, starship_config: &StarshipConfig) {
+    for file in &starship_config.files {
+        println!("# Loaded {}", file.display());
+    }
+    let mut env_overrides = starship_config.env_overrides.values().collect::<Vec<_>>();
+    env_overrides.sort();
+    for name in env_overrides {
+        println!("# Overridden by ${name}");
+    }
+
+    if let Some(project_config) = find_project_config(&context.current_dir)
+        && !starship_config.files.contains(&project_config)
+    {
+        println!(
+            "# Ignored {}, add its directory to `trusted_project_configs` to load it",
+            project_config.display()
+        );
+    }
+    println!();
+}
+
+/// Adds a comment to each value of the printed config with the file it was read from,
+/// and with the style it resolves to if it uses classes of `[styles]`
+fn annotate_values(
+    string_config: &str,
+    starship_config: &StarshipConfig,
+    styles: &HashMap<String, String>,
+) -> String {
+    fn annotate(
+        table: &mut dyn TableLike,
+        path: &mut Vec<String>,
+        config: &StarshipConfig,
+        styles: &HashMap<String, String>,
+    ) {
+        for (key, item) in table.iter_mut() {
+            path.push(key.get().to_string());
+            if let Some(table) = item.as_table_like_mut() {
+                annotate(table, path, config, styles);
+            } else if let Some(value) = item.as_value_mut() {
+                let mut comments = Vec::new();
+                if shows_origins(config)
+                    && let Some(origin) = config.origin(path)
+                {
+                    comments.push(origin);
+                }
+                let keys = path.iter().map(String::as_str).collect::<Vec<_>>();
+                if let Some(style) = value.as_str()
+                    && is_style_option(&keys)
+                    && let (Cow::Owned(resolved), _) = expand_style_classes(style, styles)
+                {
+                    comments.push(format!("resolves to '{resolved}'"));
+                }
+                if !comments.is_empty() {
+                    value
+                        .decor_mut()
+                        .set_suffix(format!(" # {}", comments.join(", ")));
+                }
+            }
+            path.pop();
+        }
+    }
+
+    let Ok(mut doc) = string_config.parse::<DocumentMut>() else {
+        return string_config.to_string();
+    };
+    annotate(doc.as_table_mut(), &mut Vec::new(), starship_config, styles);
+    doc.to_string()
+}
+
 fn extract_toml_paths(mut config: toml::Value, paths: &[String]) -> toml::Value {
     // Extract all the requested sections into a new configuration.
     let mut subset = toml::value::Table::new();
@@ -222,10 +451,139 @@ fn handle_toggle_configuration(doc: &mut DocumentMut, name: &str, key: &str) ->
     Ok(())
 }
 
-pub fn get_configuration(context: &Context) -> toml::Table {
-    let starship_config = StarshipConfig::initialize(context.get_config_path_os().as_deref());
+pub fn unset_configuration(context: &Context, name: &str) {
+    let mut doc = get_configuration_edit(context);
+
+    match handle_unset_configuration(&mut doc, name) {
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        _ => write_configuration(context, &doc),
+    }
+}
+
+fn handle_unset_configuration(doc: &mut DocumentMut, name: &str) -> Result<(), String> {
+    let keys = name.split('.').collect::<Vec<_>>();
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err("Empty table keys are not supported".to_owned());
+    }
 
-    starship_config.config.unwrap_or_default()
+    let (last_key, parents) = keys.split_last().unwrap();
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for key in parents {
+        table = table
+            .get_mut(key)
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .ok_or_else(|| format!("Given config key '{name}' not found in config file"))?;
+    }
+
+    table
+        .remove(last_key)
+        .map(|_| ())
+        .ok_or_else(|| format!("Given config key '{name}' not found in config file"))
+}
+
+/// Writes a preset to the configuration file. With `merge`, the options of the preset are
+/// added to the existing configuration, which keeps the options it already sets.
+pub fn apply_preset(context: &Context, preset: &Preset, merge: bool, force: bool) {
+    let Some(config_path) = context.get_config_path_os() else {
+        eprintln!("config path required to write configuration");
+        process::exit(1);
+    };
+    let config_path = PathBuf::from(config_path);
+    let content = shadow::get_preset_content(preset.0);
+
+    if config_path.exists() && merge {
+        let mut doc = get_configuration_edit(context);
+        let preset = content
+            .parse::<DocumentMut>()
+            .expect("Failed to parse the preset");
+        merge_preset(&mut doc, &preset);
+        write_configuration(context, &doc);
+    } else if config_path.exists() && !force {
+        eprintln!(
+            "The configuration file {} already exists, use --merge to merge the preset into it or --force to replace it",
+            config_path.display()
+        );
+        process::exit(1);
+    } else if let Err(e) = utils::write_file_atomic(&config_path, content, true) {
+        eprintln!("Unable to write configuration: {e}");
+        process::exit(1);
+    }
+}
+
+/// Deep-merges a preset into the configuration, keeping the values the configuration
+/// already has. Tables which are new to the configuration are added after its own tables.
+fn merge_preset(doc: &mut DocumentMut, preset: &DocumentMut) {
+    let mut position = last_table_position(doc.as_table()) + 1;
+    merge_preset_table(doc.as_table_mut(), preset.as_table(), &mut position);
+}
+
+fn merge_preset_table(config: &mut dyn TableLike, preset: &dyn TableLike, position: &mut isize) {
+    for (key, item) in preset.iter() {
+        match config.get_mut(key) {
+            Some(existing) => {
+                if let (Some(existing), Some(item)) =
+                    (existing.as_table_like_mut(), item.as_table_like())
+                {
+                    merge_preset_table(existing, item, position);
+                }
+            }
+            None => {
+                let mut item = item.clone();
+                renumber_tables(&mut item, position);
+                // Keep the formatting of the key as well
+                let key = preset
+                    .get_key_value(key)
+                    .map_or_else(|| Key::new(key), |(key, _)| key.clone());
+                config.entry_format(&key).or_insert(item);
+            }
+        }
+    }
+}
+
+/// The highest position of a table in the document
+fn last_table_position(table: &Table) -> isize {
+    table
+        .iter()
+        .filter_map(|(_, item)| match item {
+            Item::Table(table) => {
+                Some(last_table_position(table).max(table.position().unwrap_or(0)))
+            }
+            Item::ArrayOfTables(array) => array
+                .iter()
+                .map(|table| last_table_position(table).max(table.position().unwrap_or(0)))
+                .max(),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Assigns increasing positions to the tables of an item, so that they are printed in order
+fn renumber_tables(item: &mut Item, position: &mut isize) {
+    match item {
+        Item::Table(table) => renumber_table(table, position),
+        Item::ArrayOfTables(array) => {
+            for table in array.iter_mut() {
+                renumber_table(table, position);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn renumber_table(table: &mut Table, position: &mut isize) {
+    table.set_position(Some(*position));
+    *position += 1;
+    for (_, item) in table.iter_mut() {
+        renumber_tables(item, position);
+    }
+}
+
+pub fn get_configuration(context: &Context) -> toml::Table {
+    context.config.config.clone().unwrap_or_default()
 }
 
 pub fn get_configuration_edit(context: &Context) -> DocumentMut {
@@ -582,6 +940,100 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unset_config_simple() {
+        let mut doc = create_doc();
+        doc["status"]["format"] = toml_edit::value("$status");
+
+        handle_unset_configuration(&mut doc, "status.format").unwrap();
+
+        assert_eq!(doc.to_string(), create_doc().to_string());
+    }
+
+    #[test]
+    fn test_unset_config_missing_key() {
+        let mut doc = create_doc();
+
+        assert!(handle_unset_configuration(&mut doc, "status.format").is_err());
+        assert!(handle_unset_configuration(&mut doc, "character.format").is_err());
+        assert!(handle_unset_configuration(&mut doc, "status.disabled.value").is_err());
+    }
+
+    #[test]
+    fn test_unset_config_empty() {
+        let mut doc = create_doc();
+
+        assert!(handle_unset_configuration(&mut doc, "").is_err());
+        assert!(handle_unset_configuration(&mut doc, "status.").is_err());
+        assert!(handle_unset_configuration(&mut doc, ".status").is_err());
+    }
+
+    #[test]
+    fn test_format_configuration_value() {
+        let config = toml::Value::Table(toml::toml! {
+            add_newline = false
+            [git_branch]
+            symbol = "X "
+            ignore_branches = ["main"]
+        });
+
+        assert_eq!(
+            format_configuration_value(&config, "git_branch.symbol").unwrap(),
+            "X "
+        );
+        assert_eq!(
+            format_configuration_value(&config, "git_branch.ignore_branches").unwrap(),
+            r#"["main"]"#
+        );
+        assert_eq!(
+            format_configuration_value(&config, "add_newline").unwrap(),
+            "false"
+        );
+        assert_eq!(
+            format_configuration_value(&config, "git_branch").unwrap(),
+            "symbol = \"X \"\nignore_branches = [\"main\"]\n"
+        );
+        assert!(format_configuration_value(&config, "git_branch.style").is_err());
+        assert!(format_configuration_value(&config, "add_newline.value").is_err());
+    }
+
+    #[test]
+    fn list_configuration_changed() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_path = dir.path().join("starship.toml");
+        std::fs::write(
+            &config_path,
+            concat!(
+                "add_newline = true\n",
+                "[git_branch]\n",
+                "symbol = 'X '\n",
+                "[custom.foo]\n",
+                "command = 'echo foo'\n",
+            ),
+        )?;
+        let mut env = Env::default();
+        env.insert("STARSHIP_CONFIG", config_path.to_string_lossy().to_string());
+        let context = Context::new_with_shell_and_path(
+            Properties::default(),
+            Shell::Unknown,
+            Target::Main,
+            PathBuf::default(),
+            PathBuf::default(),
+            env,
+        );
+
+        assert_eq!(
+            list_configuration(&context, true),
+            "git_branch.symbol = \"X \"\ncustom.foo.command = \"echo foo\"\n"
+        );
+
+        let list = list_configuration(&context, false);
+        assert!(list.contains("\nformat = \"$all\"\n"));
+        assert!(list.contains("\nadd_newline = true\n"));
+        assert!(list.contains("\ncustom.foo.style = \"green bold\"\n"));
+        dir.close()
+    }
+
     #[test]
     fn write_and_get_configuration_test() -> io::Result<()> {
         let dir = tempfile::tempdir()?;
@@ -693,4 +1145,55 @@ mod tests {
             env,
         ))
     }
+
+    #[test]
+    fn merge_preset_keeps_config_values() {
+        let mut doc = r#"# My config
+format = "$directory$character"
+
+[directory]
+style = "bold red" # mine
+
+[character]
+success_symbol = { symbol = ">" }
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let preset = r#"format = "$all"
+add_newline = false
+
+# Shorter paths
+[directory]
+style = "blue"
+truncation_length = 3
+
+[directory.substitutions]
+"Documents" = "D"
+
+[git_branch]
+symbol = "B "
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+
+        merge_preset(&mut doc, &preset);
+        let expected = r#"# My config
+format = "$directory$character"
+add_newline = false
+
+[directory]
+style = "bold red" # mine
+truncation_length = 3
+
+[character]
+success_symbol = { symbol = ">" }
+
+[directory.substitutions]
+"Documents" = "D"
+
+[git_branch]
+symbol = "B "
+"#;
+        assert_eq!(doc.to_string(), expected);
+    }
 }
@@ -0,0 +1,639 @@
+//! The interactive `starship configure` wizard, which walks through the modules of the prompt.
+
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use nu_ansi_term::Style;
+use toml_edit::DocumentMut;
+
+use crate::config::{ModuleConfig, StarshipConfig, get_palette};
+use crate::configs::{PROMPT_ORDER, StarshipRootConfig};
+use crate::configure::{
+    effective_configuration, get_configuration_edit, handle_update_configuration,
+    write_configuration,
+};
+use crate::context::{Context, Properties, Target};
+use crate::formatter::parse_rendered_variables;
+use crate::modules;
+use crate::print;
+use crate::shadow;
+
+/// Colors offered for styles after those of the palette
+const BASIC_COLORS: &[&str] = &[
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "purple",
+    "cyan",
+    "white",
+    "bright-black",
+];
+
+/// Words of a style string which aren't colors, and are kept when picking a color
+const STYLE_ATTRIBUTES: &[&str] = &[
+    "bold",
+    "italic",
+    "underline",
+    "dimmed",
+    "inverted",
+    "blink",
+    "hidden",
+    "strikethrough",
+];
+
+const HELP: &str = "Enter: next, p: previous, d: enable/disable, s: symbol, t: style, \
+<: move left, >: move right, w: save and quit, q: quit without saving";
+
+/// Walks through the modules of the prompt, and saves the changes to the configuration file
+/// while keeping its comments.
+pub fn run_wizard(context: &Context) {
+    let mut wizard = Wizard::new(get_configuration_edit(context));
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let saved = wizard.run(&mut stdin.lock(), &mut stdout.lock(), &preview_module);
+
+    match saved {
+        Ok(true) => {
+            write_configuration(context, &wizard.doc);
+            println!("Saved the configuration");
+        }
+        Ok(false) => println!("Discarded the changes"),
+        Err(e) => {
+            eprintln!("Unable to run the configuration wizard: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Renders a module in the current directory with the given config
+fn preview_module(config: &toml::Table, module: &str) -> Option<String> {
+    let config = StarshipConfig {
+        config: Some(config.clone()),
+        ..Default::default()
+    };
+    let context = Context::new_with_config(Properties::default(), Target::Main, config);
+    print::get_module(module, &context).filter(|module| !module.is_empty())
+}
+
+struct Wizard {
+    doc: DocumentMut,
+    /// The `format` of the prompt, as it was in the file
+    format: String,
+    /// The modules of the prompt, in the order of `format`
+    order: Vec<FormatModule>,
+    index: usize,
+    reordered: bool,
+}
+
+/// A module of the prompt's format
+#[derive(Debug, PartialEq)]
+struct FormatModule {
+    name: String,
+    /// Where the variable of the module is in the format, or `None` for modules of `$all`
+    token: Option<Range<usize>>,
+}
+
+impl Wizard {
+    fn new(doc: DocumentMut) -> Self {
+        let format = doc
+            .get("format")
+            .and_then(|item| item.as_str())
+            .unwrap_or("$all")
+            .to_string();
+        let order = format_modules(&format);
+        let index = order
+            .iter()
+            .position(|module| is_walkable(&module.name))
+            .unwrap_or(0);
+        Self {
+            doc,
+            format,
+            order,
+            index,
+            reordered: false,
+        }
+    }
+
+    /// Shows the modules one by one and applies the commands read from `input`.
+    /// Returns whether the changes should be saved.
+    fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        preview: &dyn Fn(&toml::Table, &str) -> Option<String>,
+    ) -> io::Result<bool> {
+        if !self.order.iter().any(|module| is_walkable(&module.name)) {
+            writeln!(
+                output,
+                "The format of the prompt doesn't contain any modules"
+            )?;
+            return Ok(false);
+        }
+        writeln!(output, "{HELP}")?;
+
+        loop {
+            let config = self.config();
+            self.show_module(output, &config, preview)?;
+            let Some(command) = read_line(input, output, "> ")? else {
+                return Ok(false);
+            };
+
+            match command.trim() {
+                "" | "n" => {
+                    if !self.step(1) {
+                        writeln!(output, "This is the last module, use w to save")?;
+                    }
+                }
+                "p" => {
+                    self.step(-1);
+                }
+                "d" => self.toggle(output, &config)?,
+                "s" => self.pick_symbol(input, output, &config)?,
+                "t" => self.pick_style(input, output, &config)?,
+                "<" => self.move_module(input, output, -1)?,
+                ">" => self.move_module(input, output, 1)?,
+                "w" => {
+                    self.update_format(output)?;
+                    return Ok(true);
+                }
+                "q" => return Ok(false),
+                other => writeln!(output, "Unknown command '{other}'. {HELP}")?,
+            }
+        }
+    }
+
+    fn module(&self) -> &str {
+        &self.order[self.index].name
+    }
+
+    /// The configuration being edited
+    fn config(&self) -> toml::Table {
+        self.doc.to_string().parse().unwrap_or_default()
+    }
+
+    /// The options of the current module, including the defaults
+    fn module_options(&self, config: &toml::Table) -> toml::Table {
+        let config = StarshipConfig {
+            config: Some(config.clone()),
+            ..Default::default()
+        };
+        effective_configuration(&config)
+            .get(self.module())
+            .and_then(toml::Value::as_table)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn show_module<W: Write>(
+        &self,
+        output: &mut W,
+        config: &toml::Table,
+        preview: &dyn Fn(&toml::Table, &str) -> Option<String>,
+    ) -> io::Result<()> {
+        let name = self.module();
+        let walkable = self.order.iter().filter(|module| is_walkable(&module.name));
+        let position = walkable
+            .clone()
+            .take_while(|other| other.name != name)
+            .count()
+            + 1;
+        writeln!(output)?;
+        writeln!(
+            output,
+            "[{position}/{}] {}: {}",
+            walkable.count(),
+            Style::new().bold().paint(name),
+            modules::description(name)
+        )?;
+
+        let options = self.module_options(config);
+        let disabled = is_disabled(&options);
+        match (!disabled).then(|| preview(config, name)) {
+            None => writeln!(output, "  (disabled)")?,
+            Some(Some(rendered)) => writeln!(output, "  {rendered}")?,
+            Some(None) => writeln!(output, "  (not shown in the current directory)")?,
+        }
+
+        let mut details = vec![if disabled { "disabled" } else { "enabled" }.to_string()];
+        for option in ["symbol", "style"] {
+            if let Some(value) = options.get(option).and_then(toml::Value::as_str) {
+                details.push(format!("{option} '{value}'"));
+            }
+        }
+        writeln!(output, "  {}", details.join(", "))
+    }
+
+    /// Moves to the next walkable module in `direction`. Returns false if there is none.
+    fn step(&mut self, direction: isize) -> bool {
+        let mut index = self.index;
+        loop {
+            match index.checked_add_signed(direction) {
+                Some(next) if next < self.order.len() => index = next,
+                _ => return false,
+            }
+            if is_walkable(&self.order[index].name) {
+                self.index = index;
+                return true;
+            }
+        }
+    }
+
+    fn toggle<W: Write>(&mut self, output: &mut W, config: &toml::Table) -> io::Result<()> {
+        let disabled = !is_disabled(&self.module_options(config));
+        let name = format!("{}.disabled", self.module());
+        self.set(output, &name, &disabled.to_string())
+    }
+
+    fn pick_symbol<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        config: &toml::Table,
+    ) -> io::Result<()> {
+        if !self.module_options(config).contains_key("symbol") {
+            return writeln!(output, "This module has no symbol");
+        }
+        let choices = preset_symbols(self.module());
+        self.pick(input, output, "symbol", &choices)
+    }
+
+    fn pick_style<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        config: &toml::Table,
+    ) -> io::Result<()> {
+        let options = self.module_options(config);
+        let Some(style) = options.get("style").and_then(toml::Value::as_str) else {
+            return writeln!(output, "This module has no style");
+        };
+        // Keep the attributes of the current style, like `bold`
+        let attributes = style
+            .split_whitespace()
+            .filter(|word| STYLE_ATTRIBUTES.contains(&word.to_lowercase().as_str()))
+            .collect::<Vec<_>>();
+
+        let root = StarshipRootConfig::load(config);
+        let mut colors = get_palette(&root.palettes, root.palette.as_deref())
+            .map(|palette| palette.into_keys().collect::<Vec<_>>())
+            .unwrap_or_default();
+        colors.sort();
+        colors.extend(BASIC_COLORS.iter().map(ToString::to_string));
+
+        let choices = colors
+            .into_iter()
+            .map(|color| {
+                attributes
+                    .iter()
+                    .copied()
+                    .chain([color.as_str()])
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        self.pick(input, output, "style", &choices)
+    }
+
+    /// Lets the user pick one of `choices` by its number, or type any other value
+    fn pick<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        option: &str,
+        choices: &[String],
+    ) -> io::Result<()> {
+        for (number, choice) in choices.iter().enumerate() {
+            writeln!(output, "  {}) '{choice}'", number + 1)?;
+        }
+        let prompt = format!("New {option} (a number or a value, empty to keep it): ");
+        let Some(answer) = read_line(input, output, &prompt)? else {
+            return Ok(());
+        };
+        let value = match answer.trim().parse::<usize>() {
+            Ok(number) if (1..=choices.len()).contains(&number) => choices[number - 1].clone(),
+            _ if answer.is_empty() => return Ok(()),
+            _ => answer,
+        };
+
+        let name = format!("{}.{option}", self.module());
+        self.set(output, &name, &toml_edit::Value::from(value).to_string())
+    }
+
+    /// Swaps the current module with its neighbor in the format. Modules of `$all` can only
+    /// be moved once `$all` is replaced by their variables, which the user is asked about.
+    fn move_module<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        direction: isize,
+    ) -> io::Result<()> {
+        let Some(other) = self
+            .index
+            .checked_add_signed(direction)
+            .filter(|other| *other < self.order.len())
+        else {
+            return Ok(());
+        };
+        if self.order[self.index].token.is_none() || self.order[other].token.is_none() {
+            let prompt = "Modules of `$all` can't be moved. Replace `$all` with the variables \
+of its modules in `format`? [y/N] ";
+            let answer = read_line(input, output, prompt)?.unwrap_or_default();
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                return Ok(());
+            }
+            // The modules keep their positions, so `other` is still the neighbor
+            self.expand_all();
+        }
+        self.order.swap(self.index, other);
+        self.index = other;
+        self.reordered = true;
+        Ok(())
+    }
+
+    /// Replaces `$all` in the format with the variables of the modules it stands for,
+    /// keeping the modules which were moved so far and the current one.
+    fn expand_all(&mut self) {
+        let mut format = self.reordered_format();
+        let variables = parse_rendered_variables(&format).unwrap_or_default();
+        let all = variables
+            .iter()
+            .filter(|(variable, _)| *variable == "all")
+            .map(|(_, token)| token.clone())
+            .collect::<Vec<_>>();
+        let expanded = PROMPT_ORDER
+            .iter()
+            .filter(|module| !variables.iter().any(|(variable, _)| variable == *module))
+            .map(|module| format!("${module}"))
+            .collect::<String>();
+        // Replace from the end, so the earlier ranges stay valid
+        for token in all.into_iter().rev() {
+            format.replace_range(token, &expanded);
+        }
+
+        let name = self.module().to_string();
+        self.order = format_modules(&format);
+        self.index = self
+            .order
+            .iter()
+            .position(|module| module.name == name)
+            .unwrap_or(0);
+        self.format = format;
+        self.reordered = true;
+    }
+
+    /// The format with the variables of the modules in their current order. Only the
+    /// variables are swapped, so any text, groups and styles around them are kept.
+    fn reordered_format(&self) -> String {
+        let mut slots = self
+            .order
+            .iter()
+            .filter_map(|module| module.token.clone())
+            .collect::<Vec<_>>();
+        slots.sort_by_key(|slot| slot.start);
+
+        let mut format = String::new();
+        let mut end = 0;
+        for (slot, module) in slots
+            .iter()
+            .zip(self.order.iter().filter_map(|module| module.token.as_ref()))
+        {
+            format.push_str(&self.format[end..slot.start]);
+            format.push_str(&self.format[module.clone()]);
+            end = slot.end;
+        }
+        format.push_str(&self.format[end..]);
+        format
+    }
+
+    /// Writes the order of the modules to `format`, if they were moved
+    fn update_format<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        if !self.reordered {
+            return Ok(());
+        }
+        let format = self.reordered_format();
+        self.set(
+            output,
+            "format",
+            &toml_edit::Value::from(format).to_string(),
+        )
+    }
+
+    /// Edits an option of the configuration, and tells the user if that isn't possible
+    fn set<W: Write>(&mut self, output: &mut W, name: &str, value: &str) -> io::Result<()> {
+        match handle_update_configuration(&mut self.doc, name, value) {
+            Ok(()) => Ok(()),
+            Err(e) => writeln!(output, "Unable to set {name}: {e}"),
+        }
+    }
+}
+
+/// The modules of a format string in order, with `$all` expanded to the modules
+/// it stands for. A module which is used more than once is listed where it first appears.
+fn format_modules(format: &str) -> Vec<FormatModule> {
+    let variables = parse_rendered_variables(format).unwrap_or_default();
+    let mut modules = Vec::<FormatModule>::new();
+    for (variable, token) in &variables {
+        if *variable == "all" {
+            modules.extend(
+                PROMPT_ORDER
+                    .iter()
+                    .filter(|module| !variables.iter().any(|(variable, _)| variable == *module))
+                    .map(|module| FormatModule {
+                        name: module.to_string(),
+                        token: None,
+                    }),
+            );
+        } else if !modules.iter().any(|module| module.name == *variable) {
+            modules.push(FormatModule {
+                name: variable.to_string(),
+                token: Some(token.clone()),
+            });
+        }
+    }
+    modules
+}
+
+/// Named modules like `custom.foo` are configured in the file
+fn is_walkable(module: &str) -> bool {
+    !module.contains('.') && module != "custom" && module != "env_var"
+}
+
+fn is_disabled(options: &toml::Table) -> bool {
+    options
+        .get("disabled")
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// The symbols the presets use for a module
+fn preset_symbols(module: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
+    for preset in shadow::get_preset_list() {
+        let Ok(config) = toml::from_str::<toml::Table>(shadow::get_preset_content(preset.0)) else {
+            continue;
+        };
+        if let Some(symbol) = config
+            .get(module)
+            .and_then(|module| module.get("symbol"))
+            .and_then(toml::Value::as_str)
+            && !symbols.iter().any(|other| other == symbol)
+        {
+            symbols.push(symbol.to_string());
+        }
+    }
+    symbols
+}
+
+/// Reads a line without its line ending, or `None` at the end of the input
+fn read_line<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    prompt: &str,
+) -> io::Result<Option<String>> {
+    write!(output, "{prompt}")?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    Ok(Some(line.strip_suffix('\r').unwrap_or(line).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# My prompt
+format = "$directory$git_branch$character"
+
+[git_branch]
+# Show the branch in purple
+style = "bold purple"
+"#;
+
+    fn run(input: &str) -> (bool, String) {
+        let mut wizard = Wizard::new(CONFIG.parse().unwrap());
+        let mut output = Vec::new();
+        let saved = wizard
+            .run(&mut input.as_bytes(), &mut output, &|_, module| {
+                Some(format!("<{module}>"))
+            })
+            .unwrap();
+        (saved, wizard.doc.to_string())
+    }
+
+    fn run_with_format(format: &str, input: &str) -> String {
+        let mut doc = DocumentMut::new();
+        doc["format"] = toml_edit::value(format);
+        let mut wizard = Wizard::new(doc);
+        let saved = wizard
+            .run(&mut input.as_bytes(), &mut Vec::new(), &|_, _| None)
+            .unwrap();
+        assert!(saved);
+        wizard.doc["format"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn modules_of_the_format() {
+        let names = |format| {
+            format_modules(format)
+                .into_iter()
+                .map(|module| module.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("$directory${custom.foo}[$git_branch](bold $style)$directory"),
+            ["directory", "custom.foo", "git_branch"]
+        );
+        let all = format_modules("$directory$all");
+        assert_eq!(all[0].token, Some(0..10));
+        assert_eq!(all[1].token, None);
+        assert_eq!(all.len(), PROMPT_ORDER.len());
+    }
+
+    #[test]
+    fn edit_modules() {
+        // Disable the directory, then pick a style and type a symbol for the branch
+        let (saved, config) = run("d\n\nt\n1\ns\n🌿 \nw\n");
+        assert!(saved);
+        let expected = r#"# My prompt
+format = "$directory$git_branch$character"
+
+[git_branch]
+# Show the branch in purple
+style = "bold red"
+symbol = "🌿 "
+
+[directory]
+disabled = true
+"#;
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn reorder_modules() {
+        let (saved, config) = run(">\nw\n");
+        assert!(saved);
+        assert!(config.starts_with("# My prompt\nformat = \"$git_branch$directory$character\"\n"));
+    }
+
+    #[test]
+    fn reorder_keeps_the_format() {
+        let format = "[┌](bold green)$directory( on [$git_branch|upper](purple))\n\\$\n$character";
+        // Move the branch before the directory
+        assert_eq!(
+            run_with_format(format, "\n<\nw\n"),
+            "[┌](bold green)$git_branch|upper( on [$directory](purple))\n\\$\n$character"
+        );
+        assert_eq!(run_with_format("$all", ">\nn\nw\n"), "$all");
+        assert_eq!(
+            run_with_format("$username$all$character", ">\n\n<\nn\nw\n"),
+            "$username$all$character"
+        );
+    }
+
+    #[test]
+    fn reorder_expands_all() {
+        let all = PROMPT_ORDER
+            .iter()
+            .filter(|module| !["username", "character"].contains(module))
+            .collect::<Vec<_>>();
+        // Move the username after the first module of `$all`
+        let format = run_with_format("$username$all$character", ">\ny\nw\n");
+        let expected = format!(
+            "${}$username{}$character",
+            all[0],
+            all[1..]
+                .iter()
+                .map(|module| format!("${module}"))
+                .collect::<String>()
+        );
+        assert_eq!(format, expected);
+    }
+
+    #[test]
+    fn report_failed_edits() {
+        let mut wizard = Wizard::new(
+            "format = '$directory'\ndirectory = 'short'\n"
+                .parse()
+                .unwrap(),
+        );
+        let mut output = Vec::new();
+        wizard
+            .run(&mut "d\nq\n".as_bytes(), &mut output, &|_, _| None)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "Unable to set directory.disabled: This command can only index into TOML tables"
+        ));
+    }
+
+    #[test]
+    fn quit_without_saving() {
+        assert!(!run("d\nq\n").0);
+        // The end of the input quits as well
+        assert!(!run("d\n").0);
+    }
+}
@@ -3,7 +3,9 @@ use std::fmt::Debug;
 use std::path::PathBuf;
 use std::time::Duration;
 
-use gix::{Repository, ThreadSafeRepository, state as git_state};
+use gix::remote::Direction;
+use gix::url::Scheme;
+use gix::{Repository, ThreadSafeRepository, Url, state as git_state};
 
 use crate::utils::{CommandOutput, create_command, exec_timeout};
 
@@ -87,4 +89,70 @@ impl GitRepo {
             Duration::from_millis(context.root_config.command_timeout),
         )
     }
+
+    /// Returns the web URL of the remote tracked by the current branch,
+    /// or of the default remote if the branch doesn't track one.
+    pub fn remote_web_url(&self) -> Option<String> {
+        let repo = self.open();
+        let remote_name = self
+            .remote
+            .as_ref()
+            .and_then(|remote| remote.name.as_deref());
+        let remote = match remote_name {
+            Some(name) => repo.find_remote(name).ok()?,
+            None => repo.find_default_remote(Direction::Fetch)?.ok()?,
+        };
+        web_url(remote.url(Direction::Fetch)?)
+    }
+}
+
+/// Derives the web URL of a repository from its remote URL, assuming the host serves the
+/// repository over HTTPS at the same path, e.g. `git@github.com:starship/starship.git`
+/// becomes `https://github.com/starship/starship`.
+fn web_url(url: &Url) -> Option<String> {
+    let host = url.host()?;
+    let port = match (&url.scheme, url.port) {
+        (Scheme::Http | Scheme::Https, Some(port)) => format!(":{port}"),
+        (Scheme::Http | Scheme::Https | Scheme::Ssh | Scheme::Git, _) => String::new(),
+        (Scheme::File | Scheme::Ext(_), _) => return None,
+    };
+    let scheme = if url.scheme == Scheme::Http {
+        "http"
+    } else {
+        "https"
+    };
+    let path = url.path.to_string();
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    Some(format!("{scheme}://{host}{port}/{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn web_url_of(url: &str) -> Option<String> {
+        web_url(&gix::url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn web_url_from_remote_url() {
+        let expected = Some("https://github.com/starship/starship".to_string());
+        assert_eq!(web_url_of("git@github.com:starship/starship.git"), expected);
+        assert_eq!(
+            web_url_of("ssh://git@github.com:22/starship/starship.git"),
+            expected
+        );
+        assert_eq!(web_url_of("https://github.com/starship/starship"), expected);
+        assert_eq!(
+            web_url_of("https://user@github.com/starship/starship.git/"),
+            expected
+        );
+        assert_eq!(
+            web_url_of("http://git.example.com:8080/repo.git"),
+            Some("http://git.example.com:8080/repo".to_string())
+        );
+        assert_eq!(web_url_of("/srv/git/repo.git"), None);
+        assert_eq!(web_url_of("file:///srv/git/repo.git"), None);
+    }
 }
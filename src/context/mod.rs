@@ -1,5 +1,6 @@
-use crate::config::{ModuleConfig, StarshipConfig};
-use crate::configs::StarshipRootConfig;
+use crate::cache::{self, VersionCache};
+use crate::config::{ColorLevel, ModuleConfig, StarshipConfig, Theme, WhenFacts, get_palette};
+use crate::configs::{Palette, StarshipRootConfig};
 use crate::module::Module;
 use crate::utils::{CommandOutput, PathExt, create_command, exec_timeout, read_file};
 
@@ -10,9 +11,8 @@ use gix::{
     Repository, ThreadSafeRepository,
     sec::{self as git_sec, trust::DefaultForLevel},
 };
-#[cfg(test)]
-use std::collections::HashMap;
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
@@ -56,17 +56,24 @@ pub struct Context<'a> {
     pub logical_dir: PathBuf,
 
     /// A struct containing directory contents in a lookup-optimized format.
-    dir_contents: OnceLock<Result<DirContents, std::io::Error>>,
+    dir_contents: OnceLock<Result<Arc<DirContents>, std::io::Error>>,
 
     /// Properties to provide to modules.
     pub properties: Properties,
 
     /// Private field to store Git information for modules who need it
-    git_repo: OnceLock<Result<GitRepo, Box<gix::discover::Error>>>,
+    git_repo: OnceLock<Result<Arc<GitRepo>, Box<gix::discover::Error>>>,
 
     /// Private field to store JJ information for modules who need it
     jj_repo: OnceLock<Option<JJRepo>>,
 
+    /// Private field to store the on-disk cache of version command outputs
+    version_cache: OnceLock<VersionCache>,
+
+    /// Outputs of commands to use instead of running them, for previews of the prompt.
+    /// Other commands fail without being run.
+    command_outputs: Option<HashMap<String, CommandOutput>>,
+
     /// The shell the user is assumed to be running
     pub shell: Shell,
 
@@ -93,6 +100,15 @@ pub struct Context<'a> {
     /// Starship root config
     pub root_config: StarshipRootConfig,
 
+    /// The colors of the palette selected by `palette`, resolved on first use
+    palette: OnceLock<Option<Palette>>,
+
+    /// The colors supported by the terminal, detected on first use
+    color_level: OnceLock<ColorLevel>,
+
+    /// When modules still running are abandoned, if the render has a `render_timeout`
+    render_deadline: OnceLock<Instant>,
+
     /// Claude Code session data (when running as statusline)
     pub claude_code_data: Option<Box<ClaudeCodeData>>,
 
@@ -105,6 +121,20 @@ impl<'a> Context<'a> {
     /// for it. "logical-path" is used when a shell allows the "current working directory"
     /// to be something other than a file system path (like powershell provider specific paths).
     pub fn new(arguments: Properties, target: Target) -> Self {
+        let env = Env::default();
+        let config = StarshipConfig::initialize(
+            get_config_path_os(&env).as_deref(),
+            get_config_cache_dir(&env),
+        );
+        Self::new_with_config(arguments, target, config)
+    }
+
+    /// Create an instance of Context like [`Context::new`], using an already loaded config.
+    pub(crate) fn new_with_config(
+        arguments: Properties,
+        target: Target,
+        config: StarshipConfig,
+    ) -> Self {
         let shell = Context::get_shell();
 
         // Retrieve the "current directory".
@@ -126,27 +156,51 @@ impl<'a> Context<'a> {
             .or_else(|| env::var("PWD").map(PathBuf::from).ok())
             .unwrap_or_else(|| path.clone());
 
-        Self::new_with_shell_and_path(
+        Self::new_with_shell_path_and_config(
             arguments,
             shell,
             target,
             path,
             logical_path,
             Default::default(),
+            config,
         )
     }
 
     /// Create a new instance of Context for the provided directory
     pub fn new_with_shell_and_path(
-        mut properties: Properties,
+        properties: Properties,
         shell: Shell,
         target: Target,
         path: PathBuf,
         logical_path: PathBuf,
         env: Env<'a>,
     ) -> Self {
-        let config = StarshipConfig::initialize(get_config_path_os(&env).as_deref());
+        let config = StarshipConfig::initialize(
+            get_config_path_os(&env).as_deref(),
+            get_config_cache_dir(&env),
+        );
+        Self::new_with_shell_path_and_config(
+            properties,
+            shell,
+            target,
+            path,
+            logical_path,
+            env,
+            config,
+        )
+    }
 
+    /// Create an instance of Context for the provided directory, using an already loaded config.
+    pub(crate) fn new_with_shell_path_and_config(
+        mut properties: Properties,
+        shell: Shell,
+        target: Target,
+        path: PathBuf,
+        logical_path: PathBuf,
+        env: Env<'a>,
+        mut config: StarshipConfig,
+    ) -> Self {
         // If the vector is zero-length, we should pretend that we didn't get a
         // pipestatus at all (since this is the input `--pipestatus=""`)
         if properties
@@ -172,6 +226,18 @@ impl<'a> Context<'a> {
         let current_dir = dunce::canonicalize(&current_dir).unwrap_or(current_dir);
         let logical_dir = logical_path;
 
+        config.merge_project_config(&current_dir);
+        let facts = WhenFacts::new(shell, &current_dir, &logical_dir, &env);
+        config.apply_when_blocks(&facts);
+        // A profile chosen with `--profile` takes precedence over `[[profile_rules]]`
+        if properties.profile.is_none() {
+            properties.profile = config.match_profile_rules(&facts);
+        }
+        if let Some(profile) = &properties.profile {
+            config.apply_profile(profile);
+        }
+        config.apply_env_overrides(&env);
+
         let root_config = config
             .config
             .as_ref()
@@ -187,6 +253,8 @@ impl<'a> Context<'a> {
             dir_contents: OnceLock::new(),
             git_repo: OnceLock::new(),
             jj_repo: OnceLock::new(),
+            version_cache: OnceLock::new(),
+            command_outputs: None,
             shell,
             target,
             width,
@@ -198,6 +266,9 @@ impl<'a> Context<'a> {
             #[cfg(feature = "battery")]
             battery_info_provider: &crate::modules::BatteryInfoProviderImpl,
             root_config,
+            palette: OnceLock::new(),
+            color_level: OnceLock::new(),
+            render_deadline: OnceLock::new(),
             claude_code_data: None,
             _marker: PhantomData,
         }
@@ -206,12 +277,53 @@ impl<'a> Context<'a> {
     /// Sets the context config, overwriting the existing config
     pub fn set_config(mut self, config: toml::Table) -> Self {
         self.root_config = StarshipRootConfig::load(&config);
+        self.palette = OnceLock::new();
+        self.color_level = OnceLock::new();
         self.config = StarshipConfig {
             config: Some(config),
+            ..Default::default()
         };
         self
     }
 
+    /// Answers the commands run by modules with canned outputs instead of running them,
+    /// keyed by the command and its arguments like `node --version`
+    pub(crate) fn with_command_outputs(mut self, outputs: HashMap<String, CommandOutput>) -> Self {
+        self.command_outputs = Some(outputs);
+        self
+    }
+
+    /// Seeds the directory contents and git repository with values from an earlier render
+    /// of the same directory, so that they don't have to be computed again.
+    pub(crate) fn with_warm_state(
+        self,
+        dir_contents: Option<Arc<DirContents>>,
+        git_repo: Option<Arc<GitRepo>>,
+    ) -> Self {
+        if let Some(dir_contents) = dir_contents {
+            let _ = self.dir_contents.set(Ok(dir_contents));
+        }
+        if let Some(git_repo) = git_repo {
+            let _ = self.git_repo.set(Ok(git_repo));
+        }
+        self
+    }
+
+    /// Returns the directory contents if they were fully scanned during this render.
+    pub(crate) fn warm_dir_contents(&self) -> Option<Arc<DirContents>> {
+        self.dir_contents
+            .get()?
+            .as_ref()
+            .ok()
+            .filter(|dir_contents| dir_contents.complete)
+            .cloned()
+    }
+
+    /// Returns the git repository if it was discovered during this render.
+    pub(crate) fn warm_git_repo(&self) -> Option<Arc<GitRepo>> {
+        self.git_repo.get()?.as_ref().ok().cloned()
+    }
+
     /// Sets the Claude Code session data
     pub fn with_claude_code_data(mut self, data: ClaudeCodeData) -> Self {
         self.claude_code_data = Some(Box::new(data));
@@ -223,6 +335,55 @@ impl<'a> Context<'a> {
         home_dir(&self.env)
     }
 
+    /// Returns the colors of the palette selected by the `palette` option, or by
+    /// `palette_light` and `palette_dark` for the background of the terminal
+    pub fn palette(&self) -> Option<&Palette> {
+        self.palette
+            .get_or_init(|| {
+                let root_config = &self.root_config;
+                let themed = Theme::detect(self).and_then(|theme| match theme {
+                    Theme::Light => root_config.palette_light.as_deref(),
+                    Theme::Dark => root_config.palette_dark.as_deref(),
+                });
+                get_palette(
+                    &root_config.palettes,
+                    themed.or(root_config.palette.as_deref()),
+                )
+            })
+            .as_ref()
+    }
+
+    /// Abandons the modules which are still running at `deadline`: the commands they run
+    /// are stopped at the deadline, and no new commands are started after it.
+    pub(crate) fn set_render_deadline(&self, deadline: Instant) {
+        let _ = self.render_deadline.set(deadline);
+    }
+
+    /// Returns true if the render deadline has passed, and its modules were abandoned
+    pub(crate) fn is_abandoned(&self) -> bool {
+        self.render_deadline
+            .get()
+            .is_some_and(|deadline| Instant::now() >= *deadline)
+    }
+
+    /// Returns how long a command may run given its own `timeout`, without running past the
+    /// render deadline. `None` lets the command run until it finishes.
+    pub(crate) fn command_time_limit(&self, timeout: Option<Duration>) -> Option<Duration> {
+        let left = self
+            .render_deadline
+            .get()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match (timeout, left) {
+            (Some(timeout), Some(left)) => Some(timeout.min(left)),
+            (timeout, left) => timeout.or(left),
+        }
+    }
+
+    /// Returns the colors supported by the terminal, see [`ColorLevel::detect`]
+    pub fn color_level(&self) -> ColorLevel {
+        *self.color_level.get_or_init(|| ColorLevel::detect(self))
+    }
+
     // Retrieves a environment variable from the os or from a table if in testing mode
     #[inline]
     pub fn get_env<K: AsRef<str>>(&self, key: K) -> Option<String> {
@@ -262,6 +423,26 @@ impl<'a> Context<'a> {
         disabled == Some(true)
     }
 
+    /// Returns the `priority` of a module, used to decide which modules to drop first
+    /// when the prompt is wider than the terminal. Modules without one are never dropped.
+    pub fn get_module_priority(&self, name: &str) -> Option<i64> {
+        self.get_module_layout_option(name, "priority")
+    }
+
+    /// Returns true if the terminal is narrower than the `min_width` of a module.
+    pub fn is_terminal_too_narrow_for_module(&self, name: &str) -> bool {
+        self.width > 0
+            && self
+                .get_module_layout_option(name, "min_width")
+                .is_some_and(|min_width| (self.width as i64) < min_width)
+    }
+
+    /// Looks up an option shared by all modules, including `custom.<name>` and `env_var.<name>`
+    fn get_module_layout_option(&self, name: &str, option: &str) -> Option<i64> {
+        let path = name.split('.').chain([option]).collect::<Vec<_>>();
+        self.config.get_config(&path)?.as_integer()
+    }
+
     /// Returns true when a negated environment variable is defined in `env_vars` and is present
     fn has_negated_env_var(&self, env_vars: &'a [&'a str]) -> bool {
         env_vars
@@ -336,7 +517,7 @@ impl<'a> Context<'a> {
     /// Will lazily get repo root and branch when a module requests it.
     pub fn get_git_repo(&self) -> Result<&GitRepo, &gix::discover::Error> {
         self.git_repo
-            .get_or_init(|| -> Result<GitRepo, Box<gix::discover::Error>> {
+            .get_or_init(|| -> Result<Arc<GitRepo>, Box<gix::discover::Error>> {
                 // custom open options
                 let mut git_open_opts_map =
                     git_sec::trust::Mapping::<gix::open::Options>::default();
@@ -397,7 +578,7 @@ impl<'a> Context<'a> {
                     .boolean("core.fsmonitor")
                     .unwrap_or(false);
 
-                Ok(GitRepo {
+                Ok(Arc::new(GitRepo {
                     repo: shared_repo,
                     branch: branch.map(|b| b.shorten().to_string()),
                     workdir: repository.workdir().map(PathBuf::from),
@@ -405,10 +586,11 @@ impl<'a> Context<'a> {
                     state: repository.state(),
                     remote,
                     fs_monitor_value_is_true,
-                })
+                }))
             })
             .as_ref()
-            .map_err(std::convert::AsRef::as_ref)
+            .map(AsRef::as_ref)
+            .map_err(AsRef::as_ref)
     }
 
     /// Will lazily discover Jujutsu repo root when a module requests it.
@@ -430,8 +612,10 @@ impl<'a> Context<'a> {
                     Duration::from_millis(timeout),
                     self.root_config.follow_symlinks,
                 )
+                .map(Arc::new)
             })
             .as_ref()
+            .map(AsRef::as_ref)
     }
 
     fn get_shell() -> Shell {
@@ -480,12 +664,38 @@ impl<'a> Context<'a> {
                 return output;
             }
         }
+        if let Some(outputs) = &self.command_outputs {
+            return outputs
+                .get(utils::display_command(&cmd, args).as_str())
+                .cloned();
+        }
+        if self.is_abandoned() {
+            log::debug!("Not running {cmd:?} for a module which was abandoned");
+            return None;
+        }
         let mut cmd = create_command(cmd).ok()?;
+
+        let version_cache =
+            (self.root_config.version_cache && cache::is_version_query(args)).then(|| {
+                self.version_cache
+                    .get_or_init(|| VersionCache::load(cache::get_version_cache_path()))
+            });
+        let binary = PathBuf::from(cmd.get_program());
+        if let Some(output) = version_cache.and_then(|cache| cache.get(&binary, args)) {
+            return Some(output);
+        }
+
         cmd.args(args).current_dir(&self.current_dir);
-        exec_timeout(
+        let timeout = Duration::from_millis(self.root_config.command_timeout);
+        let output = exec_timeout(
             &mut cmd,
-            Duration::from_millis(self.root_config.command_timeout),
-        )
+            self.command_time_limit(Some(timeout)).unwrap_or(timeout),
+        );
+
+        if let (Some(cache), Some(output)) = (version_cache, &output) {
+            cache.insert(&binary, args, output.clone());
+        }
+        output
     }
 
     /// Attempt to execute several commands with `exec_cmd`, return the results of the first that works
@@ -510,6 +720,10 @@ impl<'a> Context<'a> {
     pub fn get_config_path_os(&self) -> Option<OsString> {
         get_config_path_os(&self.env)
     }
+
+    pub fn get_config_cache_dir(&self) -> Option<PathBuf> {
+        get_config_cache_dir(&self.env)
+    }
 }
 
 impl Default for Context<'_> {
@@ -531,7 +745,7 @@ pub enum Detected {
 }
 
 fn home_dir(env: &Env) -> Option<PathBuf> {
-    if cfg!(test)
+    if env.is_mocked()
         && let Some(home) = env.get_env("HOME")
     {
         return Some(PathBuf::from(home));
@@ -539,7 +753,13 @@ fn home_dir(env: &Env) -> Option<PathBuf> {
     utils::home_dir()
 }
 
-fn get_config_path_os(env: &Env) -> Option<OsString> {
+/// The directory of the cache of parsed config files. A mocked environment
+/// must not read or write the user's cache, so it has none.
+pub(crate) fn get_config_cache_dir(env: &Env) -> Option<PathBuf> {
+    (!env.is_mocked()).then(cache::get_config_cache_dir)
+}
+
+pub(crate) fn get_config_path_os(env: &Env) -> Option<OsString> {
     if let Some(config_path) = env.get_env_os("STARSHIP_CONFIG") {
         return Some(config_path);
     }
@@ -556,6 +776,8 @@ pub struct DirContents {
     folders: HashSet<PathBuf>,
     // HashSet of all extensions found, without dots, e.g. "js" instead of ".js".
     extensions: HashSet<String>,
+    // Whether the scan finished before `scan_timeout` was reached.
+    complete: bool,
 }
 
 impl DirContents {
@@ -578,6 +800,7 @@ impl DirContents {
         let mut files: HashSet<PathBuf> = HashSet::new();
         let mut file_names: HashSet<String> = HashSet::new();
         let mut extensions: HashSet<String> = HashSet::new();
+        let complete;
 
         let base_path = base;
         let base = Arc::from(base);
@@ -651,6 +874,7 @@ impl DirContents {
                         log::warn!(
                             "You can set scan_timeout in your config to a higher value to allow longer-running scans to keep executing."
                         );
+                        complete = false;
                         break;
                     }
 
@@ -659,8 +883,9 @@ impl DirContents {
                     remaining_time =
                         timeout.saturating_sub(Instant::now().saturating_duration_since(start));
                 }
-                Err(_) => {
+                Err(e) => {
                     // Timeout or Disconnected occurred
+                    complete = e == mpsc::RecvTimeoutError::Disconnected;
                     break;
                 }
             }
@@ -676,6 +901,7 @@ impl DirContents {
             file_names,
             folders,
             extensions,
+            complete,
         })
     }
 
@@ -892,7 +1118,7 @@ pub enum Shell {
 }
 
 /// Which kind of prompt target to print (main prompt, rprompt, ...)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Target {
     Main,
     Right,
@@ -901,7 +1127,7 @@ pub enum Target {
 }
 
 /// Properties as passed on from the shell as arguments
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
 pub struct Properties {
     /// The status code of the previously run command as an unsigned or signed 32bit integer
     #[clap(short = 's', long = "status")]
@@ -914,11 +1140,11 @@ pub struct Properties {
     terminal_width: usize,
     /// The path that the prompt should render for.
     #[clap(short, long)]
-    path: Option<PathBuf>,
+    pub(crate) path: Option<PathBuf>,
     /// The logical path that the prompt should render for.
     /// This path should be a virtual/logical representation of the PATH argument.
     #[clap(short = 'P', long)]
-    logical_path: Option<PathBuf>,
+    pub(crate) logical_path: Option<PathBuf>,
     /// The execution duration of the last command, in milliseconds
     #[clap(short = 'd', long)]
     pub cmd_duration: Option<String>,
@@ -931,6 +1157,9 @@ pub struct Properties {
     /// The current value of SHLVL, for shells that mis-handle it in $()
     #[clap(long, value_parser=parse_i64)]
     pub shlvl: Option<i64>,
+    /// Use the formats and module overrides of a profile from `[profiles]`
+    #[clap(long)]
+    pub profile: Option<String>,
 }
 
 impl Default for Properties {
@@ -945,6 +1174,7 @@ impl Default for Properties {
             keymap: "viins".to_string(),
             jobs: 0,
             shlvl: None,
+            profile: None,
         }
     }
 }
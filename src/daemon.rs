@@ -0,0 +1,521 @@
+//! A background server rendering prompts on behalf of `starship prompt`.
+//!
+//! The daemon keeps the parsed configuration, the scanned directory contents and the
+//! discovered git repositories between renders. Each of them is invalidated as soon as
+//! the modification time or size of the files it was computed from changes.
+//!
+//! Clients and daemons only talk to each other when they share the same environment,
+//! since modules read environment variables and spawn commands inheriting them.
+//! The socket name is derived from a hash of the environment to ensure this.
+
+use crate::context::{Properties, Target};
+use serde::{Deserialize, Serialize};
+
+#[cfg(unix)]
+pub use unix::{render, serve};
+
+/// Environment variables that change between prompts without affecting the rendered prompt,
+/// or that are passed to the daemon explicitly.
+const VOLATILE_ENV_VARS: &[&str] = &["_", "OLDPWD", "PWD", "STARSHIP_SESSION_KEY"];
+
+#[cfg_attr(not(unix), allow(dead_code))]
+#[derive(Serialize, Deserialize)]
+struct Request {
+    properties: Properties,
+    target: Target,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+#[derive(Serialize, Deserialize)]
+struct Response {
+    prompt: String,
+}
+
+/// Renders the prompt through a daemon serving the current environment.
+/// Returns `None` when no daemon is available, in which case the prompt should
+/// be rendered in-process.
+#[cfg(not(unix))]
+pub fn render(_properties: &Properties, _target: &Target) -> Option<String> {
+    None
+}
+
+/// Runs the daemon. Unix sockets are not available on this platform.
+#[cfg(not(unix))]
+pub fn serve(_idle_timeout: std::time::Duration) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "starship daemon is only supported on Unix-like systems",
+    ))
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn env_hash<I>(vars: I) -> String
+where
+    I: IntoIterator<Item = (std::ffi::OsString, std::ffi::OsString)>,
+{
+    use sha1::{Digest, Sha1};
+
+    let mut vars = vars
+        .into_iter()
+        .filter(|(key, _)| !VOLATILE_ENV_VARS.iter().any(|volatile| key == volatile))
+        .collect::<Vec<_>>();
+    vars.sort();
+
+    let mut hasher = Sha1::new();
+    hasher.update(crate::shadow::VERSION);
+    for (key, value) in vars {
+        hasher.update(key.as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_encoded_bytes());
+        hasher.update([0]);
+    }
+    // Keep the socket path well below the length limit of Unix socket addresses
+    crate::utils::encode_to_hex(&hasher.finalize()[..8])
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{Request, Response, env_hash};
+    use crate::cache::file_stamp;
+    use crate::config::StarshipConfig;
+    use crate::context::{Context, DirContents, Env, GitRepo, Properties, Target};
+    use crate::logger::get_log_dir;
+    use crate::print;
+
+    use std::collections::HashMap;
+    use std::env;
+    use std::ffi::{OsStr, OsString};
+    use std::fs;
+    use std::io::{self, Write};
+    use std::net::Shutdown;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// How long a client waits for the daemon before rendering the prompt itself.
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// How long the daemon waits for a client to send its request or to read the prompt.
+    const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Maximum number of directories for which warm state is kept.
+    const MAX_CACHED_DIRS: usize = 256;
+
+    type Stamp = Option<(u128, u64)>;
+
+    /// Warm state per directory, along with the stamps it is valid for.
+    type DirCache<S, T> = Mutex<HashMap<PathBuf, (S, Arc<T>)>>;
+
+    fn socket_path() -> PathBuf {
+        get_log_dir()
+            .join("daemon")
+            .join(format!("{}.sock", env_hash(env::vars_os())))
+    }
+
+    /// Creates the directory of the sockets, which only the user may access. The sockets are
+    /// bound inside of it, so others can never connect to them, not even right after binding.
+    fn create_socket_dir(dir: &Path) -> io::Result<()> {
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            result => result?,
+        }
+        // The directory may have been created with other permissions
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+
+    /// Renders the prompt through a daemon serving the current environment.
+    /// Returns `None` when no daemon is available, in which case the prompt should
+    /// be rendered in-process.
+    pub fn render(properties: &Properties, target: &Target) -> Option<String> {
+        let socket = socket_path();
+        if !socket.exists() {
+            return None;
+        }
+
+        // The daemon runs in a different directory, so resolve the paths the same way
+        // `Context::new` would before sending them over.
+        let mut properties = properties.clone();
+        properties.path = properties
+            .path
+            .or_else(|| env::current_dir().ok())
+            .or_else(|| env::var("PWD").map(PathBuf::from).ok());
+        properties.logical_path = properties
+            .logical_path
+            .or_else(|| env::var("PWD").map(PathBuf::from).ok())
+            .or_else(|| properties.path.clone());
+
+        let request = Request {
+            properties,
+            target: target.clone(),
+        };
+        request_prompt(&socket, &request)
+            .inspect_err(|e| log::debug!("Unable to render prompt with daemon: {e}"))
+            .ok()
+    }
+
+    fn request_prompt(socket: &Path, request: &Request) -> io::Result<String> {
+        let mut stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        serde_json::to_writer(&mut stream, request)?;
+        stream.shutdown(Shutdown::Write)?;
+        let response: Response = serde_json::from_reader(stream)?;
+        Ok(response.prompt)
+    }
+
+    /// Listens for prompt requests from shells sharing the current environment,
+    /// exiting after `idle_timeout` without requests. A zero timeout never exits.
+    pub fn serve(idle_timeout: Duration) -> io::Result<()> {
+        let socket = socket_path();
+        if UnixStream::connect(&socket).is_ok() {
+            log::info!("A daemon is already listening on {}", socket.display());
+            return Ok(());
+        }
+
+        // Remove the socket of a daemon which did not shut down cleanly
+        let _ = fs::remove_file(&socket);
+        if let Some(parent) = socket.parent() {
+            create_socket_dir(parent)?;
+        }
+        let listener = UnixListener::bind(&socket)?;
+        log::debug!("Listening on {}", socket.display());
+
+        let last_request = Arc::new(Mutex::new(Instant::now()));
+        if !idle_timeout.is_zero() {
+            let last_request = Arc::clone(&last_request);
+            let socket = socket.clone();
+            thread::Builder::new()
+                .name("daemon watchdog".into())
+                .spawn(move || watch(&socket, idle_timeout, &last_request))?;
+        }
+
+        let env = Env::default();
+        let server = Arc::new(Server::new(
+            crate::context::get_config_path_os(&env),
+            crate::context::get_config_cache_dir(&env),
+        ));
+        accept(&listener, &server, &last_request);
+        Ok(())
+    }
+
+    /// Handles each client on its own thread, so that a slow render or a client
+    /// which never sends its request doesn't hold up the others.
+    fn accept(listener: &UnixListener, server: &Arc<Server>, last_request: &Mutex<Instant>) {
+        for stream in listener.incoming() {
+            if let Ok(mut last_request) = last_request.lock() {
+                *last_request = Instant::now();
+            }
+            let server = Arc::clone(server);
+            let handled = stream.and_then(|stream| {
+                stream.set_read_timeout(Some(SERVER_TIMEOUT))?;
+                stream.set_write_timeout(Some(SERVER_TIMEOUT))?;
+                thread::Builder::new()
+                    .name("daemon client".into())
+                    .spawn(move || {
+                        if let Err(e) = server.handle(stream) {
+                            log::debug!("Unable to handle prompt request: {e}");
+                        }
+                    })
+            });
+            if let Err(e) = handled {
+                log::debug!("Unable to accept prompt request: {e}");
+            }
+        }
+    }
+
+    /// Shuts the daemon down once it has been idle for `idle_timeout`,
+    /// or when its socket has been removed.
+    fn watch(socket: &Path, idle_timeout: Duration, last_request: &Mutex<Instant>) {
+        loop {
+            thread::sleep(idle_timeout.min(Duration::from_secs(60)));
+            if !socket.exists() {
+                log::debug!("Socket {} was removed, shutting down", socket.display());
+                break;
+            }
+            if last_request
+                .lock()
+                .is_ok_and(|last_request| last_request.elapsed() >= idle_timeout)
+            {
+                log::debug!("No requests received for {idle_timeout:?}, shutting down");
+                let _ = fs::remove_file(socket);
+                break;
+            }
+        }
+        std::process::exit(0);
+    }
+
+    /// State kept warm between renders, shared by the threads handling clients.
+    struct Server {
+        config_path: Option<OsString>,
+        config_cache_dir: Option<PathBuf>,
+        config: Mutex<Option<(Vec<Stamp>, StarshipConfig)>>,
+        dir_contents: DirCache<Stamp, DirContents>,
+        git_repos: DirCache<Vec<Stamp>, GitRepo>,
+    }
+
+    impl Server {
+        fn new(config_path: Option<OsString>, config_cache_dir: Option<PathBuf>) -> Self {
+            Self {
+                config_path,
+                config_cache_dir,
+                config: Mutex::new(None),
+                dir_contents: Mutex::new(HashMap::new()),
+                git_repos: Mutex::new(HashMap::new()),
+            }
+        }
+
+        fn handle(&self, mut stream: UnixStream) -> io::Result<()> {
+            let request: Request = serde_json::from_reader(&stream)?;
+            let response = Response {
+                prompt: self.render(request),
+            };
+            serde_json::to_writer(&mut stream, &response)?;
+            stream.flush()
+        }
+
+        fn render(&self, request: Request) -> String {
+            let config = self.load_config();
+            let context = Context::new_with_config(request.properties, request.target, config);
+
+            let dir = context.current_dir.clone();
+            // Stamp the directory before rendering, so changes made during the scan
+            // invalidate the result
+            let dir_stamp = file_stamp(&dir);
+            let dir_contents = lock(&self.dir_contents)
+                .get(&dir)
+                .filter(|(stamp, _)| *stamp == dir_stamp)
+                .map(|(_, dir_contents)| Arc::clone(dir_contents));
+            let git_repo = lock(&self.git_repos)
+                .get(&dir)
+                .map(|(stamps, git_repo)| (stamps.clone(), Arc::clone(git_repo)))
+                .filter(|(stamps, git_repo)| *stamps == git_stamps(git_repo))
+                .map(|(_, git_repo)| git_repo);
+            let context = Arc::new(context.with_warm_state(dir_contents, git_repo));
+
+            let prompt = print::get_prompt_with_deadline(&context);
+
+            if let Some(dir_contents) = context.warm_dir_contents() {
+                let mut cached = lock(&self.dir_contents);
+                if cached.len() >= MAX_CACHED_DIRS {
+                    cached.clear();
+                }
+                cached.insert(dir.clone(), (dir_stamp, dir_contents));
+            }
+            if let Some(git_repo) = context.warm_git_repo() {
+                let stamps = git_stamps(&git_repo);
+                let mut cached = lock(&self.git_repos);
+                if cached.len() >= MAX_CACHED_DIRS {
+                    cached.clear();
+                }
+                cached.insert(dir, (stamps, git_repo));
+            }
+            prompt
+        }
+
+        /// Returns the parsed config, re-reading it if the file has changed.
+        /// Any change to the config drops the rest of the warm state,
+        /// since the config affects how directories are scanned.
+        fn load_config(&self) -> StarshipConfig {
+            let mut cached = lock(&self.config);
+            match &*cached {
+                Some((cached_stamps, config))
+                    if *cached_stamps == config_stamps(self.config_path.as_deref(), config) =>
+                {
+                    config.clone()
+                }
+                _ => {
+                    log::debug!("Loading config from {:?}", self.config_path);
+                    let config = StarshipConfig::initialize(
+                        self.config_path.as_deref(),
+                        self.config_cache_dir.clone(),
+                    );
+                    let stamps = config_stamps(self.config_path.as_deref(), &config);
+                    *cached = Some((stamps, config.clone()));
+                    lock(&self.dir_contents).clear();
+                    lock(&self.git_repos).clear();
+                    config
+                }
+            }
+        }
+    }
+
+    /// Locks a part of the warm state. A thread which panicked while holding the lock
+    /// can't leave it inconsistent, since entries are only ever inserted whole.
+    fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+        mutex
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Stamps of the config file and the files it includes.
+    fn config_stamps(config_path: Option<&OsStr>, config: &StarshipConfig) -> Vec<Stamp> {
+        config_path
+            .map(Path::new)
+            .into_iter()
+            .chain(config.files.iter().map(PathBuf::as_path))
+            .map(file_stamp)
+            .collect()
+    }
+
+    /// Stamps of the files the information in a `GitRepo` is read from.
+    /// The git directory itself changes when an operation like a merge or rebase
+    /// starts or ends, `HEAD` when switching branches and `config` when remotes change.
+    fn git_stamps(git_repo: &GitRepo) -> Vec<Stamp> {
+        let common_dir = git_repo.repo.to_thread_local().common_dir().to_path_buf();
+        vec![
+            file_stamp(&git_repo.path),
+            file_stamp(&git_repo.path.join("HEAD")),
+            file_stamp(&common_dir.join("config")),
+        ]
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn request(path: &Path) -> Request {
+            let mut properties = Properties::default();
+            properties.path = Some(path.to_path_buf());
+            properties.logical_path = Some(path.to_path_buf());
+            Request {
+                properties,
+                target: Target::Main,
+            }
+        }
+
+        #[test]
+        fn renders_prompt_over_socket() -> io::Result<()> {
+            let dir = tempfile::tempdir()?;
+            let config_path = dir.path().join("starship.toml");
+            fs::write(&config_path, "add_newline = false\nformat = 'served'\n")?;
+            let socket = dir.path().join("daemon.sock");
+            let listener = UnixListener::bind(&socket)?;
+
+            let server = thread::spawn(move || -> io::Result<()> {
+                let server = Server::new(Some(config_path.clone().into()), None);
+                for stream in listener.incoming().take(2) {
+                    server.handle(stream?)?;
+                    // A config with a different size invalidates the cached one
+                    fs::write(&config_path, "add_newline = false\nformat = 'reloaded'\n")?;
+                }
+                Ok(())
+            });
+
+            assert_eq!(request_prompt(&socket, &request(dir.path()))?, "served");
+            assert_eq!(request_prompt(&socket, &request(dir.path()))?, "reloaded");
+            server.join().unwrap()?;
+            dir.close()
+        }
+
+        #[test]
+        fn socket_dir_is_private() -> io::Result<()> {
+            let dir = tempfile::tempdir()?;
+            let socket_dir = dir.path().join("logs").join("daemon");
+            create_socket_dir(&socket_dir)?;
+            let mode = |path: &Path| fs::metadata(path).map(|meta| meta.permissions().mode());
+            assert_eq!(mode(&socket_dir)? & 0o777, 0o700);
+
+            // An existing directory is made private as well
+            fs::set_permissions(&socket_dir, fs::Permissions::from_mode(0o755))?;
+            create_socket_dir(&socket_dir)?;
+            assert_eq!(mode(&socket_dir)? & 0o777, 0o700);
+            dir.close()
+        }
+
+        #[test]
+        fn stalled_client_does_not_block_others() -> io::Result<()> {
+            let dir = tempfile::tempdir()?;
+            let config_path = dir.path().join("starship.toml");
+            fs::write(&config_path, "add_newline = false\nformat = 'served'\n")?;
+            let socket = dir.path().join("daemon.sock");
+            let listener = UnixListener::bind(&socket)?;
+            let server = Arc::new(Server::new(Some(config_path.into()), None));
+            thread::spawn(move || accept(&listener, &server, &Mutex::new(Instant::now())));
+
+            // Connect without ever sending a request
+            let _stalled = UnixStream::connect(&socket)?;
+            assert_eq!(request_prompt(&socket, &request(dir.path()))?, "served");
+            dir.close()
+        }
+
+        #[test]
+        fn keeps_dir_contents_until_directory_changes() -> io::Result<()> {
+            let dir = tempfile::tempdir()?;
+            let config_path = dir.path().join("starship.toml");
+            fs::write(&config_path, "add_newline = false\nformat = '$nodejs'\n")?;
+            let project = tempfile::tempdir()?;
+            let project_dir = dunce::canonicalize(project.path())?;
+            let server = Server::new(Some(config_path.into()), None);
+
+            server.render(request(project.path()));
+            let (_, first) = lock(&server.dir_contents)
+                .get(&project_dir)
+                .unwrap()
+                .clone();
+            server.render(request(project.path()));
+            let (_, second) = lock(&server.dir_contents)
+                .get(&project_dir)
+                .unwrap()
+                .clone();
+            assert!(Arc::ptr_eq(&first, &second));
+
+            fs::write(project.path().join("package.json"), "{}")?;
+            // Make sure the modification time of the directory changes
+            let times = fs::FileTimes::new()
+                .set_modified(std::time::SystemTime::now() + Duration::from_secs(60));
+            fs::File::open(project.path())?.set_times(times)?;
+            server.render(request(project.path()));
+            let (_, third) = lock(&server.dir_contents)
+                .get(&project_dir)
+                .unwrap()
+                .clone();
+            assert!(!Arc::ptr_eq(&second, &third));
+            assert!(third.has_file_name("package.json"));
+            project.close()?;
+            dir.close()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        vars.iter()
+            .map(|(key, value)| (OsString::from(key), OsString::from(value)))
+            .collect()
+    }
+
+    #[test]
+    fn env_hash_ignores_volatile_vars_and_order() {
+        let hash = env_hash(vars(&[("HOME", "/home/user"), ("PATH", "/bin")]));
+        assert_eq!(
+            hash,
+            env_hash(vars(&[
+                ("PATH", "/bin"),
+                ("PWD", "/tmp"),
+                ("HOME", "/home/user"),
+                ("STARSHIP_SESSION_KEY", "1234"),
+            ]))
+        );
+        assert_ne!(
+            hash,
+            env_hash(vars(&[("HOME", "/home/user"), ("PATH", "/usr/bin")]))
+        );
+        assert_ne!(
+            hash,
+            env_hash(vars(&[
+                ("HOME", "/home/user"),
+                ("PATH", "/bin"),
+                ("VIRTUAL_ENV", "/venv")
+            ]))
+        );
+    }
+}
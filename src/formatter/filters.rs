@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use regex::Regex;
+use unicode_width::UnicodeWidthStr;
+
+use super::VersionFormatter;
+use super::model::Filter;
+use super::string_formatter::StringFormatterError;
+use crate::modules::utils::truncate::truncate_text;
+use crate::utils::humanize_int;
+
+/// Applies a chain of filters to the value of a variable, from left to right
+pub fn apply_filters(value: String, filters: &[Filter]) -> Result<String, StringFormatterError> {
+    filters
+        .iter()
+        .try_fold(value, |value, filter| apply_filter(value, filter))
+}
+
+fn apply_filter(value: String, filter: &Filter) -> Result<String, StringFormatterError> {
+    let Filter { name, args } = filter;
+    let arg = |index: usize| args.get(index).map(Cow::as_ref);
+    let number_arg = |index: usize| {
+        arg(index)
+            .map(|arg| {
+                arg.parse::<usize>().map_err(|_| {
+                    StringFormatterError::Custom(format!(
+                        "Filter `{name}` expects a number, got {arg:?}"
+                    ))
+                })
+            })
+            .transpose()
+    };
+    let expect_args = |min: usize, max: usize| {
+        if (min..=max).contains(&args.len()) {
+            Ok(())
+        } else {
+            Err(StringFormatterError::Custom(format!(
+                "Filter `{name}` expects {} arguments, got {}",
+                if min == max {
+                    min.to_string()
+                } else {
+                    format!("{min} to {max}")
+                },
+                args.len()
+            )))
+        }
+    };
+
+    match *name {
+        "upper" => expect_args(0, 0).map(|()| value.to_uppercase()),
+        "lower" => expect_args(0, 0).map(|()| value.to_lowercase()),
+        "capitalize" => expect_args(0, 0).map(|()| {
+            let mut chars = value.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect()
+            })
+        }),
+        "trim" => expect_args(0, 0).map(|()| value.trim().to_string()),
+        "truncate" => {
+            expect_args(1, 2)?;
+            let length = number_arg(0)?.unwrap_or_default();
+            Ok(truncate_text(&value, length, arg(1).unwrap_or("…")))
+        }
+        "replace" => {
+            expect_args(2, 2)?;
+            let pattern = arg(0).unwrap_or_default();
+            let regex = Regex::new(pattern).map_err(|error| {
+                StringFormatterError::Custom(format!("Invalid regex {pattern:?}: {error}"))
+            })?;
+            Ok(regex
+                .replace_all(&value, arg(1).unwrap_or_default())
+                .into_owned())
+        }
+        "default" => {
+            expect_args(1, 1)?;
+            Ok(if value.is_empty() {
+                arg(0).unwrap_or_default().to_string()
+            } else {
+                value
+            })
+        }
+        "pad_left" | "pad_right" => {
+            expect_args(1, 2)?;
+            let width = number_arg(0)?.unwrap_or_default();
+            let fill = arg(1).unwrap_or(" ");
+            let padding = fill.repeat(width.saturating_sub(value.width()));
+            Ok(if *name == "pad_left" {
+                padding + &value
+            } else {
+                value + &padding
+            })
+        }
+        "humanize" => {
+            expect_args(0, 0).map(|()| value.trim().parse::<u64>().map_or(value, humanize_int))
+        }
+        "major" | "major_minor" => expect_args(0, 0).map(|()| {
+            // Keep a `v` prefix, e.g. from the default `version_format`
+            let (prefix, version) = value
+                .strip_prefix('v')
+                .map_or(("", value.as_str()), |version| ("v", version));
+            let format = if *name == "major" {
+                "${major}"
+            } else {
+                "${major}(.${minor})"
+            };
+            match VersionFormatter::format_version(version, format) {
+                Ok(formatted) if !formatted.is_empty() => format!("{prefix}{formatted}"),
+                _ => value,
+            }
+        }),
+        "basename" => expect_args(0, 0).map(|()| {
+            Path::new(&value)
+                .file_name()
+                .map_or_else(|| value.clone(), |name| name.to_string_lossy().into_owned())
+        }),
+        "dirname" => expect_args(0, 0).map(|()| {
+            Path::new(&value)
+                .parent()
+                .map(|parent| parent.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }),
+        _ => Err(StringFormatterError::Custom(format!(
+            "Unknown filter `{name}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter<'a>(name: &'a str, args: &[&'a str]) -> Filter<'a> {
+        Filter {
+            name,
+            args: args.iter().map(|&arg| arg.into()).collect(),
+        }
+    }
+
+    fn apply(value: &str, name: &str, args: &[&str]) -> Result<String, StringFormatterError> {
+        apply_filters(value.to_string(), &[filter(name, args)])
+    }
+
+    #[test]
+    fn case_filters() {
+        assert_eq!(apply("Main", "upper", &[]).unwrap(), "MAIN");
+        assert_eq!(apply("Main", "lower", &[]).unwrap(), "main");
+        assert_eq!(apply("éclair", "capitalize", &[]).unwrap(), "Éclair");
+        assert_eq!(apply("", "capitalize", &[]).unwrap(), "");
+        assert_eq!(apply(" x ", "trim", &[]).unwrap(), "x");
+    }
+
+    #[test]
+    fn truncate_filter() {
+        assert_eq!(apply("production", "truncate", &["4"]).unwrap(), "prod…");
+        assert_eq!(
+            apply("production", "truncate", &["4", "~"]).unwrap(),
+            "prod~"
+        );
+        assert_eq!(apply("prod", "truncate", &["4"]).unwrap(), "prod");
+        assert!(apply("prod", "truncate", &["four"]).is_err());
+        assert!(apply("prod", "truncate", &[]).is_err());
+    }
+
+    #[test]
+    fn replace_filter() {
+        assert_eq!(
+            apply("feature/login", "replace", &["^feature/", "f:"]).unwrap(),
+            "f:login"
+        );
+        assert_eq!(apply("a-b-c", "replace", &["-(.)", "$1"]).unwrap(), "abc");
+        assert!(apply("a", "replace", &["(", ""]).is_err());
+    }
+
+    #[test]
+    fn default_filter() {
+        assert_eq!(apply("", "default", &["none"]).unwrap(), "none");
+        assert_eq!(apply("dev", "default", &["none"]).unwrap(), "dev");
+    }
+
+    #[test]
+    fn pad_filters() {
+        assert_eq!(apply("ab", "pad_left", &["4"]).unwrap(), "  ab");
+        assert_eq!(apply("ab", "pad_right", &["4", "."]).unwrap(), "ab..");
+        assert_eq!(apply("abcde", "pad_right", &["4"]).unwrap(), "abcde");
+    }
+
+    #[test]
+    fn humanize_filter() {
+        assert_eq!(apply("1234567", "humanize", &[]).unwrap(), "1.2M");
+        assert_eq!(apply("12", "humanize", &[]).unwrap(), "12");
+        assert_eq!(apply("n/a", "humanize", &[]).unwrap(), "n/a");
+    }
+
+    #[test]
+    fn version_filters() {
+        assert_eq!(apply("v1.2.3", "major_minor", &[]).unwrap(), "v1.2");
+        assert_eq!(apply("18.4.0", "major", &[]).unwrap(), "18");
+        assert_eq!(apply("nightly", "major_minor", &[]).unwrap(), "nightly");
+    }
+
+    #[test]
+    fn path_filters() {
+        assert_eq!(apply("/home/user/src", "basename", &[]).unwrap(), "src");
+        assert_eq!(
+            apply("/home/user/src", "dirname", &[]).unwrap(),
+            "/home/user"
+        );
+    }
+
+    #[test]
+    fn filter_chain() {
+        let filters = [filter("truncate", &["3", ""]), filter("upper", &[])];
+        assert_eq!(
+            apply_filters("production".to_string(), &filters).unwrap(),
+            "PRO"
+        );
+    }
+}
@@ -1,8 +1,10 @@
+mod filters;
 pub mod model;
 mod parser;
 pub mod string_formatter;
 mod version;
 
 pub use model::{StyleVariableHolder, VariableHolder};
+pub use parser::{parse_literal_styles, parse_rendered_variables};
 pub use string_formatter::StringFormatter;
 pub use version::VersionFormatter;
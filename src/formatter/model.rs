@@ -20,27 +20,96 @@ pub struct TextGroup<'a> {
 #[derive(Clone)]
 pub enum FormatElement<'a> {
     Text(Cow<'a, str>),
-    Variable(Cow<'a, str>),
+    Variable(Cow<'a, str>, Vec<Filter<'a>>),
     TextGroup(TextGroup<'a>),
     Conditional(Vec<Self>),
+    IfElse(IfElse<'a>),
+}
+
+/// A textgroup which is rendered if `condition` holds, with an optional alternative
+#[derive(Clone)]
+pub struct IfElse<'a> {
+    pub condition: Condition<'a>,
+    pub then: TextGroup<'a>,
+    pub otherwise: Option<TextGroup<'a>>,
+}
+
+/// A single operand, which holds if it is not empty, or a comparison of two operands
+#[derive(Clone)]
+pub struct Condition<'a> {
+    pub left: Operand<'a>,
+    pub comparison: Option<(Comparator, Operand<'a>)>,
+}
+
+#[derive(Clone)]
+pub enum Operand<'a> {
+    Variable(Cow<'a, str>, Vec<Filter<'a>>),
+    Literal(Cow<'a, str>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Match,
+    NotMatch,
+}
+
+impl<'a> Condition<'a> {
+    /// Returns the operands of the condition
+    pub fn operands(&self) -> impl Iterator<Item = &Operand<'a>> {
+        std::iter::once(&self.left).chain(self.comparison.as_ref().map(|(_, right)| right))
+    }
+}
+
+impl<'a> IfElse<'a> {
+    /// Returns the textgroups of both branches
+    pub fn branches(&self) -> impl Iterator<Item = &TextGroup<'a>> {
+        std::iter::once(&self.then).chain(self.otherwise.as_ref())
+    }
+}
+
+/// A transformation of the value of a variable, e.g. `|truncate(12)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter<'a> {
+    pub name: &'a str,
+    pub args: Vec<Cow<'a, str>>,
 }
 
 #[derive(Clone)]
 pub enum StyleElement<'a> {
     Text(Cow<'a, str>),
-    Variable(Cow<'a, str>),
+    Variable(Cow<'a, str>, Vec<Filter<'a>>),
 }
 
 impl<'a> VariableHolder<Cow<'a, str>> for FormatElement<'a> {
     fn get_variables(&self) -> BTreeSet<Cow<'a, str>> {
         match self {
-            Self::Variable(var) => {
+            Self::Variable(var, _) => {
                 let mut variables = BTreeSet::new();
                 variables.insert(var.clone());
                 variables
             }
             Self::TextGroup(textgroup) => textgroup.format.get_variables(),
             Self::Conditional(format) => format.get_variables(),
+            Self::IfElse(if_else) => {
+                let mut variables = if_else
+                    .condition
+                    .operands()
+                    .filter_map(|operand| match operand {
+                        Operand::Variable(var, _) => Some(var.clone()),
+                        Operand::Literal(_) => None,
+                    })
+                    .collect::<BTreeSet<_>>();
+                for textgroup in if_else.branches() {
+                    variables.extend(textgroup.format.get_variables());
+                }
+                variables
+            }
             _ => Default::default(),
         }
     }
@@ -67,7 +136,7 @@ impl<'a> VariableHolder<Cow<'a, str>> for &[FormatElement<'a>] {
 impl<'a> StyleVariableHolder<Cow<'a, str>> for StyleElement<'a> {
     fn get_style_variables(&self) -> BTreeSet<Cow<'a, str>> {
         match self {
-            Self::Variable(var) => {
+            Self::Variable(var, _) => {
                 let mut variables = BTreeSet::new();
                 variables.insert(var.clone());
                 variables
@@ -98,6 +167,13 @@ impl<'a> StyleVariableHolder<Cow<'a, str>> for Vec<FormatElement<'a>> {
                 acc.extend(format.get_style_variables());
                 acc
             }
+            FormatElement::IfElse(if_else) => {
+                for textgroup in if_else.branches() {
+                    acc.extend(textgroup.style.get_style_variables());
+                    acc.extend(textgroup.format.get_style_variables());
+                }
+                acc
+            }
             _ => acc,
         })
     }
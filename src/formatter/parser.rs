@@ -1,6 +1,8 @@
 // Can't rename internal Pest names
 #![allow(clippy::upper_case_acronyms)]
 
+use std::ops::Range;
+
 use pest::{Parser, error::Error, iterators::Pair};
 use pest_derive::*;
 
@@ -13,11 +15,60 @@ struct IdentParser;
 fn parse_value(value: Pair<Rule>) -> FormatElement {
     match value.as_rule() {
         Rule::text => FormatElement::Text(parse_text(value).into()),
-        Rule::variable => FormatElement::Variable(parse_variable(value).into()),
+        Rule::variable => {
+            let (name, filters) = parse_variable(value);
+            FormatElement::Variable(name.into(), filters)
+        }
         Rule::textgroup => FormatElement::TextGroup(parse_textgroup(value)),
         Rule::conditional => {
             FormatElement::Conditional(parse_format(value.into_inner().next().unwrap()))
         }
+        Rule::if_else => FormatElement::IfElse(parse_if_else(value)),
+        _ => unreachable!(),
+    }
+}
+
+fn parse_if_else(if_else: Pair<Rule>) -> IfElse {
+    let mut inner_rules = if_else.into_inner();
+    let condition = parse_condition(inner_rules.next().unwrap());
+    let then = parse_textgroup(inner_rules.next().unwrap());
+    let otherwise = inner_rules.next().map(parse_textgroup);
+
+    IfElse {
+        condition,
+        then,
+        otherwise,
+    }
+}
+
+fn parse_condition(condition: Pair<Rule>) -> Condition {
+    let mut inner_rules = condition.into_inner();
+    let left = parse_operand(inner_rules.next().unwrap());
+    let comparison = inner_rules.next().map(|comparator| {
+        let comparator = match comparator.as_str() {
+            "==" => Comparator::Equal,
+            "!=" => Comparator::NotEqual,
+            ">" => Comparator::Greater,
+            ">=" => Comparator::GreaterOrEqual,
+            "<" => Comparator::Less,
+            "<=" => Comparator::LessOrEqual,
+            "=~" => Comparator::Match,
+            "!~" => Comparator::NotMatch,
+            _ => unreachable!(),
+        };
+        (comparator, parse_operand(inner_rules.next().unwrap()))
+    });
+
+    Condition { left, comparison }
+}
+
+fn parse_operand(operand: Pair<Rule>) -> Operand {
+    match operand.as_rule() {
+        Rule::variable => {
+            let (name, filters) = parse_variable(operand);
+            Operand::Variable(name.into(), filters)
+        }
+        Rule::literal => Operand::Literal(parse_literal(operand).into()),
         _ => unreachable!(),
     }
 }
@@ -33,8 +84,32 @@ fn parse_textgroup(textgroup: Pair<Rule>) -> TextGroup {
     }
 }
 
-fn parse_variable(variable: Pair<'_, Rule>) -> &str {
-    variable.into_inner().next().unwrap().as_str()
+fn parse_variable(variable: Pair<'_, Rule>) -> (&str, Vec<Filter<'_>>) {
+    let mut inner_rules = variable.into_inner().peekable();
+    let name = inner_rules.next().unwrap().as_str();
+    // `${name:-fallback}` is the same as `${name}|default(fallback)`
+    let fallback = inner_rules
+        .next_if(|pair| pair.as_rule() == Rule::variable_fallback)
+        .map(|fallback| Filter {
+            name: "default",
+            args: vec![fallback.as_str().into()],
+        });
+    let filters = fallback
+        .into_iter()
+        .chain(inner_rules.map(parse_filter))
+        .collect();
+    (name, filters)
+}
+
+fn parse_filter(filter: Pair<'_, Rule>) -> Filter<'_> {
+    let mut inner_rules = filter.into_inner();
+    let name = inner_rules.next().unwrap().as_str();
+    let args = inner_rules.map(|arg| parse_literal(arg).into()).collect();
+    Filter { name, args }
+}
+
+fn parse_literal(literal: Pair<'_, Rule>) -> &str {
+    literal.into_inner().next().unwrap().as_str()
 }
 
 fn parse_text(text: Pair<Rule>) -> String {
@@ -52,7 +127,10 @@ fn parse_style(style: Pair<Rule>) -> Vec<StyleElement> {
         .into_inner()
         .map(|pair| match pair.as_rule() {
             Rule::string => StyleElement::Text(pair.as_str().into()),
-            Rule::variable => StyleElement::Variable(parse_variable(pair).into()),
+            Rule::variable => {
+                let (name, filters) = parse_variable(pair);
+                StyleElement::Variable(name.into(), filters)
+            }
             _ => unreachable!(),
         })
         .collect()
@@ -68,3 +146,66 @@ pub fn parse(format: &str) -> Result<Vec<FormatElement<'_>>, Box<Error<Rule>>> {
         })
         .map_err(Box::new)
 }
+
+/// A variable's name, and the byte range of the variable in the format string
+pub type VariableSpan<'a> = (&'a str, Range<usize>);
+
+/// Parses the variables which are rendered by a format string, with the byte range of
+/// each variable including its filters, in the order they appear.
+///
+/// Variables used in styles and in the conditions of `$if` are not included.
+pub fn parse_rendered_variables(format: &str) -> Result<Vec<VariableSpan<'_>>, Box<Error<Rule>>> {
+    fn visit<'a>(pair: Pair<'a, Rule>, variables: &mut Vec<VariableSpan<'a>>) {
+        match pair.as_rule() {
+            Rule::variable => {
+                let span = pair.as_span();
+                let name = pair.into_inner().next().unwrap().as_str();
+                variables.push((name, span.start()..span.end()));
+            }
+            Rule::textgroup => visit(pair.into_inner().next().unwrap(), variables),
+            Rule::format | Rule::conditional => {
+                pair.into_inner().for_each(|pair| visit(pair, variables));
+            }
+            Rule::if_else => pair
+                .into_inner()
+                .filter(|pair| pair.as_rule() == Rule::textgroup)
+                .for_each(|pair| visit(pair, variables)),
+            _ => {}
+        }
+    }
+
+    let pairs = IdentParser::parse(Rule::expression, format).map_err(Box::new)?;
+    let mut variables = Vec::new();
+    pairs.for_each(|pair| visit(pair, &mut variables));
+    Ok(variables)
+}
+
+/// A style string, and its byte range in the format string
+pub type StyleSpan<'a> = (&'a str, Range<usize>);
+
+/// Parses the styles of the text groups of a format string which contain no variables,
+/// with the byte range of each style, in the order they appear.
+pub fn parse_literal_styles(format: &str) -> Result<Vec<StyleSpan<'_>>, Box<Error<Rule>>> {
+    fn visit<'a>(pair: Pair<'a, Rule>, styles: &mut Vec<StyleSpan<'a>>) {
+        match pair.as_rule() {
+            Rule::style
+                if pair
+                    .clone()
+                    .into_inner()
+                    .all(|pair| pair.as_rule() == Rule::string) =>
+            {
+                let span = pair.as_span();
+                styles.push((span.as_str(), span.start()..span.end()));
+            }
+            Rule::textgroup | Rule::format | Rule::conditional | Rule::if_else => {
+                pair.into_inner().for_each(|pair| visit(pair, styles));
+            }
+            _ => {}
+        }
+    }
+
+    let pairs = IdentParser::parse(Rule::expression, format).map_err(Box::new)?;
+    let mut styles = Vec::new();
+    pairs.for_each(|pair| visit(pair, &mut styles));
+    Ok(styles)
+}
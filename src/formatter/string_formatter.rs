@@ -9,6 +9,7 @@ use crate::config::{Style, parse_style_string};
 use crate::context::{Context, Shell};
 use crate::segment::Segment;
 
+use super::filters::apply_filters;
 use super::model::*;
 use super::parser::{Rule, parse};
 
@@ -54,6 +55,7 @@ impl From<String> for StringFormatterError {
     }
 }
 
+#[derive(Clone)]
 pub struct StringFormatter<'a> {
     format: Vec<FormatElement<'a>>,
     variables: VariableMapType<'a>,
@@ -147,6 +149,40 @@ impl<'a> StringFormatter<'a> {
         self
     }
 
+    /// Returns the variables of the format string in the order they first appear in
+    pub fn get_variables_in_order(&self) -> Vec<String> {
+        fn collect(format: &[FormatElement], variables: &mut Vec<String>) {
+            for element in format {
+                match element {
+                    FormatElement::Variable(name, _) => {
+                        if !variables.iter().any(|variable| variable == name) {
+                            variables.push(name.to_string());
+                        }
+                    }
+                    FormatElement::TextGroup(textgroup) => collect(&textgroup.format, variables),
+                    FormatElement::Conditional(format) => collect(format, variables),
+                    FormatElement::IfElse(if_else) => {
+                        for operand in if_else.condition.operands() {
+                            if let Operand::Variable(name, _) = operand
+                                && !variables.iter().any(|variable| variable == name)
+                            {
+                                variables.push(name.to_string());
+                            }
+                        }
+                        for textgroup in if_else.branches() {
+                            collect(&textgroup.format, variables);
+                        }
+                    }
+                    FormatElement::Text(_) => {}
+                }
+            }
+        }
+
+        let mut variables = Vec::new();
+        collect(&self.format, &mut variables);
+        variables
+    }
+
     /// Maps a meta-variable to a format string containing other variables.
     ///
     /// This function should be called **before** other map methods so that variables found in
@@ -244,40 +280,51 @@ impl<'a> StringFormatter<'a> {
             style_variables: &'a StyleVariableMapType<'a>,
             context: Option<&Context>,
         ) -> Result<Vec<Segment>, StringFormatterError> {
-            let style = parse_style(textgroup.style, style_variables, context).transpose()?;
+            let (style, link) = parse_style(textgroup.style, style_variables, context)?;
 
             // Empty textgroups still produce a segment to preserve style for prev_fg/prev_bg references
             if textgroup.format.is_empty() {
                 return Ok(Segment::from_text(style, ""));
             }
-            parse_format(textgroup.format, style, variables, style_variables, context)
+            let mut segments =
+                parse_format(textgroup.format, style, variables, style_variables, context)?;
+            if let Some(link) = link {
+                // Nested textgroups keep their own links
+                for segment in &mut segments {
+                    segment.set_link_if_empty(&link);
+                }
+            }
+            Ok(segments)
         }
 
         fn parse_style<'a>(
             style: Vec<StyleElement>,
             variables: &'a StyleVariableMapType<'a>,
             context: Option<&Context>,
-        ) -> Option<Result<Style, StringFormatterError>> {
+        ) -> Result<(Option<Style>, Option<String>), StringFormatterError> {
             let style_strings = style
                 .into_iter()
                 .map(|style| match style {
                     StyleElement::Text(text) => Ok(text),
-                    StyleElement::Variable(name) => {
+                    StyleElement::Variable(name, filters) => {
                         let variable = variables.get(name.as_ref()).unwrap_or(&None);
-                        match variable {
-                            Some(style_string) => style_string.clone(),
-                            None => Ok("".into()),
+                        let style_string = match variable {
+                            Some(style_string) => style_string.clone()?,
+                            None => "".into(),
+                        };
+                        if filters.is_empty() {
+                            Ok(style_string)
+                        } else {
+                            apply_filters(style_string.into_owned(), &filters).map(Cow::from)
                         }
                     }
                 })
                 .collect::<Result<Vec<Cow<str>>, StringFormatterError>>();
-            style_strings
-                .map(|style_strings| {
-                    let style_string: String =
-                        style_strings.iter().flat_map(|s| s.chars()).collect();
-                    parse_style_string(&style_string, context)
-                })
-                .transpose()
+            style_strings.map(|style_strings| {
+                let style_string: String = style_strings.iter().flat_map(|s| s.chars()).collect();
+                let (style_string, link) = split_link(&style_string);
+                (parse_style_string(&style_string, context), link)
+            })
         }
 
         fn parse_format<'a>(
@@ -304,7 +351,63 @@ impl<'a> StringFormatter<'a> {
                         FormatElement::TextGroup(textgroup) => {
                             parse_textgroup(textgroup, variables, style_variables, context)
                         }
-                        FormatElement::Variable(name) => variables
+                        FormatElement::Variable(name, filters)
+                            if !filters.is_empty()
+                                && !only_unused_fallbacks(
+                                    &name,
+                                    &filters,
+                                    variables,
+                                    style_variables,
+                                    context,
+                                ) =>
+                        {
+                            let value = variables
+                                .get(name.as_ref())
+                                .and_then(Option::as_ref)
+                                .cloned()
+                                .transpose()?;
+                            let segments = match &value {
+                                Some(VariableValue::Styled(segments)) => segments.clone(),
+                                Some(VariableValue::Meta(format)) => StringFormatter {
+                                    format: format.clone(),
+                                    variables: clone_without_meta(variables),
+                                    style_variables: style_variables.clone(),
+                                }
+                                .parse(style, context)?,
+                                _ => Vec::new(),
+                            };
+                            if segments.iter().any(|segment| !segment.value().is_empty()) {
+                                // Styled values are already escaped for the shell and made up of
+                                // several styled parts, so only their text can be filtered
+                                return Err(StringFormatterError::Custom(format!(
+                                    "Filters can only be applied to text variables, but ${name} is styled"
+                                )));
+                            }
+
+                            let (text, escape) = match value {
+                                Some(VariableValue::Plain(text)) => (text.into_owned(), true),
+                                Some(VariableValue::NoEscapingPlain(text)) => {
+                                    (text.into_owned(), false)
+                                }
+                                _ => (String::new(), true),
+                            };
+                            let text = apply_filters(text, &filters)?;
+                            Ok(Segment::from_text(
+                                style,
+                                if escape {
+                                    shell_prompt_escape(
+                                        text,
+                                        match context {
+                                            None => Shell::Unknown,
+                                            Some(c) => c.shell,
+                                        },
+                                    )
+                                } else {
+                                    text
+                                },
+                            ))
+                        }
+                        FormatElement::Variable(name, _) => variables
                             .get(name.as_ref())
                             .expect("Uncached variable found")
                             .as_ref()
@@ -384,7 +487,9 @@ impl<'a> StringFormatter<'a> {
                                 })
                             }
 
-                            let should_show: bool = should_show_elements(&format, variables);
+                            // A variable falling back to a non-empty value isn't empty either
+                            let should_show: bool = should_show_elements(&format, variables)
+                                || shows_fallback(&format, variables, style_variables, context);
 
                             if should_show {
                                 parse_format(format, style, variables, style_variables, context)
@@ -392,16 +497,45 @@ impl<'a> StringFormatter<'a> {
                                 Ok(Vec::new())
                             }
                         }
+                        FormatElement::IfElse(if_else) => {
+                            let holds = evaluate_condition(
+                                &if_else.condition,
+                                variables,
+                                style_variables,
+                                context,
+                            )?;
+                            let branch = if holds {
+                                Some(if_else.then)
+                            } else {
+                                if_else.otherwise
+                            };
+                            branch.map_or_else(
+                                || Ok(Vec::new()),
+                                |textgroup| {
+                                    parse_textgroup(textgroup, variables, style_variables, context)
+                                },
+                            )
+                        }
                     }
                 })
                 .collect();
             Ok(results?.into_iter().flatten().collect())
         }
 
+        // `${env:NAME}` reads an environment variable, unless it was mapped to another value
+        let mut variables = self.variables;
+        for (name, value) in variables.iter_mut().filter(|(_, value)| value.is_none()) {
+            if let Some(env_name) = name.strip_prefix("env:") {
+                *value = context
+                    .and_then(|context| context.get_env(env_name))
+                    .map(|env_value| Ok(VariableValue::Plain(env_value.into())));
+            }
+        }
+
         parse_format(
             self.format,
             default_style,
-            &self.variables,
+            &variables,
             &self.style_variables,
             context,
         )
@@ -420,6 +554,126 @@ impl StyleVariableHolder<String> for StringFormatter<'_> {
     }
 }
 
+/// Returns true if the filters only provide fallbacks and the variable isn't empty,
+/// so that the variable can keep its own styles
+fn only_unused_fallbacks(
+    name: &str,
+    filters: &[Filter],
+    variables: &VariableMapType,
+    style_variables: &StyleVariableMapType,
+    context: Option<&Context>,
+) -> bool {
+    filters.iter().all(|filter| filter.name == "default")
+        && variable_text(name, variables, style_variables, context)
+            .is_ok_and(|text| !text.is_empty())
+}
+
+/// Returns true if a variable of the format string is empty, but rendered as a non-empty
+/// fallback by its `default` filter
+fn shows_fallback(
+    format: &[FormatElement],
+    variables: &VariableMapType,
+    style_variables: &StyleVariableMapType,
+    context: Option<&Context>,
+) -> bool {
+    format.iter().any(|element| match element {
+        FormatElement::Variable(name, filters) => {
+            filters.iter().any(|filter| filter.name == "default")
+                && variable_text(name, variables, style_variables, context)
+                    .and_then(|text| apply_filters(text, filters))
+                    .is_ok_and(|text| !text.is_empty())
+        }
+        FormatElement::TextGroup(textgroup) => {
+            shows_fallback(&textgroup.format, variables, style_variables, context)
+        }
+        FormatElement::Conditional(format) => {
+            shows_fallback(format, variables, style_variables, context)
+        }
+        FormatElement::IfElse(if_else) => {
+            let branch =
+                match evaluate_condition(&if_else.condition, variables, style_variables, context) {
+                    Ok(true) => Some(&if_else.then),
+                    Ok(false) => if_else.otherwise.as_ref(),
+                    Err(_) => None,
+                };
+            branch.is_some_and(|textgroup| {
+                shows_fallback(&textgroup.format, variables, style_variables, context)
+            })
+        }
+        FormatElement::Text(_) => false,
+    })
+}
+
+/// Evaluates the condition of an `$if(...)` with the current values of the variables
+fn evaluate_condition(
+    condition: &Condition,
+    variables: &VariableMapType,
+    style_variables: &StyleVariableMapType,
+    context: Option<&Context>,
+) -> Result<bool, StringFormatterError> {
+    let value = |operand: &Operand| match operand {
+        Operand::Literal(literal) => Ok(literal.to_string()),
+        Operand::Variable(name, filters) => apply_filters(
+            variable_text(name, variables, style_variables, context)?,
+            filters,
+        ),
+    };
+    let left = value(&condition.left)?;
+    let Some((comparator, right)) = &condition.comparison else {
+        return Ok(!left.is_empty());
+    };
+    let right = value(right)?;
+
+    let numbers = left
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .zip(right.trim().parse::<f64>().ok());
+    let matches = || {
+        regex::Regex::new(&right)
+            .map(|regex| regex.is_match(&left))
+            .map_err(|error| {
+                StringFormatterError::Custom(format!("Invalid regex {right:?}: {error}"))
+            })
+    };
+    Ok(match comparator {
+        Comparator::Equal => numbers.map_or(left == right, |(l, r)| l == r),
+        Comparator::NotEqual => numbers.map_or(left != right, |(l, r)| l != r),
+        // Only numbers can be ordered
+        Comparator::Greater => numbers.is_some_and(|(l, r)| l > r),
+        Comparator::GreaterOrEqual => numbers.is_some_and(|(l, r)| l >= r),
+        Comparator::Less => numbers.is_some_and(|(l, r)| l < r),
+        Comparator::LessOrEqual => numbers.is_some_and(|(l, r)| l <= r),
+        Comparator::Match => matches()?,
+        Comparator::NotMatch => !matches()?,
+    })
+}
+
+/// Returns the text a variable would render to, or an empty string if it is not set
+fn variable_text(
+    name: &str,
+    variables: &VariableMapType,
+    style_variables: &StyleVariableMapType,
+    context: Option<&Context>,
+) -> Result<String, StringFormatterError> {
+    let Some(value) = variables.get(name).and_then(Option::as_ref) else {
+        return Ok(String::new());
+    };
+    let segments = match value.clone()? {
+        VariableValue::Plain(text) | VariableValue::NoEscapingPlain(text) => {
+            return Ok(text.into_owned());
+        }
+        VariableValue::Styled(segments) => segments,
+        VariableValue::Meta(format) => StringFormatter {
+            format,
+            variables: clone_without_meta(variables),
+            style_variables: style_variables.clone(),
+        }
+        .parse(None, context)?,
+    };
+    Ok(segments.iter().map(Segment::value).collect())
+}
+
 fn clone_without_meta<'a>(variables: &VariableMapType<'a>) -> VariableMapType<'a> {
     variables
         .iter()
@@ -437,6 +691,24 @@ fn clone_without_meta<'a>(variables: &VariableMapType<'a>) -> VariableMapType<'a
         .collect()
 }
 
+/// Splits the `link:<url>` token off a style string, since the URL is case-sensitive and
+/// isn't part of the style. An empty URL, e.g. from an unset variable, means no link.
+fn split_link(style_string: &str) -> (String, Option<String>) {
+    let mut link = None;
+    let style_string = style_string
+        .split_whitespace()
+        .filter(|token| match token.strip_prefix("link:") {
+            Some(url) => {
+                link = Some(url).filter(|url| !url.is_empty()).map(str::to_string);
+                false
+            }
+            None => true,
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    (style_string, link)
+}
+
 /// Escape interpretable characters for the shell prompt
 pub fn shell_prompt_escape<T>(text: T, shell: Shell) -> String
 where
@@ -565,6 +837,36 @@ mod tests {
         match_next!(result_iter, "inner", inner_style);
     }
 
+    #[test]
+    fn test_link_in_style() {
+        const FORMAT_STR: &str =
+            "[outer [inner](link:$inner)](red link:$outer) [none](link:$unset)";
+
+        let formatter = StringFormatter::new(FORMAT_STR)
+            .unwrap()
+            .map_style(|variable| match variable {
+                "outer" => Some(Ok("https://example.com/Outer")),
+                "inner" => Some(Ok("file:///inner")),
+                "unset" => Some(Ok("")),
+                _ => None,
+            });
+        let result = formatter.parse(None, None).unwrap();
+        let links = result
+            .iter()
+            .map(|segment| (segment.value(), segment.link()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            vec![
+                ("outer ", Some("https://example.com/Outer")),
+                ("inner", Some("file:///inner")),
+                (" ", None),
+                ("none", None),
+            ]
+        );
+        assert_eq!(result[0].style(), Some(Color::Red.normal()));
+    }
+
     #[test]
     fn test_style_variable_nested() {
         const STYLE_VAR_NAME: &str = "style";
@@ -786,6 +1088,248 @@ mod tests {
         match_next!(result_iter, "$some", None);
     }
 
+    #[test]
+    fn test_if_else() {
+        let render = |format: &str| {
+            StringFormatter::new(format)
+                .unwrap()
+                .map(|var| match var {
+                    "ahead" => Some(Ok("3")),
+                    "behind" => Some(Ok("0")),
+                    "context" => Some(Ok("prod-eu")),
+                    _ => None,
+                })
+                .parse(None, None)
+                .unwrap()
+                .iter()
+                .map(Segment::value)
+                .collect::<String>()
+        };
+
+        assert_eq!(render("$if($ahead > 0)[⇡$ahead]()$else[≡]()"), "⇡3");
+        assert_eq!(render("$if($behind > 0)[⇣$behind]()$else[≡]()"), "≡");
+        assert_eq!(render("$if($behind>=0)[yes]()"), "yes");
+        assert_eq!(render("$if( $ahead <= 2 )[yes]()"), "");
+        assert_eq!(render("$if($context == 'prod-eu')[!]()"), "!");
+        assert_eq!(render(r#"$if($context != "prod-eu")[!]()"#), "");
+        assert_eq!(render("$if($context =~ '^prod')[!]()$else[ok]()"), "!");
+        assert_eq!(render("$if($context !~ ^prod)[!]()$else[ok]()"), "ok");
+        assert_eq!(render("$if($ahead == $ahead)[same]()"), "same");
+        // A single operand holds if it isn't empty
+        assert_eq!(render("$if($context)[set]()"), "set");
+        assert_eq!(render("$if($unknown)[set]()$else[unset]()"), "unset");
+        // Ordering is only defined for numbers
+        assert_eq!(render("$if($context > 0)[yes]()$else[no]()"), "no");
+    }
+
+    #[test]
+    fn test_if_else_style() {
+        const FORMAT_STR: &str = "$if($var == 1)[one](red)$else[other](green)";
+        let formatter = |value: &'static str| {
+            StringFormatter::new(FORMAT_STR)
+                .unwrap()
+                .map(move |var| match var {
+                    "var" => Some(Ok(value)),
+                    _ => None,
+                })
+        };
+
+        let result = formatter("1").parse(None, None).unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "one", Some(Color::Red.normal()));
+
+        let result = formatter("2").parse(None, None).unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "other", Some(Color::Green.normal()));
+    }
+
+    #[test]
+    fn test_if_else_variables() {
+        const FORMAT_STR: &str = "$if($a > $b)[$c]($s)$else[$d]($t) $if(x == 'y')[]()";
+
+        let formatter = StringFormatter::new(FORMAT_STR).unwrap();
+        assert_eq!(formatter.get_variables_in_order(), vec!["a", "b", "c", "d"]);
+        assert_eq!(
+            formatter.get_style_variables(),
+            ["s", "t"].into_iter().map(String::from).collect()
+        );
+    }
+
+    #[test]
+    fn test_if_else_invalid_regex() {
+        let result = StringFormatter::new("$if($var =~ '(')[x]()")
+            .unwrap()
+            .map(|_| Some(Ok("")))
+            .parse(None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_variable_filters() {
+        let render = |format: &str| {
+            StringFormatter::new(format)
+                .unwrap()
+                .map(|var| match var {
+                    "branch" => Some(Ok("feature/Login")),
+                    "context" => Some(Ok("production-eu-west")),
+                    "empty" => Some(Ok("")),
+                    _ => None,
+                })
+                .parse(None, None)
+                .unwrap()
+                .iter()
+                .map(Segment::value)
+                .collect::<String>()
+        };
+
+        assert_eq!(render("$branch|upper"), "FEATURE/LOGIN");
+        assert_eq!(render("$branch|basename|lower"), "login");
+        assert_eq!(render("[$context|truncate(10)](red)"), "production…");
+        assert_eq!(render("$context|truncate( 4 , '~' )"), "prod~");
+        assert_eq!(render(r#"$branch|replace("^feature/", "f:")"#), "f:Login");
+        assert_eq!(
+            render("$empty|default(none) $unknown|default('n/a')"),
+            "none n/a"
+        );
+        assert_eq!(render("${branch}|lower"), "feature/login");
+        // Unknown filter names are text
+        assert_eq!(render("$branch|nope"), "feature/Login|nope");
+        assert_eq!(render("$branch|uppercase"), "feature/Login|uppercase");
+        assert_eq!(render("$if($branch|lower =~ login)[yes]()"), "yes");
+    }
+
+    #[test]
+    fn test_styled_variable_filters() {
+        let formatter = |format| {
+            StringFormatter::new(format)
+                .unwrap()
+                .map_variables_to_segments(|var| match var {
+                    "module" => Some(Ok(Segment::from_text(Some(Color::Red.into()), "module"))),
+                    "empty" => Some(Ok(Vec::new())),
+                    _ => None,
+                })
+        };
+
+        let result = formatter("$module|upper").parse(None, None);
+        assert_eq!(
+            result.err().map(|error| error.to_string()).as_deref(),
+            Some("Filters can only be applied to text variables, but $module is styled")
+        );
+
+        // Fallbacks don't change the value of styled variables
+        let result = formatter("$module|default(none) $empty|default(none)")
+            .parse(None, None)
+            .unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "module", Some(Color::Red.normal()));
+        match_next!(result_iter, " ", None);
+        match_next!(result_iter, "none", None);
+    }
+
+    #[test]
+    fn test_variable_filter_errors() {
+        let result = StringFormatter::new("$var|truncate(a)")
+            .unwrap()
+            .map(|_| Some(Ok("text")))
+            .parse(None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_style_variable_filters() {
+        const FORMAT_STR: &str = "[text]($style|default(red))";
+        let result = StringFormatter::new(FORMAT_STR)
+            .unwrap()
+            .map_style(|_| None::<Result<&str, StringFormatterError>>)
+            .parse(None, None)
+            .unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "text", Some(Color::Red.normal()));
+    }
+
+    #[test]
+    fn test_variable_fallback() {
+        let mut context = Context::default();
+        context.env.insert("HOST", "box".to_string());
+        let render = |format: &str| {
+            StringFormatter::new(format)
+                .unwrap()
+                .map(|var| match var {
+                    "namespace" => Some(Ok("kube-system")),
+                    "empty" => Some(Ok("")),
+                    _ => None,
+                })
+                .parse(None, Some(&context))
+                .unwrap()
+                .iter()
+                .map(Segment::value)
+                .collect::<String>()
+        };
+
+        assert_eq!(render("${namespace:-default}"), "kube-system");
+        assert_eq!(render("${empty:-default}"), "default");
+        assert_eq!(render("${unknown:-default}"), "default");
+        assert_eq!(render("${empty:-}"), "");
+        assert_eq!(render("${env:HOST}"), "box");
+        assert_eq!(render("${env:HOST:-localhost}"), "box");
+        assert_eq!(render("${env:UNSET:-localhost}"), "localhost");
+        assert_eq!(render("${empty:-dev}|upper"), "DEV");
+        // A variable falling back to a non-empty value is never empty
+        assert_eq!(render("(on ${empty:-default})"), "on default");
+        assert_eq!(render("(on ${empty})"), "");
+    }
+
+    #[test]
+    fn test_conditional_without_used_fallbacks_collapses() {
+        let render = |format: &str| {
+            StringFormatter::new(format)
+                .unwrap()
+                .map(|var| match var {
+                    "empty" => Some(Ok("")),
+                    _ => None,
+                })
+                .parse(None, None)
+                .unwrap()
+                .iter()
+                .map(Segment::value)
+                .collect::<String>()
+        };
+
+        assert_eq!(render("(on $empty|upper)"), "");
+        assert_eq!(render("(on ${empty:-})"), "");
+        assert_eq!(render("(on ${empty:-x}|replace('x', ''))"), "");
+        assert_eq!(render("(on $empty$if($empty)[${empty:-x}]())"), "");
+        assert_eq!(
+            render("(on $empty$if($empty)[]()$else[${empty:-x}]())"),
+            "on x"
+        );
+    }
+
+    #[test]
+    fn test_variable_fallback_keeps_styles() {
+        const FORMAT_STR: &str = "[${var:-fallback}](red)";
+        let styled = Some(Color::Green.normal());
+
+        let result = StringFormatter::new(FORMAT_STR)
+            .unwrap()
+            .map_variables_to_segments(|variable| match variable {
+                "var" => Some(Ok(Segment::from_text(styled.map(Into::into), "value"))),
+                _ => None,
+            })
+            .parse(None, None)
+            .unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "value", styled);
+
+        let result = StringFormatter::new(FORMAT_STR)
+            .unwrap()
+            .map_variables_to_segments(|_| Some(Ok(Vec::new())))
+            .parse(None, None)
+            .unwrap();
+        let mut result_iter = result.iter();
+        match_next!(result_iter, "fallback", Some(Color::Red.normal()));
+    }
+
     #[test]
     fn test_conditional_meta_variable() {
         const FORMAT_STR: &str = r"(\[$all\]) ";
@@ -814,6 +1358,14 @@ mod tests {
         assert_eq!(variables, expected_variables);
     }
 
+    #[test]
+    fn test_variables_in_order() {
+        const FORMAT_STR: &str = "$d ($b [($a) $b](none $s)) $c";
+
+        let formatter = StringFormatter::new(FORMAT_STR).unwrap();
+        assert_eq!(formatter.get_variables_in_order(), vec!["d", "b", "a", "c"]);
+    }
+
     #[test]
     fn test_style_variable_holder() {
         const FORMAT_STR: &str = "($a [($b) $c](none $s)) $d [t]($t)";
@@ -24,6 +24,10 @@ starship_preexec() {
     if [ "${STARSHIP_PREEXEC_READY:-}" = "true" ]; then
         STARSHIP_PREEXEC_READY=false
         STARSHIP_START_TIME=$(::STARSHIP:: time)
+        # Mark the start of the command output (OSC 133 ; C), if the prompt was marked
+        if [[ -n "${STARSHIP_SEMANTIC_PROMPT-}" ]]; then
+            printf '\e]133;C\a'
+        fi
     fi
 
     : "$PREV_LAST_ARG"
@@ -78,6 +82,12 @@ starship_precmd() {
         STARSHIP_START_TIME=""
     fi
     PS1="$(::STARSHIP:: prompt "${ARGS[@]}")"
+    # The prompt carries OSC 133 markers if the `semantic_prompt` option is set
+    if [[ "$PS1" == *']133;B'* ]]; then
+        STARSHIP_SEMANTIC_PROMPT=true
+    else
+        STARSHIP_SEMANTIC_PROMPT=
+    fi
     if [[ ${BLE_ATTACHED-} ]]; then
         local nlns=${PS1//[!$'\n']}
         bleopt prompt_rps1="$nlns$(::STARSHIP:: prompt --right "${ARGS[@]}")"
@@ -108,6 +118,11 @@ else
         if [[ "${PS0-}" != *"starship_preexec_ps0"* ]]; then
             PS0='${STARSHIP_START_TIME:$((STARSHIP_START_TIME="$(starship_preexec_ps0)",STARSHIP_PREEXEC_READY=0,0)):0}'"${PS0-}"
         fi
+        # PS0 is printed right before the command runs, so it also carries the OSC 133 ; C marker
+        # if the prompt was marked. Its escapes are decoded before the variable is expanded.
+        if [[ "${PS0-}" != *'STARSHIP_SEMANTIC_PROMPT'* ]]; then
+            PS0="${PS0-}"'${STARSHIP_SEMANTIC_PROMPT:+\e]133;C\a}'
+        fi
     else
         # We want to avoid destroying an existing DEBUG hook. If we detect one, create
         # a new function that runs both the existing function AND our function, then
@@ -150,6 +165,10 @@ STARSHIP_SESSION_KEY="$RANDOM$RANDOM$RANDOM$RANDOM$RANDOM"; # Random generates a
 STARSHIP_SESSION_KEY="${STARSHIP_SESSION_KEY}0000000000000000" # Pad it to 16+ chars.
 export STARSHIP_SESSION_KEY=${STARSHIP_SESSION_KEY:0:16}; # Trim to 16-digits if excess.
 
+# Ask the terminal for its background color once per session, for `palette_light` and `palette_dark`.
+# Starship only queries the terminal if one of them is set, and prints nothing otherwise.
+export STARSHIP_TERMINAL_THEME="$(::STARSHIP:: theme --query)"
+
 # Set the continuation prompt
 PS2="$(::STARSHIP:: prompt --continuation)"
 
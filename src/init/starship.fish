@@ -38,7 +38,9 @@ function fish_prompt
             printf "\e[1;32m❯\e[0m "
         end
     else
-        ::STARSHIP:: prompt --terminal-width="$COLUMNS" --status=$STARSHIP_CMD_STATUS --pipestatus="$STARSHIP_CMD_PIPESTATUS" --keymap=$STARSHIP_KEYMAP --cmd-duration=$STARSHIP_DURATION --jobs=$STARSHIP_JOBS
+        # The prompt is kept to check for OSC 133 markers before a command runs
+        set -g STARSHIP_PROMPT (::STARSHIP:: prompt --terminal-width="$COLUMNS" --status=$STARSHIP_CMD_STATUS --pipestatus="$STARSHIP_CMD_PIPESTATUS" --keymap=$STARSHIP_KEYMAP --cmd-duration=$STARSHIP_DURATION --jobs=$STARSHIP_JOBS | string collect)
+        printf "%s" $STARSHIP_PROMPT
     end
 end
 
@@ -117,6 +119,16 @@ function __starship_fish_version_at_least --description 'Check if fish version i
     end
 end
 
+# Mark the start of the command output (OSC 133 ; C), if the prompt was marked by the
+# `semantic_prompt` option. fish >= 4.0 emits this marker itself
+if not __starship_fish_version_at_least 4.0
+    function __starship_mark_command_start --on-event fish_preexec
+        if string match -q -- '*]133;B*' "$STARSHIP_PROMPT"
+            printf "\e]133;C\a"
+        end
+    end
+end
+
 # --user is the default, but listed anyway to make it explicit.
 function enable_transience --description 'enable transient prompt keybindings'
     # fish >= 4.1 has transient prompt support built
@@ -144,3 +156,7 @@ end
 # Set up the session key that will be used to store logs
 # We don't use `random [min] [max]` because it is unavailable in older versions of fish shell
 set -gx STARSHIP_SESSION_KEY (string sub -s1 -l16 (random)(random)(random)(random)(random)0000000000000000)
+
+# Ask the terminal for its background color once per session, for `palette_light` and `palette_dark`.
+# Starship only queries the terminal if one of them is set, and prints nothing otherwise.
+set -gx STARSHIP_TERMINAL_THEME (::STARSHIP:: theme --query)
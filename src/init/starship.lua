@@ -9,6 +9,9 @@ end_time = 0
 curr_duration = 0
 is_line_empty = true
 
+-- Whether the last prompt was marked by the `semantic_prompt` option
+local semantic_prompt = false
+
 clink.onbeginedit(function ()
   end_time = os.clock()
   if not is_line_empty then
@@ -21,6 +24,10 @@ clink.onendedit(function (curr_line)
     starship_precmd_user_func(curr_line)
   end
   start_time = os.clock()
+  -- Mark the start of the command output (OSC 133 ; C), if the prompt was marked
+  if semantic_prompt then
+    clink.print("\x1b]133;C\a", NONL)
+  end
   if string.len(string.gsub(curr_line, '^%s*(.-)%s*$', '%1')) == 0 then
     is_line_empty = true
   else
@@ -32,12 +39,14 @@ function starship_prompt:filter(prompt)
   if starship_preprompt_user_func ~= nil then
     starship_preprompt_user_func(prompt)
   end
-  return io.popen([[::STARSHIP::]].." prompt"
+  local output = io.popen([[::STARSHIP::]].." prompt"
     .." --status="..os.geterrorlevel()
     .." --cmd-duration="..math.floor(curr_duration*1000)
     .." --terminal-width="..console.getwidth()
     .." --keymap="..rl.getvariable('keymap')
   ):read("*a")
+  semantic_prompt = string.find(output, "]133;B", 1, true) ~= nil
+  return output
 end
 
 function starship_prompt:rightfilter(prompt)
@@ -0,0 +1,192 @@
+//! Machine-readable output of rendered modules, shared by the `--json` options of
+//! `starship prompt`, `starship module`, `starship explain` and `starship timings`.
+
+use nu_ansi_term::{Color, Style};
+use serde::Serialize;
+
+use crate::module::Module;
+
+/// A rendered module
+#[derive(Serialize)]
+pub struct ModuleJson<'a> {
+    name: &'a str,
+    description: &'a str,
+    segments: Vec<SegmentJson>,
+    duration_ms: f64,
+    empty: bool,
+}
+
+/// A piece of text of a module with its resolved style
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SegmentJson {
+    text: String,
+    fg: Option<String>,
+    bg: Option<String>,
+    attributes: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+}
+
+impl<'a> From<&'a Module<'_>> for ModuleJson<'a> {
+    fn from(module: &'a Module<'_>) -> Self {
+        Self {
+            name: module.get_name(),
+            description: module.get_description(),
+            // `ansi_strings` resolves `prev_fg` and `prev_bg` to the actual colors
+            segments: module
+                .ansi_strings()
+                .iter()
+                .map(|string| {
+                    let mut segment = SegmentJson::new(string.as_str(), *string.style_ref());
+                    segment.link = string.url_string().map(str::to_string);
+                    segment
+                })
+                .collect(),
+            duration_ms: module.duration.as_secs_f64() * 1000.0,
+            empty: module.is_empty(),
+        }
+    }
+}
+
+impl SegmentJson {
+    fn new(text: &str, style: Style) -> Self {
+        let attributes = [
+            (style.is_bold, "bold"),
+            (style.is_dimmed, "dimmed"),
+            (style.is_italic, "italic"),
+            (style.is_underline, "underline"),
+            (style.is_blink, "blink"),
+            (style.is_reverse, "inverted"),
+            (style.is_hidden, "hidden"),
+            (style.is_strikethrough, "strikethrough"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect();
+
+        Self {
+            text: text.to_string(),
+            fg: style.foreground.and_then(color_name),
+            bg: style.background.and_then(color_name),
+            attributes,
+            link: None,
+        }
+    }
+}
+
+/// Returns the name of a color as it would be written in the configuration
+fn color_name(color: Color) -> Option<String> {
+    let name = match color {
+        Color::Black => "black",
+        Color::Red => "red",
+        Color::Green => "green",
+        Color::Yellow => "yellow",
+        Color::Blue => "blue",
+        Color::Purple | Color::Magenta => "purple",
+        Color::Cyan => "cyan",
+        Color::White => "white",
+        Color::DarkGray => "bright-black",
+        Color::LightRed => "bright-red",
+        Color::LightGreen => "bright-green",
+        Color::LightYellow => "bright-yellow",
+        Color::LightBlue => "bright-blue",
+        Color::LightPurple | Color::LightMagenta => "bright-purple",
+        Color::LightCyan => "bright-cyan",
+        Color::LightGray => "bright-white",
+        Color::Fixed(n) => return Some(n.to_string()),
+        Color::Rgb(r, g, b) => return Some(format!("#{r:02x}{g:02x}{b:02x}")),
+        Color::Default => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Serializes a list of modules into a JSON array
+pub fn modules_to_string(modules: &[Module]) -> String {
+    let modules = modules.iter().map(ModuleJson::from).collect::<Vec<_>>();
+    serde_json::to_string(&modules).expect("modules should serialize to JSON")
+}
+
+/// Serializes a single module, or `null` if there is none
+pub fn module_to_string(module: Option<&Module>) -> String {
+    serde_json::to_string(&module.map(ModuleJson::from)).expect("module should serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_style_string;
+    use crate::segment::Segment;
+
+    #[test]
+    fn segment_style_is_resolved() {
+        let style = Color::Fixed(8)
+            .on(Color::Rgb(0x12, 0xab, 0xff))
+            .bold()
+            .italic();
+        assert_eq!(
+            SegmentJson::new("text", style),
+            SegmentJson {
+                text: "text".to_string(),
+                fg: Some("8".to_string()),
+                bg: Some("#12abff".to_string()),
+                attributes: vec!["bold", "italic"],
+                link: None,
+            }
+        );
+        assert_eq!(
+            SegmentJson::new("", Style::new()),
+            SegmentJson {
+                text: String::new(),
+                fg: None,
+                bg: None,
+                attributes: vec![],
+                link: None,
+            }
+        );
+    }
+
+    #[test]
+    fn module_json() {
+        let mut module = Module::new("test", "A test module", None);
+        let mut segments = Segment::from_text(parse_style_string("red", None), "a");
+        segments.extend(Segment::from_text(
+            parse_style_string("fg:prev_fg bg:bright-black", None),
+            "b",
+        ));
+        module.set_segments(segments);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&module_to_string(Some(&module))).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "test",
+                "description": "A test module",
+                "segments": [
+                    { "text": "a", "fg": "red", "bg": null, "attributes": [] },
+                    { "text": "b", "fg": "red", "bg": "bright-black", "attributes": [] },
+                ],
+                "duration_ms": 0.0,
+                "empty": false,
+            })
+        );
+        assert_eq!(module_to_string(None), "null");
+    }
+
+    #[test]
+    fn module_json_link() {
+        let mut module = Module::new("test", "A test module", None);
+        let mut segments = Segment::from_text(None, "a");
+        segments[0].set_link_if_empty("https://example.com");
+        module.set_segments(segments);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&module_to_string(Some(&module))).unwrap();
+        assert_eq!(
+            json["segments"],
+            serde_json::json!([
+                { "text": "a", "fg": null, "bg": null, "attributes": [], "link": "https://example.com" },
+            ])
+        );
+    }
+}
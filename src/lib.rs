@@ -9,12 +9,18 @@ shadow!(shadow);
 
 // Lib is present to allow for benchmarking
 pub mod bug_report;
+pub mod cache;
 pub mod config;
+pub mod config_check;
+pub mod config_migrate;
 pub mod configs;
 pub mod configure;
+pub mod configure_wizard;
 pub mod context;
+pub mod daemon;
 pub mod formatter;
 pub mod init;
+mod json;
 pub mod logger;
 pub mod module;
 mod modules;
@@ -3,14 +3,19 @@
 use clap::crate_authors;
 use std::io;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::generate;
 use rand::RngExt;
+use starship::config::Theme;
 use starship::context::{Context, Properties, Target};
 use starship::module::ALL_MODULES;
-use starship::{bug_report, configure, init, logger, num_rayon_threads, print, shadow};
+use starship::print::OutputFormat;
+use starship::{
+    bug_report, cache, config_check, config_migrate, configure, configure_wizard, daemon, init,
+    logger, num_rayon_threads, print, shadow,
+};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -63,25 +68,99 @@ enum Statuslines {
     ClaudeCode,
 }
 
+#[derive(Subcommand, Debug)]
+enum CacheCommands {
+    /// Delete all cached data
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+enum PresetCommands {
+    /// Write a preset to the configuration file
+    Apply {
+        /// The name of the preset
+        #[clap(value_enum)]
+        name: print::Preset,
+        /// Merge the preset into the existing configuration, keeping the options it already sets
+        #[clap(long)]
+        merge: bool,
+        /// Replace the existing configuration file
+        #[clap(short, long, conflicts_with = "merge")]
+        force: bool,
+    },
+    /// Print the prompt of presets for a sample project, to compare them
+    Preview {
+        /// The names of the presets
+        #[clap(required = true, value_enum)]
+        names: Vec<print::Preset>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Check the configuration file for unknown keys and invalid values
+    Check,
+    /// Move renamed options in the configuration file to their new names
+    Migrate,
+    /// Print the value of a configuration key, including default values
+    Get {
+        /// Configuration key to print, like `git_branch.symbol`
+        name: String,
+    },
+    /// Remove a key from the configuration file
+    Unset {
+        /// Configuration key to remove, like `git_branch.symbol`
+        name: String,
+    },
+    /// Print every configuration key with its value
+    List {
+        /// Only print keys that differ from the default configuration
+        #[clap(long)]
+        changed: bool,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Create a pre-populated GitHub issue with information about your configuration
     BugReport,
+    /// Manage the data starship caches between prompts
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommands,
+    },
     /// Generate starship shell completions for your shell to stdout
     Completions {
         #[clap(value_enum)]
         shell: CompletionShell,
     },
     /// Edit the starship configuration
+    #[clap(args_conflicts_with_subcommands = true)]
     Config {
+        #[clap(subcommand)]
+        command: Option<ConfigCommands>,
         /// Configuration key to edit
         #[clap(requires = "value")]
         name: Option<String>,
         /// Value to place into that key
         value: Option<String>,
     },
+    /// Walk through the modules of the prompt and configure them interactively
+    Configure,
+    /// Run a background server that renders prompts for shells sharing the current environment
+    Daemon {
+        /// Exit after this many seconds without a prompt request, never exit if set to 0
+        #[clap(long, default_value_t = 3600)]
+        idle_timeout: u64,
+    },
     /// Explains the currently showing modules
-    Explain(Properties),
+    Explain {
+        /// Print the modules as JSON
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        properties: Properties,
+    },
     ///  Prints the shell function used to execute starship
     Init {
         shell: String,
@@ -96,11 +175,17 @@ enum Commands {
         /// List out all supported modules
         #[clap(short, long)]
         list: bool,
+        /// Print the module as JSON
+        #[clap(long, conflicts_with = "list")]
+        json: bool,
         #[clap(flatten)]
         properties: Properties,
     },
     /// Prints a preset config
+    #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Preset {
+        #[clap(subcommand)]
+        command: Option<PresetCommands>,
         /// The name of preset to be printed
         #[clap(required_unless_present("list"), value_enum)]
         name: Option<print::Preset>,
@@ -127,12 +212,12 @@ enum Commands {
         /// Print the right prompt (instead of the standard left prompt)
         #[clap(long)]
         right: bool,
-        /// Print the prompt with the specified profile name (instead of the standard left prompt)
-        #[clap(long, conflicts_with = "right")]
-        profile: Option<String>,
         /// Print the continuation prompt (instead of the standard left prompt)
-        #[clap(long, conflicts_with = "right", conflicts_with = "profile")]
+        #[clap(long, conflicts_with = "right")]
         continuation: bool,
+        /// The format to print the prompt in
+        #[clap(long, value_enum, default_value_t)]
+        output: OutputFormat,
         #[clap(flatten)]
         properties: Properties,
     },
@@ -142,16 +227,26 @@ enum Commands {
     Statusline {
         /// The statusline provider to use
         provider: Statuslines,
-        #[clap(long)]
-        profile: Option<String>,
         #[clap(flatten)]
         properties: Properties,
     },
+    /// Print whether the terminal has a light or a dark background, as used for `palette_light` and `palette_dark`
+    Theme {
+        /// Ask the terminal for its background color, if `palette_light` or `palette_dark` is set
+        #[clap(long)]
+        query: bool,
+    },
     /// Prints time in milliseconds
     #[clap(hide = true)]
     Time,
     /// Prints timings of all active modules
-    Timings(Properties),
+    Timings {
+        /// Print the modules and their timings as JSON
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        properties: Properties,
+    },
     /// Toggle a given starship module
     Toggle {
         /// The name of the module to be toggled
@@ -227,20 +322,22 @@ fn main() {
         Commands::Prompt {
             properties,
             right,
-            profile,
             continuation,
+            output,
         } => {
-            let target = match (right, profile, continuation) {
+            // A profile's own formats are used for `--right` and `--continuation`
+            let target = match (right, properties.profile.clone(), continuation) {
                 (true, _, _) => Target::Right,
-                (_, Some(profile_name), _) => Target::Profile(profile_name),
                 (_, _, true) => Target::Continuation,
+                (_, Some(profile_name), _) => Target::Profile(profile_name),
                 (_, _, _) => Target::Main,
             };
-            print::prompt(properties, target);
+            print::prompt(properties, target, output);
         }
         Commands::Module {
             name,
             list,
+            json,
             properties,
         } => {
             if list {
@@ -251,16 +348,60 @@ fn main() {
                 }
             }
             if let Some(module_name) = name {
-                print::module(&module_name, properties);
+                print::module(&module_name, properties, output_format(json));
             }
         }
         Commands::Preset {
+            command: Some(PresetCommands::Apply { name, merge, force }),
+            ..
+        } => configure::apply_preset(&Context::default(), &name, merge, force),
+        Commands::Preset {
+            command: Some(PresetCommands::Preview { names }),
+            ..
+        } => print::preset_preview(&names),
+        Commands::Preset {
+            command: None,
             name,
             list,
             output,
             force,
         } => print::preset_command(name, output, force, list),
-        Commands::Config { name, value } => {
+        Commands::Configure => configure_wizard::run_wizard(&Context::default()),
+        Commands::Config {
+            command: Some(ConfigCommands::Check),
+            ..
+        } => {
+            if !config_check::check_configuration(&Context::default()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Config {
+            command: Some(ConfigCommands::Migrate),
+            ..
+        } => {
+            if !config_migrate::migrate_configuration(&Context::default()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Config {
+            command: Some(ConfigCommands::Get { name }),
+            ..
+        } => configure::print_configuration_value(&Context::default(), &name),
+        Commands::Config {
+            command: Some(ConfigCommands::Unset { name }),
+            ..
+        } => configure::unset_configuration(&Context::default(), &name),
+        Commands::Config {
+            command: Some(ConfigCommands::List { changed }),
+            ..
+        } => {
+            configure::list_configuration(&Context::default(), changed);
+        }
+        Commands::Config {
+            command: None,
+            name,
+            value,
+        } => {
             let context = Context::default();
             if let Some(name) = name {
                 if let Some(value) = value {
@@ -278,6 +419,20 @@ fn main() {
             configure::toggle_configuration(&Context::default(), &name, &value);
         }
         Commands::BugReport => bug_report::create(),
+        Commands::Cache {
+            command: CacheCommands::Clear,
+        } => {
+            if let Err(e) = cache::clear() {
+                eprintln!("Unable to clear cache: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::Daemon { idle_timeout } => {
+            if let Err(e) = daemon::serve(Duration::from_secs(idle_timeout)) {
+                eprintln!("Unable to run daemon: {e}");
+                std::process::exit(1);
+            }
+        }
         Commands::Time => {
             match SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
@@ -287,8 +442,23 @@ fn main() {
                 None => println!("{}", -1),
             }
         }
-        Commands::Explain(props) => print::explain(props),
-        Commands::Timings(props) => print::timings(props),
+        Commands::Theme { query } => {
+            let context = Context::default();
+            let theme = if query {
+                Theme::query(&context)
+            } else {
+                Theme::detect(&context)
+            };
+            if let Some(theme) = theme {
+                println!("{theme}");
+            }
+        }
+        Commands::Explain { json, properties } => {
+            print::explain(properties, output_format(json));
+        }
+        Commands::Timings { json, properties } => {
+            print::timings(properties, output_format(json));
+        }
         Commands::Completions { shell } => generate_completions(shell),
         Commands::Session => println!(
             "{}",
@@ -300,12 +470,14 @@ fn main() {
         ),
         Commands::Statusline {
             provider,
-            profile,
-            properties,
+            mut properties,
         } => {
-            let profile = profile.unwrap_or_else(|| match provider {
-                Statuslines::ClaudeCode => "claude-code".to_string(),
-            });
+            let profile = properties
+                .profile
+                .get_or_insert_with(|| match provider {
+                    Statuslines::ClaudeCode => "claude-code".to_string(),
+                })
+                .clone();
 
             let target = Target::Profile(profile);
             print::prompt_with_claude_code(properties, target);
@@ -322,3 +494,12 @@ fn init_global_threadpool() {
         .build_global()
         .expect("Failed to initialize worker thread pool");
 }
+
+/// Map the `--json` flag of a subcommand to the corresponding output format
+const fn output_format(json: bool) -> OutputFormat {
+    if json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Text
+    }
+}
@@ -82,6 +82,7 @@ pub fn module<'a>(name: &str, context: &'a Context) -> Option<Module<'a>> {
             })
             .map_style(|variable| match variable {
                 "style" => Some(Ok(config.style)),
+                "link" => Some(Ok(config.link)),
                 _ => None,
             });
 
@@ -190,6 +191,10 @@ fn shell_command(cmd: &str, config: &CustomConfig, context: &Context) -> Option<
         command.arg(cmd);
     }
 
+    if context.is_abandoned() {
+        log::debug!("Not running custom command {cmd:?} for a module which was abandoned");
+        return None;
+    }
     let mut child = match command.spawn() {
         Ok(child) => child,
         Err(error) => {
@@ -206,10 +211,10 @@ fn shell_command(cmd: &str, config: &CustomConfig, context: &Context) -> Option<
 
     let mut output = child.controlled_with_output();
 
-    if !config.ignore_timeout {
-        output = output
-            .time_limit(Duration::from_millis(context.root_config.command_timeout))
-            .terminate_for_timeout()
+    let timeout = (!config.ignore_timeout)
+        .then(|| Duration::from_millis(context.root_config.command_timeout));
+    if let Some(time_limit) = context.command_time_limit(timeout) {
+        output = output.time_limit(time_limit).terminate_for_timeout();
     }
 
     match output.wait().ok()? {
@@ -822,4 +827,26 @@ mod tests {
 
         dir.close()
     }
+
+    #[test]
+    fn link() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let actual = ModuleRenderer::new("custom.test")
+            .path(dir.path())
+            .config(toml::toml! {
+                [custom.test]
+                format = "[docs](link:$link)"
+                link = "https://starship.rs/config/#custom-commands"
+                when = true
+            })
+            .collect();
+        let expected = Some(
+            "\x1b]8;;https://starship.rs/config/#custom-commands\x1b\\docs\x1b]8;;\x1b\\"
+                .to_string(),
+        );
+        assert_eq!(expected, actual);
+
+        dir.close()
+    }
 }
@@ -153,6 +153,13 @@ pub fn module<'a>(context: &'a Context) -> Option<Module<'a>> {
                 "before_repo_root_style" => Some(Ok(before_repo_root_style)),
                 _ => None,
             })
+            .map_style(|variable| match variable {
+                "link" => Some(Ok(file_url(
+                    physical_dir,
+                    &whoami::hostname().unwrap_or_default(),
+                ))),
+                _ => None,
+            })
             .map(|variable| match variable {
                 "path" => Some(Ok(path_vec[2].as_str())),
                 "before_root_path" => Some(Ok(path_vec[0].as_str())),
@@ -198,6 +205,27 @@ fn remove_extended_path_prefix(path: String) -> String {
     path
 }
 
+/// Returns the `file://` URL of a directory. The hostname lets terminals tell
+/// whether the directory is on the local machine.
+fn file_url(path: &Path, hostname: &str) -> String {
+    let path = path
+        .to_slash_lossy()
+        .split('/')
+        .enumerate()
+        .map(|(i, component)| {
+            // Keep the colon of Windows drive letters
+            if i == 0 && component.ends_with(':') {
+                Cow::Borrowed(component)
+            } else {
+                urlencoding::encode(component)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let separator = if path.starts_with('/') { "" } else { "/" };
+    format!("file://{hostname}{separator}{path}")
+}
+
 fn is_readonly_dir(path: &Path) -> bool {
     match directory_utils::is_write_allowed(path) {
         Ok(res) => !res,
@@ -402,6 +430,40 @@ mod tests {
         assert_eq!(output, "~/schematics/rocket");
     }
 
+    #[test]
+    fn directory_file_url() {
+        assert_eq!(
+            file_url(Path::new("/home/astronaut/my rocket#1"), "host"),
+            "file://host/home/astronaut/my%20rocket%231"
+        );
+        assert_eq!(file_url(Path::new("/"), ""), "file:///");
+        #[cfg(windows)]
+        assert_eq!(
+            file_url(Path::new(r"C:\Users\astronaut"), "host"),
+            "file://host/C:/Users/astronaut"
+        );
+    }
+
+    #[test]
+    fn directory_link() -> io::Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let dir = tmp_dir.path().join("link");
+        fs::create_dir_all(&dir)?;
+
+        let actual = ModuleRenderer::new("directory")
+            .config(toml::toml! {
+                [directory]
+                format = "[$path](link:$link)"
+            })
+            .path(&dir)
+            .collect()
+            .unwrap();
+        let url = file_url(&dir, &whoami::hostname().unwrap_or_default());
+        assert!(actual.starts_with(&format!("\x1b]8;;{url}\x1b\\")));
+        assert!(actual.ends_with("/link\x1b]8;;\x1b\\"));
+        tmp_dir.close()
+    }
+
     #[test]
     fn contract_repo_directory() -> io::Result<()> {
         let tmp_dir = TempDir::new_in(home_dir().unwrap().as_path())?;
@@ -132,6 +132,22 @@ pub fn module<'a>(context: &'a Context) -> Option<Module<'a>> {
                 "style" => Some(Ok(config.style)),
                 _ => None,
             })
+            .map_style(|variable| match variable {
+                "link" => {
+                    let web_url = repo.remote_web_url()?;
+                    // A new local branch has no upstream yet
+                    let branch = Some(remote_branch_string.as_str())
+                        .filter(|branch| !branch.is_empty())
+                        .unwrap_or(&branch_name);
+                    let path = branch
+                        .split('/')
+                        .map(urlencoding::encode)
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    Some(Ok(format!("{web_url}/tree/{path}")))
+                }
+                _ => None,
+            })
             .map(|variable| match variable {
                 "branch" => Some(Ok(graphemes.concat())),
                 "remote_branch" => {
@@ -621,6 +637,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_remote_link() -> io::Result<()> {
+        test_link(None, "master", "master")
+    }
+
+    #[test]
+    fn test_link_without_upstream() -> io::Result<()> {
+        test_link(Some("feature/login"), "feature/login", "feature/login")
+    }
+
+    #[test]
+    fn test_link_with_special_characters() -> io::Result<()> {
+        test_link(Some("fix#12%"), "fix#12%", "fix%2312%25")
+    }
+
+    fn test_link(new_branch: Option<&str>, branch: &str, path: &str) -> io::Result<()> {
+        for &mode in COMMON_GIT_PROVIDERS {
+            let repo_dir = fixture_repo(mode)?;
+
+            create_command("git")?
+                .args(["remote", "set-url", "origin"])
+                .arg("https://github.com/starship/starship.git")
+                .current_dir(repo_dir.path())
+                .output()?;
+
+            if let Some(new_branch) = new_branch {
+                create_command("git")?
+                    .args(["checkout", "-b", new_branch])
+                    .current_dir(repo_dir.path())
+                    .output()?;
+            }
+
+            let actual = ModuleRenderer::new("git_branch")
+                .path(repo_dir.path())
+                .config(toml::toml! {
+                    [git_branch]
+                    format = "[$branch](link:$link)"
+                })
+                .collect();
+
+            let expected = format!(
+                "\x1b]8;;https://github.com/starship/starship/tree/{path}\x1b\\{branch}\x1b]8;;\x1b\\"
+            );
+
+            assert_eq!(Some(expected), actual);
+            repo_dir.close()?;
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_branch_fallback_on_detached() -> io::Result<()> {
         for &mode in COMMON_GIT_PROVIDERS {
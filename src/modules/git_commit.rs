@@ -27,6 +27,14 @@ pub fn module<'a>(context: &'a Context) -> Option<Module<'a>> {
                 "style" => Some(Ok(config.style)),
                 _ => None,
             })
+            .map_style(|variable| match variable {
+                "link" => {
+                    let web_url = repo.remote_web_url()?;
+                    let head_id = repo.open().head_id().ok()?.detach();
+                    Some(Ok(format!("{web_url}/commit/{head_id}")))
+                }
+                _ => None,
+            })
             .map(|variable| match variable {
                 "hash" => Some(Ok(git_hash(context.get_git_repo().ok()?, &config)?)),
                 "tag" if !config.tag_disabled => Some(Ok(format!(
@@ -136,6 +144,43 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_render_commit_link() -> io::Result<()> {
+        for &provider in COMMON_GIT_PROVIDERS {
+            let repo_dir = fixture_repo(provider)?;
+
+            create_command("git")?
+                .args(["remote", "set-url", "origin"])
+                .arg("git@github.com:starship/starship.git")
+                .current_dir(repo_dir.path())
+                .output()?;
+            let git_output = create_command("git")?
+                .args(["rev-parse", "HEAD"])
+                .current_dir(repo_dir.path())
+                .output()?
+                .stdout;
+            let hash = str::from_utf8(&git_output).unwrap().trim();
+
+            let actual = ModuleRenderer::new("git_commit")
+                .config(toml::toml! {
+                    [git_commit]
+                        only_detached = false
+                        format = "[$hash](link:$link)"
+                })
+                .path(repo_dir.path())
+                .collect();
+
+            let expected = Some(format!(
+                "\x1b]8;;https://github.com/starship/starship/commit/{hash}\x1b\\{}\x1b]8;;\x1b\\",
+                &hash[..7]
+            ));
+
+            assert_eq!(expected, actual);
+            repo_dir.close()?;
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_render_commit_hash_len_override() -> io::Result<()> {
         for &provider in COMMON_GIT_PROVIDERS {
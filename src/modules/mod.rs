@@ -99,7 +99,7 @@ mod swift;
 mod terraform;
 mod time;
 mod username;
-mod utils;
+pub(crate) mod utils;
 mod vagrant;
 mod vcs;
 mod vcsh;
@@ -120,6 +120,11 @@ use crate::module::Module;
 use std::time::Instant;
 
 pub fn handle<'a>(module: &str, context: &'a Context) -> Option<Module<'a>> {
+    if context.is_terminal_too_narrow_for_module(module) {
+        log::debug!("Skipping module {module}, the terminal is narrower than its min_width");
+        return None;
+    }
+
     let start: Instant = Instant::now();
     let mut m: Option<Module> = {
         match module {
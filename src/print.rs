@@ -1,26 +1,31 @@
 use clap::{ValueEnum, builder::PossibleValue};
+use indexmap::IndexMap;
 use nu_ansi_term::AnsiStrings;
 use rayon::prelude::*;
 use regex::Regex;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Debug, Write as FmtWrite};
+use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
-use std::sync::OnceLock;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, mpsc};
+use std::time::{Duration, Instant};
 use terminal_size::terminal_size;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthChar;
 
-use crate::configs::PROMPT_ORDER;
+use crate::config::{self, StarshipConfig};
+use crate::configs::{PROMPT_ORDER, Profile};
 use crate::context::{Context, Properties, Shell, Target};
+use crate::daemon;
 use crate::formatter::{StringFormatter, VariableHolder};
+use crate::json;
 use crate::module::ALL_MODULES;
 use crate::module::Module;
 use crate::modules;
 use crate::segment::Segment;
 use crate::shadow;
-use crate::utils::wrap_colorseq_for_shell;
+use crate::utils::{CommandOutput, env::Env, wrap_colorseq_for_shell};
 
 pub struct Grapheme<'a>(pub &'a str);
 
@@ -69,12 +74,26 @@ fn test_grapheme_aware_width() {
     assert_eq!(11, "\x1B[35;6mnormal text".width_graphemes());
 }
 
-pub fn prompt(args: Properties, target: Target) {
-    let context = Context::new(args, target);
+/// The format in which prompts and modules are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Text with ANSI escape sequences, as understood by the shell
+    #[default]
+    Text,
+    /// JSON describing each module, its segments and their styles
+    Json,
+}
+
+pub fn prompt(args: Properties, target: Target, output: OutputFormat) {
+    let prompt = match output {
+        OutputFormat::Text => daemon::render(&args, &target)
+            .unwrap_or_else(|| get_prompt_with_deadline(&Arc::new(Context::new(args, target)))),
+        OutputFormat::Json => get_prompt_json(&Context::new(args, target)),
+    };
     let stdout = io::stdout();
     let mut handle = stdout.lock();
 
-    write!(handle, "{}", get_prompt(&context)).unwrap();
+    write!(handle, "{prompt}").unwrap();
 }
 
 pub fn prompt_with_claude_code(args: Properties, target: Target) {
@@ -91,6 +110,47 @@ pub fn prompt_with_claude_code(args: Properties, target: Target) {
 }
 
 pub fn get_prompt(context: &Context) -> String {
+    render_prompt(context, &|variables, module_list| {
+        compute_variables(variables, context, module_list)
+    })
+    .0
+}
+
+/// Renders the prompt like [`get_prompt`], but only waits `render_timeout` for the modules.
+/// Modules which haven't finished by then are shown as `render_timeout_placeholder`.
+pub fn get_prompt_with_deadline(context: &Arc<Context<'static>>) -> String {
+    let timeout = context.root_config.render_timeout;
+    if timeout == 0 {
+        return get_prompt(context);
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(timeout);
+    render_prompt(context, &|variables, module_list| {
+        compute_variables_until(variables, context, module_list, deadline)
+    })
+    .0
+}
+
+/// Returns the modules of the prompt as JSON, in the order they appear in the format string.
+pub fn get_prompt_json(context: &Context) -> String {
+    let (_, modules) = render_prompt(context, &|variables, module_list| {
+        compute_variables(variables, context, module_list)
+    });
+    json::modules_to_string(&modules)
+}
+
+/// The modules computed for each variable of a format string, in the order they appear in
+type ComputedModules<'a> = IndexMap<String, Vec<Module<'a>>>;
+
+/// Computes the modules for the variables of a root format string,
+/// given the modules used in the main and right prompts
+type ComputeVariables<'a, 'b> = dyn Fn(&[String], &BTreeSet<String>) -> ComputedModules<'a> + 'b;
+
+/// Renders the prompt, returning it together with its modules.
+fn render_prompt<'a>(
+    context: &'a Context,
+    compute: &ComputeVariables<'a, '_>,
+) -> (String, Vec<Module<'a>>) {
     let config = &context.root_config;
     let mut buf = String::new();
 
@@ -98,7 +158,7 @@ pub fn get_prompt(context: &Context) -> String {
         Some(term) if term == "dumb" => {
             log::error!("Under a 'dumb' terminal (TERM=dumb).");
             buf.push_str("Starship disabled due to TERM=dumb > ");
-            return buf;
+            return (buf, Vec::new());
         }
         _ => {}
     }
@@ -110,47 +170,55 @@ pub fn get_prompt(context: &Context) -> String {
     }
 
     let (formatter, modules) = load_formatter_and_modules(context);
-
-    let formatter = formatter.map_variables_to_segments(|module| {
-        // Make $all display all modules not explicitly referenced
-        if module == "all" {
-            Some(Ok(all_modules_uniq(&modules)
-                .par_iter()
-                .flat_map(|module| {
-                    handle_module(module, context, &modules)
-                        .into_iter()
-                        .flat_map(|module| module.segments)
-                        .collect::<Vec<Segment>>()
-                })
-                .collect::<Vec<_>>()))
-        } else if context.is_module_disabled_in_config(module) {
-            None
-        } else {
-            // Get segments from module
-            Some(Ok(handle_module(module, context, &modules)
-                .into_iter()
-                .flat_map(|module| module.segments)
-                .collect::<Vec<Segment>>()))
+    let own_variables = formatter.get_variables_in_order();
+
+    // The other prompt shares the last line, so its modules are computed along with
+    // those of this prompt to measure it
+    let fit = context.width > 0 && uses_module_priorities(context);
+    let other_formatter = fit.then(|| other_prompt_formatter(context)).flatten();
+    // Keep the variables of the main prompt first, so both prompts drop modules in the same order
+    let other_variables = other_formatter
+        .as_ref()
+        .map(StringFormatter::get_variables_in_order)
+        .unwrap_or_default();
+    let (first, second) = if context.target == Target::Right {
+        (other_variables, own_variables.clone())
+    } else {
+        (own_variables.clone(), other_variables)
+    };
+    let mut variables = first;
+    for variable in second {
+        if !variables.contains(&variable) {
+            variables.push(variable);
         }
-    });
+    }
+    let mut computed = compute(&variables, &modules);
+
+    if fit {
+        fit_to_width(context, &formatter, other_formatter.as_ref(), &mut computed);
+    }
 
     // Creates a root module and prints it.
     let mut root_module = Module::new("Starship Root", "The root module", None);
-    root_module.set_segments(
-        formatter
-            .parse(None, Some(context))
-            .expect("Unexpected error returned in root format variables"),
-    );
+    root_module.set_segments(format_segments(formatter, &computed, context));
 
     let module_strings = root_module.ansi_strings_for_width(Some(context.width));
+    if config.semantic_prompt
+        && let Some(marker) = mark_command_end(context)
+    {
+        write!(buf, "{}", wrap_colorseq_for_shell(marker, context.shell)).unwrap();
+    }
     if config.add_newline && context.target != Target::Continuation {
         // continuation prompts normally do not include newlines, but they can
         writeln!(buf).unwrap();
     }
     // AnsiStrings strips redundant ANSI color sequences, so apply it before modifying the ANSI
     // color sequences for this specific shell
-    let shell_wrapped_output =
-        wrap_colorseq_for_shell(AnsiStrings(&module_strings).to_string(), context.shell);
+    let mut output = AnsiStrings(&module_strings).to_string();
+    if config.semantic_prompt {
+        output = mark_semantic_prompt(context, output);
+    }
+    let shell_wrapped_output = wrap_colorseq_for_shell(output, context.shell);
     write!(buf, "{shell_wrapped_output}").unwrap();
 
     if context.target == Target::Right {
@@ -165,12 +233,312 @@ pub fn get_prompt(context: &Context) -> String {
         buf = buf.replace('\n', " \\n");
     }
 
-    buf
+    let own_modules = computed
+        .into_iter()
+        .filter(|(variable, _)| own_variables.contains(variable))
+        .flat_map(|(_, modules)| modules)
+        .collect();
+    (buf, own_modules)
+}
+
+/// Returns the OSC 133 marker for the end of the previous command's output with its status,
+/// which is written before the main prompt if the shell passed a status
+fn mark_command_end(context: &Context) -> Option<String> {
+    // Nushell marks its prompts itself
+    if context.target != Target::Main || context.shell == Shell::Nu {
+        return None;
+    }
+    let status = context
+        .properties
+        .status_code
+        .as_deref()?
+        .parse::<i64>()
+        .ok()?;
+    Some(format!("\x1b]133;D;{status}\x07"))
+}
+
+/// Wraps a rendered prompt in OSC 133 markers, which let terminals jump between prompts and
+/// select the output of commands. The start of the command output is marked by the shell hooks
+/// in the init scripts, if the last prompt they printed was marked.
+fn mark_semantic_prompt(context: &Context, prompt: String) -> String {
+    const PROMPT_START: &str = "\x1b]133;A\x07";
+    const CONTINUATION_START: &str = "\x1b]133;A;k=s\x07";
+    const RIGHT_PROMPT_START: &str = "\x1b]133;P;k=r\x07";
+    const PROMPT_END: &str = "\x1b]133;B\x07";
+
+    // Nushell marks its prompts itself
+    if context.shell == Shell::Nu {
+        return prompt;
+    }
+    match context.target {
+        Target::Main => format!("{PROMPT_START}{prompt}{PROMPT_END}"),
+        Target::Continuation => format!("{CONTINUATION_START}{prompt}{PROMPT_END}"),
+        Target::Right => format!("{RIGHT_PROMPT_START}{prompt}"),
+        Target::Profile(_) => prompt,
+    }
+}
+
+/// Computes the modules for the variables of a root format string
+fn compute_variables<'a>(
+    variables: &[String],
+    context: &'a Context,
+    module_list: &BTreeSet<String>,
+) -> ComputedModules<'a> {
+    variables
+        .par_iter()
+        .filter_map(|variable| {
+            let modules = if variable == "all" {
+                // Make $all display all modules not explicitly referenced
+                all_modules_uniq(module_list)
+                    .par_iter()
+                    .flat_map(|module| handle_module(module, context, module_list))
+                    .collect()
+            } else if is_env_variable(variable) || context.is_module_disabled_in_config(variable) {
+                return None;
+            } else {
+                handle_module(variable, context, module_list)
+            };
+            Some((variable.clone(), modules))
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .collect()
 }
 
-pub fn module(module_name: &str, args: Properties) {
+/// Returns true for `${env:NAME}`, which the formatter replaces with an environment variable
+fn is_env_variable(variable: &str) -> bool {
+    variable.starts_with("env:")
+}
+
+/// Computes the modules for the variables of a root format string on the global thread pool,
+/// showing placeholders for the modules which haven't finished by `deadline`.
+fn compute_variables_until(
+    variables: &[String],
+    context: &Arc<Context<'static>>,
+    module_list: &BTreeSet<String>,
+    deadline: Instant,
+) -> ComputedModules<'static> {
+    // Compute the modules in `$all` separately, so a single slow module doesn't hide the others
+    let tasks = variables
+        .iter()
+        .filter(|variable| {
+            *variable == "all"
+                || !(is_env_variable(variable) || context.is_module_disabled_in_config(variable))
+        })
+        .flat_map(|variable| {
+            let modules = if variable == "all" {
+                all_modules_uniq(module_list)
+            } else {
+                vec![variable.clone()]
+            };
+            modules
+                .into_iter()
+                .map(move |module| (variable.clone(), module))
+        })
+        .collect::<Vec<_>>();
+
+    // Modules still running at the deadline are abandoned: their commands are stopped,
+    // so that they don't keep the threads of the pool busy
+    context.set_render_deadline(deadline);
+
+    let (tx, rx) = mpsc::channel();
+    for (index, (_, module)) in tasks.iter().enumerate() {
+        let context = Arc::clone(context);
+        let module_list = module_list.clone();
+        let module = module.clone();
+        let tx = tx.clone();
+        rayon::spawn(move || {
+            if context.is_abandoned() {
+                return;
+            }
+            let modules = handle_module(&module, &context, &module_list)
+                .into_iter()
+                .map(detach_module)
+                .collect::<Vec<_>>();
+            // A module which finished after the deadline may be missing the output of
+            // the commands that were stopped, and is shown as a placeholder instead
+            if !context.is_abandoned() {
+                let _ = tx.send((index, modules));
+            }
+        });
+    }
+    drop(tx);
+
+    let mut results = std::iter::repeat_with(|| None)
+        .take(tasks.len())
+        .collect::<Vec<_>>();
+    for _ in 0..tasks.len() {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, modules)) => results[index] = Some(modules),
+            Err(_) => break,
+        }
+    }
+
+    let mut computed = ComputedModules::new();
+    for ((variable, module), result) in tasks.into_iter().zip(results) {
+        let modules = result.unwrap_or_else(|| {
+            // The logger drops the messages already logged in this session
+            log::warn!(
+                "Module {module:?} did not finish within render_timeout ({}ms)",
+                context.root_config.render_timeout
+            );
+            vec![placeholder_module(&module, context)]
+        });
+        computed.entry(variable).or_default().extend(modules);
+    }
+    computed
+}
+
+/// Copies a module without its config, so it can outlive the context it was computed with
+fn detach_module(module: Module) -> Module<'static> {
+    let mut detached = Module::new(
+        module.get_name().as_str(),
+        module.get_description().as_str(),
+        None,
+    );
+    detached.duration = module.duration;
+    detached.set_segments(module.segments);
+    detached
+}
+
+/// A module showing `render_timeout_placeholder` in place of a module that took too long
+fn placeholder_module(name: &str, context: &Context) -> Module<'static> {
+    let mut module = Module::new(name, modules::description(name), None);
+    let segments = StringFormatter::new(&context.root_config.render_timeout_placeholder)
+        .map(|formatter| {
+            formatter.map(|variable| match variable {
+                "name" => Some(Ok(name)),
+                _ => None,
+            })
+        })
+        .and_then(|formatter| formatter.parse(None, Some(context)));
+    match segments {
+        Ok(segments) => module.set_segments(segments),
+        Err(e) => log::warn!("Error parsing `render_timeout_placeholder`: {e}"),
+    }
+    module
+}
+
+/// Formats the root format string with the segments of the computed modules
+fn format_segments(
+    formatter: StringFormatter,
+    computed: &ComputedModules,
+    context: &Context,
+) -> Vec<Segment> {
+    formatter
+        .map_variables_to_segments(|variable| {
+            computed.get(variable).map(|modules| {
+                Ok(modules
+                    .iter()
+                    .flat_map(|module| module.segments.clone())
+                    .collect())
+            })
+        })
+        .parse(None, Some(context))
+        .expect("Unexpected error returned in root format variables")
+}
+
+/// Returns true if any module in the config has a `priority`
+fn uses_module_priorities(context: &Context) -> bool {
+    let has_priority = |value: &toml::Value| {
+        value
+            .as_table()
+            .is_some_and(|table| table.contains_key("priority"))
+    };
+    context.config.config.as_ref().is_some_and(|config| {
+        config.values().any(|value| {
+            has_priority(value)
+                // `custom.<name>` and `env_var.<name>` modules
+                || value
+                    .as_table()
+                    .is_some_and(|table| table.values().any(has_priority))
+        })
+    })
+}
+
+/// The formatter of the prompt sharing the last line with the one being rendered
+fn other_prompt_formatter<'a>(context: &'a Context) -> Option<StringFormatter<'a>> {
+    let config = &context.root_config;
+    let format = match context.target {
+        Target::Main | Target::Profile(_) => config.right_format.as_str(),
+        Target::Right => config.format.as_str(),
+        Target::Continuation => return None,
+    };
+    StringFormatter::new(format).ok()
+}
+
+/// Drops the modules with the lowest `priority` until the prompt fits in the terminal.
+///
+/// The main prompt and the right prompt share the last line, so `computed` holds the
+/// modules of both, and the same modules are dropped no matter which of them is printed.
+fn fit_to_width(
+    context: &Context,
+    formatter: &StringFormatter,
+    other_formatter: Option<&StringFormatter>,
+    computed: &mut ComputedModules,
+) {
+    loop {
+        let main = format_segments(formatter.clone(), computed, context);
+        let other = other_formatter
+            .map(|other_formatter| format_segments(other_formatter.clone(), computed, context))
+            .unwrap_or_default();
+        let (left, right) = if context.target == Target::Right {
+            (&other, &main)
+        } else {
+            (&main, &other)
+        };
+        if prompt_fits(left, right, context.width) {
+            return;
+        }
+
+        // Drop the module with the lowest priority, or the last one of those that tie
+        let lowest = computed
+            .values_mut()
+            .flatten()
+            .filter(|module| !module.is_empty())
+            .filter_map(|module| Some((context.get_module_priority(module.get_name())?, module)))
+            .enumerate()
+            .min_by_key(|(index, (priority, _))| (*priority, std::cmp::Reverse(*index)));
+        let Some((_, (_, module))) = lowest else {
+            return;
+        };
+        log::debug!(
+            "Dropping module {} to fit the prompt in {} columns",
+            module.get_name(),
+            context.width
+        );
+        module.set_segments(Vec::new());
+    }
+}
+
+/// Returns true if every line of the left prompt fits in `width`,
+/// with the right prompt next to its last line
+fn prompt_fits(left: &[Segment], right: &[Segment], width: usize) -> bool {
+    // Fill segments shrink to the available space
+    let line_width = |segments: &[Segment]| {
+        segments
+            .iter()
+            .filter(|segment| !matches!(segment, Segment::Fill(_)))
+            .map(Segment::width_graphemes)
+            .sum::<usize>()
+    };
+    let lines = left
+        .split(|segment| matches!(segment, Segment::LineTerm))
+        .map(line_width)
+        .collect::<Vec<_>>();
+    let last_line = lines.last().copied().unwrap_or_default();
+
+    lines.iter().all(|line| *line <= width) && last_line + line_width(right) <= width
+}
+
+pub fn module(module_name: &str, args: Properties, output: OutputFormat) {
     let context = Context::new(args, Target::Main);
-    let module = get_module(module_name, &context).unwrap_or_default();
+    let module = match output {
+        OutputFormat::Text => get_module(module_name, &context).unwrap_or_default(),
+        OutputFormat::Json => {
+            json::module_to_string(modules::handle(module_name, &context).as_ref())
+        }
+    };
     print!("{module}");
 }
 
@@ -178,9 +546,19 @@ pub fn get_module(module_name: &str, context: &Context) -> Option<String> {
     modules::handle(module_name, context).map(|m| m.to_string())
 }
 
-pub fn timings(args: Properties) {
+pub fn timings(args: Properties, output: OutputFormat) {
     let context = Context::new(args, Target::Main);
 
+    if output == OutputFormat::Json {
+        let mut modules = compute_modules(&context)
+            .into_iter()
+            .filter(|module| !module.is_empty() || module.duration.as_millis() > 0)
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|m| std::cmp::Reverse(m.duration));
+        println!("{}", json::modules_to_string(&modules));
+        return;
+    }
+
     struct ModuleTiming {
         name: String,
         name_len: usize,
@@ -222,11 +600,46 @@ pub fn timings(args: Properties) {
             timing.value
         );
     }
+
+    let files = &context.config.files;
+    if !files.is_empty() {
+        println!();
+        let measured = context
+            .get_config_cache_dir()
+            .and_then(|cache_dir| config::measure_config_cache(files, &cache_dir));
+        match measured {
+            Some((parsed, cached)) => println!(
+                " Reading the config from the cache took {} instead of {} to parse it, saving {}",
+                format_precise_duration(cached),
+                format_precise_duration(parsed),
+                format_precise_duration(parsed.saturating_sub(cached)),
+            ),
+            None => println!(" Unable to read the config from the cache"),
+        }
+    }
 }
 
-pub fn explain(args: Properties) {
+/// Formats a duration in milliseconds with two decimals, for durations that are often
+/// shorter than a millisecond
+fn format_precise_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+pub fn explain(args: Properties, output: OutputFormat) {
     let context = Context::new(args, Target::Main);
 
+    static DONT_PRINT: &[&str] = &["line_break"];
+
+    if output == OutputFormat::Json {
+        let modules = compute_modules(&context)
+            .into_iter()
+            .filter(|module| !DONT_PRINT.contains(&module.get_name().as_str()))
+            .filter(|module| !module.is_empty())
+            .collect::<Vec<_>>();
+        println!("{}", json::modules_to_string(&modules));
+        return;
+    }
+
     struct ModuleInfo {
         value: String,
         value_len: usize,
@@ -234,8 +647,6 @@ pub fn explain(args: Properties) {
         duration: String,
     }
 
-    static DONT_PRINT: &[&str] = &["line_break"];
-
     let modules = compute_modules(&context)
         .into_iter()
         .filter(|module| !DONT_PRINT.contains(&module.get_name().as_str()))
@@ -459,18 +870,18 @@ fn load_formatter_and_modules<'a>(context: &'a Context) -> (StringFormatter<'a>,
 
     let (left_format_str, right_format_str): (&str, &str) = match context.target {
         Target::Main | Target::Right => (&config.format, &config.right_format),
-        Target::Profile(ref name) => {
-            if let Some(lf) = config
-                .user_profiles
-                .get(name)
-                .or_else(|| config.internal_profiles.get(name))
-            {
-                (lf, "")
-            } else {
-                log::error!("Profile {name:?} not found");
-                return (StringFormatter::raw(">"), BTreeSet::new());
-            }
-        }
+        Target::Profile(ref name) => match config.user_profiles.get(name) {
+            Some(Profile::Format(lf)) => (lf, ""),
+            // The formats of table profiles have been merged into the root config
+            Some(Profile::Config(_)) => (&config.format, &config.right_format),
+            None => match config.internal_profiles.get(name) {
+                Some(lf) => (lf, ""),
+                None => {
+                    log::error!("Profile {name:?} not found");
+                    return (StringFormatter::raw(">"), BTreeSet::new());
+                }
+            },
+        },
         Target::Continuation => unreachable!("Continuation prompt should have been handled above"),
     };
 
@@ -511,9 +922,54 @@ fn load_formatter_and_modules<'a>(context: &'a Context) -> (StringFormatter<'a>,
 #[cfg(feature = "config-schema")]
 pub fn print_schema() {
     let schema = schemars::schema_for!(crate::configs::FullConfig);
+    let mut schema = serde_json::to_value(schema).unwrap();
+    add_module_layout_options(&mut schema);
     println!("{}", serde_json::to_string_pretty(&schema).unwrap());
 }
 
+/// Adds the layout options, which are read from the raw config of every module,
+/// to the schema of each module config
+#[cfg(feature = "config-schema")]
+fn add_module_layout_options(schema: &mut serde_json::Value) {
+    let options = serde_json::json!({
+        "priority": {
+            "description": "When the prompt is wider than the terminal, modules with the lowest priority are hidden first. Modules without a priority are never hidden.",
+            "type": ["integer", "null"],
+            "format": "int64",
+        },
+        "min_width": {
+            "description": "Hide the module when the terminal is narrower than this number of columns.",
+            "type": ["integer", "null"],
+            "format": "uint",
+            "minimum": 0,
+        },
+    });
+
+    let module_configs = schema["properties"]
+        .as_object()
+        .into_iter()
+        .flat_map(serde_json::Map::values)
+        .filter_map(|property| {
+            property
+                .get("$ref")
+                // `custom.<name>` and `env_var.<name>`
+                .or_else(|| property.get("additionalProperties")?.get("$ref"))?
+                .as_str()?
+                .strip_prefix("#/$defs/")
+        })
+        .map(String::from)
+        .collect::<BTreeSet<_>>();
+
+    for name in module_configs {
+        if let Some(properties) = schema
+            .get_mut("$defs")
+            .and_then(|defs| defs.get_mut(&name)?.get_mut("properties")?.as_object_mut())
+        {
+            properties.extend(options.as_object().unwrap().clone());
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Preset(pub &'static str);
 
@@ -545,6 +1001,117 @@ pub fn preset_command(name: Option<Preset>, output: Option<PathBuf>, force: bool
     }
 }
 
+/// Prints the prompt of each preset for a sample project, so that they can be compared
+pub fn preset_preview(names: &[Preset]) {
+    let project = match create_preview_project() {
+        Ok(project) => project,
+        Err(e) => {
+            eprintln!("Unable to create a sample project for the preview: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    for (index, preset) in names.iter().enumerate() {
+        let prompt = match preview_prompt(project.path(), preset) {
+            Ok(prompt) => prompt,
+            Err(e) => {
+                eprintln!("Unable to parse the preset {}: {e}", preset.0);
+                continue;
+            }
+        };
+        if index > 0 {
+            println!();
+        }
+        println!("{}", nu_ansi_term::Style::new().bold().paint(preset.0));
+        println!("{prompt}");
+    }
+}
+
+/// Renders the prompt of `preset` for the sample project at `project`
+fn preview_prompt(project: &Path, preset: &Preset) -> Result<String, toml::de::Error> {
+    let config = toml::from_str(shadow::get_preset_content(preset.0))?;
+    let context = preview_context(project, config);
+    // The blank line of `add_newline` already separates the previews
+    Ok(get_prompt(&context).trim_start_matches('\n').to_string())
+}
+
+/// Home directory of the user in the previews
+const PREVIEW_HOME: &str = "/home/user";
+
+/// Outputs of the version commands of the languages in the sample project
+const PREVIEW_COMMAND_OUTPUTS: &[(&str, &str)] = &[
+    ("node --version", "v22.11.0\n"),
+    ("python --version", "Python 3.12.4\n"),
+    ("rustc --version", "rustc 1.85.0 (4d91de4e4 2025-02-17)\n"),
+];
+
+/// Creates a sample project for `preset_preview`: an empty git repository with a
+/// Rust crate, an npm package and a Python project
+fn create_preview_project() -> io::Result<tempfile::TempDir> {
+    let dir = tempfile::Builder::new()
+        .prefix("starship-preview")
+        .tempdir()?;
+    let path = dir.path();
+    fs::create_dir_all(path.join(".git/objects"))?;
+    fs::create_dir_all(path.join(".git/refs/heads"))?;
+    fs::write(path.join(".git/HEAD"), "ref: refs/heads/main\n")?;
+    fs::write(
+        path.join(".git/config"),
+        "[core]\n\trepositoryformatversion = 0\n\tbare = false\n",
+    )?;
+    fs::write(
+        path.join("Cargo.toml"),
+        "[package]\nname = \"sample\"\nversion = \"0.1.0\"\n",
+    )?;
+    fs::write(
+        path.join("package.json"),
+        "{ \"name\": \"sample\", \"version\": \"1.0.0\" }\n",
+    )?;
+    fs::write(
+        path.join("pyproject.toml"),
+        "[project]\nname = \"sample\"\n",
+    )?;
+    Ok(dir)
+}
+
+/// Creates a context for the sample project, which uses `config` and answers the commands
+/// run by modules with the outputs of `PREVIEW_COMMAND_OUTPUTS`
+fn preview_context(project: &Path, config: toml::Table) -> Context<'static> {
+    let mut properties = Properties::default();
+    properties.path = Some(project.to_path_buf());
+    properties.cmd_duration = Some("3200".to_string());
+    let config = StarshipConfig {
+        config: Some(config),
+        ..Default::default()
+    };
+    let outputs = PREVIEW_COMMAND_OUTPUTS
+        .iter()
+        .map(|(command, stdout)| {
+            let output = CommandOutput {
+                stdout: (*stdout).to_string(),
+                stderr: String::new(),
+            };
+            ((*command).to_string(), output)
+        })
+        .collect();
+
+    // Don't let the variables of the user's environment change the previews
+    let home = PathBuf::from(PREVIEW_HOME);
+    let env = Env::mocked(HashMap::from([("HOME", PREVIEW_HOME.to_string())]));
+    // Show a path like that of a real project, instead of the temporary directory.
+    // The escape sequences are printed directly to the terminal.
+    Context::new_with_shell_path_and_config(
+        properties,
+        Shell::Unknown,
+        Target::Main,
+        project.to_path_buf(),
+        home.join("projects").join("sample"),
+        env,
+        config,
+    )
+    .with_command_outputs(outputs)
+}
+
 fn preset_list() -> String {
     Preset::value_variants()
         .iter()
@@ -559,6 +1126,8 @@ mod test {
     use super::*;
     use crate::test::default_context;
     use crate::utils;
+    use nu_ansi_term::Color;
+    use std::path::Path;
 
     const NULL_DEVICE: &str = if cfg!(windows) { "NUL" } else { "/dev/null" };
 
@@ -577,6 +1146,112 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn prompt_json_lists_modules_in_format_order() {
+        let mut context = default_context().set_config(toml::toml! {
+                format="$username$character$line_break"
+                [character]
+                format="[>](bold green)"
+                [username]
+                format="$user"
+                show_always=true
+        });
+        context.env.insert("USER", "astronaut".to_string());
+
+        let json: serde_json::Value = serde_json::from_str(&get_prompt_json(&context)).unwrap();
+        let modules = json.as_array().unwrap();
+        let names = modules
+            .iter()
+            .map(|module| module["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["username", "character", "line_break"]);
+        assert_eq!(
+            modules[1]["segments"],
+            serde_json::json!([{
+                "text": ">",
+                "fg": "green",
+                "bg": null,
+                "attributes": ["bold"],
+            }])
+        );
+        assert_eq!(modules[1]["empty"], false);
+    }
+
+    fn layout_context(width: usize, target: Target) -> Context<'static> {
+        let mut context = default_context().set_config(toml::toml! {
+            add_newline = false
+            format = "${env_var.one}${env_var.two}${env_var.three}"
+            right_format = "|${env_var.four}"
+            [env_var.one]
+            format = "[1111](red)"
+            [env_var.two]
+            format = "2222"
+            priority = 5
+            [env_var.three]
+            format = "3333"
+            priority = 1
+            [env_var.four]
+            format = "4444"
+            priority = 1
+        });
+        for name in ["one", "two", "three", "four"] {
+            context.env.insert(name, name.to_string());
+        }
+        context.width = width;
+        context.target = target;
+        context
+    }
+
+    #[test]
+    fn prompt_fits_without_dropping_modules() {
+        let context = layout_context(17, Target::Main);
+        assert_eq!(
+            get_prompt(&context),
+            format!("{}22223333", Color::Red.paint("1111"))
+        );
+        let context = layout_context(17, Target::Right);
+        assert_eq!(get_prompt(&context), "|4444");
+    }
+
+    #[test]
+    fn prompt_drops_lowest_priority_modules() {
+        // The last module with the lowest priority is dropped first, which is the one of
+        // the right prompt, no matter which prompt is printed
+        let context = layout_context(16, Target::Main);
+        assert_eq!(
+            get_prompt(&context),
+            format!("{}22223333", Color::Red.paint("1111"))
+        );
+        let context = layout_context(16, Target::Right);
+        assert_eq!(get_prompt(&context), "|");
+        let context = layout_context(12, Target::Main);
+        assert_eq!(
+            get_prompt(&context),
+            format!("{}2222", Color::Red.paint("1111"))
+        );
+        let context = layout_context(8, Target::Main);
+        assert_eq!(get_prompt(&context), Color::Red.paint("1111").to_string());
+
+        // Modules without a priority are never dropped
+        let context = layout_context(2, Target::Main);
+        assert_eq!(get_prompt(&context), Color::Red.paint("1111").to_string());
+    }
+
+    #[test]
+    fn prompt_hides_modules_below_min_width() {
+        let mut context = default_context().set_config(toml::toml! {
+            add_newline = false
+            format = "$character"
+            [character]
+            format = ">"
+            min_width = 40
+        });
+        context.width = 40;
+        assert_eq!(get_prompt(&context), ">");
+        context.width = 39;
+        assert_eq!(get_prompt(&context), "");
+    }
+
     #[test]
     fn right_prompt() {
         let mut context = default_context().set_config(toml::toml! {
@@ -723,12 +1398,135 @@ mod test {
         dir.close()
     }
 
+    #[test]
+    fn preset_previews_render() -> io::Result<()> {
+        let project = create_preview_project()?;
+        for preset in Preset::value_variants() {
+            let prompt = preview_prompt(project.path(), preset).unwrap();
+            assert!(!prompt.trim().is_empty(), "empty preview of {}", preset.0);
+        }
+        project.close()
+    }
+
+    #[test]
+    fn preset_preview_sample_project() -> io::Result<()> {
+        let project = create_preview_project()?;
+        let config = toml::toml! {
+            format = "$directory $git_branch"
+            add_newline = false
+            [directory]
+            format = "$path"
+            [git_branch]
+            format = "$branch"
+        };
+        let context = preview_context(project.path(), config);
+        assert_eq!(get_prompt(&context), "~/projects/sample main");
+        project.close()
+    }
+
     #[test]
     #[cfg(feature = "config-schema")]
     fn print_schema_does_not_panic() {
         print_schema();
     }
 
+    #[test]
+    fn semantic_prompt_markers() {
+        let mut context = default_context().set_config(toml::toml! {
+            add_newline = false
+            semantic_prompt = true
+            format = ">"
+            right_format = "<"
+            continuation_prompt = "+"
+        });
+        assert_eq!(get_prompt(&context), "\x1b]133;A\x07>\x1b]133;B\x07");
+
+        // The end of the previous command's output is marked with its status
+        context.properties.status_code = Some("1".to_string());
+        assert_eq!(
+            get_prompt(&context),
+            "\x1b]133;D;1\x07\x1b]133;A\x07>\x1b]133;B\x07"
+        );
+
+        context.target = Target::Right;
+        assert_eq!(get_prompt(&context), "\x1b]133;P;k=r\x07<");
+
+        context.target = Target::Continuation;
+        assert_eq!(get_prompt(&context), "\x1b]133;A;k=s\x07+\x1b]133;B\x07");
+
+        context.shell = Shell::Zsh;
+        assert_eq!(
+            get_prompt(&context),
+            "%{\x1b]133;A;k=s\x07%}+%{\x1b]133;B\x07%}"
+        );
+    }
+
+    fn render_timeout_context(render_timeout: u64, dir: &Path) -> Context<'static> {
+        let mut context = default_context().set_config(toml::toml! {
+            add_newline = false
+            format = "${env_var.one}${custom.slow}"
+            render_timeout = render_timeout
+            render_timeout_placeholder = "<$name>"
+            [env_var.one]
+            format = "1111"
+            [custom.slow]
+            command = "sleep 1 && echo slow"
+            when = true
+            ignore_timeout = true
+            shell = ["sh"]
+            format = "$output"
+        });
+        context.env.insert("one", "one".to_string());
+        context.current_dir = dir.to_path_buf();
+        context
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn render_timeout_shows_placeholder_for_slow_modules() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let context = Arc::new(render_timeout_context(100, dir.path()));
+        let start = Instant::now();
+        assert_eq!(get_prompt_with_deadline(&context), "1111<custom.slow>");
+        assert!(start.elapsed() < Duration::from_secs(1));
+        dir.close()
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn render_timeout_stops_commands_of_slow_modules() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut context = render_timeout_context(100, dir.path());
+        context.config.config.as_mut().unwrap()["custom"]["slow"]["command"] =
+            "sleep 1 && touch finished".into();
+        assert_eq!(
+            get_prompt_with_deadline(&Arc::new(context)),
+            "1111<custom.slow>"
+        );
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!dir.path().join("finished").exists());
+        dir.close()
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn render_timeout_zero_waits_for_all_modules() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let context = Arc::new(render_timeout_context(0, dir.path()));
+        assert_eq!(get_prompt_with_deadline(&context), "1111slow");
+        dir.close()
+    }
+
+    #[test]
+    fn env_variables_in_root_format() {
+        let mut context = default_context().set_config(toml::toml! {
+            add_newline = false
+            format = "${env:HOST}@${env:SHELL_NAME:-sh}"
+        });
+        context.env.insert("HOST", "box".to_string());
+        assert_eq!(get_prompt(&context), "box@sh");
+    }
+
     #[test]
     fn custom_expands() -> std::io::Result<()> {
         let dir = tempfile::tempdir()?;
@@ -854,6 +1652,78 @@ mod test {
         dir.close()
     }
 
+    #[test]
+    fn table_profile() {
+        let config = StarshipConfig {
+            config: Some(toml::toml! {
+                format = "$all"
+                right_format = "$character"
+                continuation_prompt = "..."
+                [character]
+                format = ">>"
+                [profiles.base]
+                format = "0_0$character"
+                right_format = "R"
+                continuation_prompt = "><>"
+                [profiles.work]
+                extends = "base"
+                [profiles.work.character]
+                format = "<<"
+            }),
+            ..Default::default()
+        };
+        let mut properties = Properties::default();
+        properties.profile = Some("work".to_string());
+
+        // The root `add_newline` and `right_format` are not inherited
+        let context = Context::new_with_config(
+            properties.clone(),
+            Target::Profile("work".to_string()),
+            config.clone(),
+        );
+        assert_eq!(get_prompt(&context), "0_0<<");
+
+        let context = Context::new_with_config(properties.clone(), Target::Right, config.clone());
+        assert_eq!(get_prompt(&context), "R");
+
+        let context = Context::new_with_config(properties, Target::Continuation, config);
+        assert_eq!(get_prompt(&context), "><>");
+    }
+
+    #[test]
+    fn profile_rules() {
+        let config = StarshipConfig {
+            config: Some(toml::toml! {
+                format = "$all"
+                right_format = "$character"
+                add_newline = false
+                [character]
+                format = ">>"
+                [profiles]
+                minimal = "0_0$character"
+                [[profile_rules]]
+                profile = "minimal"
+            }),
+            ..Default::default()
+        };
+
+        let context = Context::new_with_config(Properties::default(), Target::Main, config.clone());
+        assert_eq!(context.properties.profile.as_deref(), Some("minimal"));
+        assert_eq!(get_prompt(&context), "0_0>>");
+
+        // A format string profile has no right prompt
+        let context =
+            Context::new_with_config(Properties::default(), Target::Right, config.clone());
+        assert_eq!(get_prompt(&context), "");
+
+        // `--profile` takes precedence
+        let mut properties = Properties::default();
+        properties.profile = Some("other".to_string());
+        let context = Context::new_with_config(properties, Target::Right, config);
+        assert_eq!(context.properties.profile.as_deref(), Some("other"));
+        assert_eq!(get_prompt(&context), ">>");
+    }
+
     #[test]
     fn test_prefer_user_profile() {
         let mut context = default_context().set_config(toml::toml! {
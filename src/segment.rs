@@ -13,14 +13,21 @@ pub struct TextSegment {
 
     /// The string value of the current segment.
     value: String,
+
+    /// The URL the segment links to, if any. Rendered as an OSC 8 hyperlink.
+    link: Option<String>,
 }
 
 impl TextSegment {
     // Returns the AnsiString of the segment value
     fn ansi_string(&self, prev: Option<&AnsiStyle>) -> AnsiString<'_> {
-        match self.style {
+        let ansi_string = match self.style {
             Some(style) => style.to_ansi_style(prev).paint(&self.value),
             None => AnsiString::from(&self.value),
+        };
+        match &self.link {
+            Some(link) if !self.value.is_empty() => ansi_string.hyperlink(link.as_str()),
+            _ => ansi_string,
         }
     }
 }
@@ -108,6 +115,7 @@ impl Segment {
             segs.push(Self::Text(TextSegment {
                 value: String::from(s),
                 style,
+                link: None,
             }));
         });
         segs
@@ -148,6 +156,23 @@ impl Segment {
         }
     }
 
+    /// Links the segment to `link`, unless it already links somewhere else
+    pub fn set_link_if_empty(&mut self, link: &str) {
+        if let Self::Text(ts) = self
+            && ts.link.is_none()
+        {
+            ts.link = Some(link.to_string());
+        }
+    }
+
+    /// The URL the segment links to
+    pub fn link(&self) -> Option<&str> {
+        match self {
+            Self::Text(ts) => ts.link.as_deref(),
+            Self::Fill(_) | Self::LineTerm => None,
+        }
+    }
+
     pub fn value(&self) -> &str {
         match self {
             Self::Fill(fs) => &fs.value,
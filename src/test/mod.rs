@@ -66,7 +66,7 @@ pub fn default_context() -> Context<'static> {
         PathBuf::new(),
         Env::default(),
     );
-    context.config = StarshipConfig { config: None };
+    context.config = StarshipConfig::default();
     context
 }
 
@@ -1,49 +1,61 @@
-#[cfg(test)]
 use std::collections::HashMap;
-#[cfg(not(test))]
 use std::env;
 use std::ffi::OsString;
 
-#[derive(Default)]
 pub struct Env<'a> {
-    /// A `HashMap` of environment variable mocks
-    #[cfg(test)]
-    pub env: HashMap<&'a str, String>,
+    /// A `HashMap` of environment variable mocks, used instead of the process environment if set
+    pub env: Option<HashMap<&'a str, String>>,
+}
 
-    #[cfg(not(test))]
-    _marker: std::marker::PhantomData<&'a ()>,
+impl Default for Env<'_> {
+    /// The process environment, or an empty set of mocks in testing mode
+    fn default() -> Self {
+        Self {
+            env: cfg!(test).then(HashMap::new),
+        }
+    }
 }
 
-#[cfg_attr(not(test), allow(clippy::needless_lifetimes))]
 impl<'a> Env<'a> {
-    // Retrieves a environment variable from the os or from a table if in testing mode
-    #[cfg(test)]
-    pub fn get_env<K: AsRef<str>>(&self, key: K) -> Option<String> {
-        self.env
-            .get(key.as_ref())
-            .map(std::string::ToString::to_string)
+    /// An environment that only contains `vars`, instead of those of the process
+    pub fn mocked(vars: HashMap<&'a str, String>) -> Self {
+        Self { env: Some(vars) }
     }
 
-    #[cfg(not(test))]
-    #[inline]
+    /// Whether the variables are mocks instead of the process environment
+    pub fn is_mocked(&self) -> bool {
+        self.env.is_some()
+    }
+
+    // Retrieves a environment variable from the os or from the table of mocks
     pub fn get_env<K: AsRef<str>>(&self, key: K) -> Option<String> {
-        env::var(key.as_ref()).ok()
+        match &self.env {
+            Some(vars) => vars.get(key.as_ref()).cloned(),
+            None => env::var(key.as_ref()).ok(),
+        }
     }
 
-    // Retrieves a environment variable from the os or from a table if in testing mode (os version)
-    #[cfg(test)]
+    // Retrieves a environment variable from the os or from the table of mocks (os version)
     pub fn get_env_os<K: AsRef<str>>(&self, key: K) -> Option<OsString> {
-        self.env.get(key.as_ref()).map(OsString::from)
+        match &self.env {
+            Some(vars) => vars.get(key.as_ref()).map(OsString::from),
+            None => env::var_os(key.as_ref()),
+        }
     }
 
-    #[cfg(not(test))]
-    #[inline]
-    pub fn get_env_os<K: AsRef<str>>(&self, key: K) -> Option<OsString> {
-        env::var_os(key.as_ref())
+    /// Names of all environment variables
+    pub fn names(&self) -> Vec<String> {
+        match &self.env {
+            Some(vars) => vars.keys().map(ToString::to_string).collect(),
+            None => env::vars_os()
+                .filter_map(|(name, _)| name.into_string().ok())
+                .collect(),
+        }
     }
 
+    /// Adds a mock, replacing the process environment if it wasn't mocked yet
     #[cfg(test)]
     pub fn insert(&mut self, k: &'a str, v: String) -> Option<String> {
-        self.env.insert(k, v)
+        self.env.get_or_insert_default().insert(k, v)
     }
 }
@@ -1,7 +1,9 @@
 pub mod env;
 pub mod serde;
 pub mod statusline;
+pub mod terminal;
 
+use ::serde::{Deserialize, Serialize};
 use process_control::{ChildExt, Control};
 use std::ffi::OsStr;
 use std::fmt::Debug;
@@ -13,6 +15,7 @@ use std::time::{Duration, Instant};
 
 use crate::context::Context;
 use crate::context::Shell;
+use crate::formatter::string_formatter::shell_prompt_escape;
 
 /// Default timeout for command execution in milliseconds
 pub const DEFAULT_COMMAND_TIMEOUT_MS: u64 = 500;
@@ -189,7 +192,7 @@ pub fn create_command<T: AsRef<OsStr>>(binary_name: T) -> Result<Command> {
     Ok(cmd)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandOutput {
     pub stdout: String,
     pub stderr: String,
@@ -201,7 +204,7 @@ impl PartialEq for CommandOutput {
     }
 }
 
-#[cfg(test)]
+/// Joins a command and its arguments with spaces
 pub fn display_command<T: AsRef<OsStr> + Debug, U: AsRef<OsStr> + Debug>(
     cmd: T,
     args: &[U],
@@ -656,6 +659,9 @@ pub fn wrap_colorseq_for_shell(ansi: String, shell: Shell) -> String {
 /// Many shells cannot deal with raw unprintable characters and miscompute the cursor position,
 /// leading to strange visual bugs like duplicated/missing chars. This function wraps a specified
 /// sequence in shell-specific escapes to avoid these problems.
+///
+/// OSC 8 hyperlinks (`ESC ]8;;url ESC \`) and OSC 133 prompt markers are wrapped up to their
+/// string terminator (`ESC \` or `BEL`) instead of `escape_end`, since the URL may contain it.
 pub fn wrap_seq_for_shell(
     ansi: String,
     shell: Shell,
@@ -670,23 +676,50 @@ pub fn wrap_seq_for_shell(
         _ => return ansi,
     };
 
+    const OSC_BEGIN: &[&str] = &["\u{1b}]8;", "\u{1b}]133;"];
+    const STRING_TERMINATOR: &str = "\u{1b}\\";
+    const BELL: char = '\u{7}';
+
     // ANSI escape codes cannot be nested, so we can keep track of whether we're
     // in an escape or not with a single boolean variable
     let mut escaped = false;
-    let final_string: String = ansi
-        .chars()
-        .map(|x| {
-            if x == escape_begin && !escaped {
-                escaped = true;
-                format!("{beg}{escape_begin}")
-            } else if x == escape_end && escaped {
-                escaped = false;
-                format!("{escape_end}{end}")
-            } else {
-                x.to_string()
-            }
-        })
-        .collect();
+    let mut final_string = String::with_capacity(ansi.len());
+    let mut rest = ansi.as_str();
+    while let Some(x) = rest.chars().next() {
+        if !escaped && escape_begin == '\u{1b}' && OSC_BEGIN.iter().any(|b| rest.starts_with(b)) {
+            let len = rest
+                .char_indices()
+                .skip(1)
+                .find_map(|(i, c)| match c {
+                    BELL => Some(i + 1),
+                    _ if rest[i..].starts_with(STRING_TERMINATOR) => {
+                        Some(i + STRING_TERMINATOR.len())
+                    }
+                    _ => None,
+                })
+                .unwrap_or(rest.len());
+            let (sequence, tail) = rest.split_at(len);
+            // URLs are interpreted by the shell like the rest of the prompt
+            final_string.push_str(beg);
+            final_string.push_str(&shell_prompt_escape(sequence, shell));
+            final_string.push_str(end);
+            rest = tail;
+            continue;
+        }
+
+        if x == escape_begin && !escaped {
+            escaped = true;
+            final_string.push_str(beg);
+            final_string.push(escape_begin);
+        } else if x == escape_end && escaped {
+            escaped = false;
+            final_string.push(escape_end);
+            final_string.push_str(end);
+        } else {
+            final_string.push(x);
+        }
+        rest = &rest[x.len_utf8()..];
+    }
     final_string
 }
 
@@ -839,6 +872,46 @@ pub fn home_dir() -> Option<PathBuf> {
     dirs::home_dir()
 }
 
+/// Matches `text` against a glob `pattern`, where `*` matches any characters except `/`,
+/// `**` matches any characters and `?` matches a single character other than `/`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let text = text.chars().collect::<Vec<_>>();
+    // Whether the pattern read so far matches the first `i` characters of the text
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+
+    let mut pattern = pattern.chars().peekable();
+    while let Some(c) = pattern.next() {
+        let mut next = vec![false; text.len() + 1];
+        match c {
+            '*' if pattern.next_if_eq(&'*').is_some() => {
+                let mut any = false;
+                for i in 0..=text.len() {
+                    any |= matched[i];
+                    next[i] = any;
+                }
+            }
+            '*' => {
+                for i in 0..=text.len() {
+                    next[i] = matched[i] || (i > 0 && next[i - 1] && text[i - 1] != '/');
+                }
+            }
+            '?' => {
+                for i in 0..text.len() {
+                    next[i + 1] = matched[i] && text[i] != '/';
+                }
+            }
+            c => {
+                for i in 0..text.len() {
+                    next[i + 1] = matched[i] && text[i] == c;
+                }
+            }
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
 const HEXTABLE: &[char] = &[
     '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
 ];
@@ -921,6 +994,26 @@ mod tests {
         assert_eq!(render_time(86_400_000_u128, false), "1d0h0m0s");
     }
 
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.example.com", "dev.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("web-??", "web-01"));
+        assert!(!glob_match("web-??", "web-1"));
+        assert!(glob_match("/home/*/src", "/home/user/src"));
+        assert!(!glob_match("/home/*", "/home/user/src"));
+        assert!(glob_match("/home/**", "/home/user/src"));
+        assert!(glob_match("/home/**/src", "/home/user/work/src"));
+        assert!(glob_match("exact", "exact"));
+        assert!(!glob_match("exact", "exactly"));
+        // Patterns with many stars don't take exponential time to fail
+        assert!(!glob_match(
+            &"*a".repeat(30),
+            &format!("{}b", "a".repeat(60))
+        ));
+        assert!(!glob_match(&"**a".repeat(30), &"a".repeat(29)));
+    }
+
     #[test]
     fn test_humanize_int() {
         assert_eq!(humanize_int(0), "0");
@@ -1079,6 +1172,35 @@ mod tests {
         assert_eq!(&bresult5, "");
     }
 
+    #[test]
+    fn test_hyperlink_sequence_wrappers() {
+        let link = "\x1b[34m\x1b]8;;file:///home/m%20y\x1b\\dir\x1b]8;;\x1b\\\x1b[0m";
+
+        assert_eq!(
+            wrap_colorseq_for_shell(link.to_string(), Shell::Zsh),
+            "%{\x1b[34m%}%{\x1b]8;;file:///home/m%%20y\x1b\\%}dir%{\x1b]8;;\x1b\\%}%{\x1b[0m%}"
+        );
+        assert_eq!(
+            wrap_colorseq_for_shell(link.to_string(), Shell::Bash),
+            "\\[\x1b[34m\\]\\[\x1b]8;;file:///home/m%20y\x1b\\\\\\]dir\\[\x1b]8;;\x1b\\\\\\]\\[\x1b[0m\\]"
+        );
+        assert_eq!(wrap_colorseq_for_shell(link.to_string(), Shell::Fish), link);
+    }
+
+    #[test]
+    fn test_semantic_prompt_sequence_wrappers() {
+        let prompt = "\x1b]133;A\x07\x1b[31m>\x1b[0m\x1b]133;B\x07";
+
+        assert_eq!(
+            wrap_colorseq_for_shell(prompt.to_string(), Shell::Zsh),
+            "%{\x1b]133;A\x07%}%{\x1b[31m%}>%{\x1b[0m%}%{\x1b]133;B\x07%}"
+        );
+        assert_eq!(
+            wrap_colorseq_for_shell(prompt.to_string(), Shell::Bash),
+            "\\[\x1b]133;A\x07\\]\\[\x1b[31m\\]>\\[\x1b[0m\\]\\[\x1b]133;B\x07\\]"
+        );
+    }
+
     #[test]
     fn test_get_command_string_output() {
         let case1 = CommandOutput {
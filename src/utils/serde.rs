@@ -1,3 +1,4 @@
+use crate::configs::MODULE_LAYOUT_OPTIONS;
 use crate::module::ALL_MODULES;
 use serde::de::{
     self, Deserializer, Error, IntoDeserializer, Visitor,
@@ -195,6 +196,16 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
             return visitor.visit_none();
         }
 
+        // Layout options are shared by all modules and read by the prompt renderer
+        if self
+            .info
+            .filter(|StructInfo { name, .. }| name != &"StarshipRootConfig")
+            .and(self.current_key)
+            .is_some_and(|key| MODULE_LAYOUT_OPTIONS.contains(&key))
+        {
+            return visitor.visit_none();
+        }
+
         if !self.error_on_ignored {
             return visitor.visit_none();
         }
@@ -500,6 +511,25 @@ mod test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_deserialize_module_layout_options() {
+        let value = toml::toml! {
+            foo = "bar"
+            priority = 10
+            min_width = 80
+        };
+        let deserializer = ValueDeserializer::new(&value);
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Sample {
+            foo: String,
+        }
+
+        let result = Sample::deserialize(deserializer).unwrap();
+        assert_eq!(result.foo, "bar");
+    }
+
     #[test]
     fn test_deserialize_unknown_typo() {
         let value = toml::toml! {
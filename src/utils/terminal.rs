@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+/// Asks the terminal for its background color with an OSC 11 query. Returns `None` if there
+/// is no terminal, or it doesn't answer within `timeout`.
+#[cfg(not(windows))]
+pub fn query_background_color(timeout: Duration) -> Option<(u8, u8, u8)> {
+    use nix::sys::termios::{self, LocalFlags, SetArg, SpecialCharacterIndices};
+    use std::io::{Read, Write};
+    use std::time::Instant;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let original = termios::tcgetattr(&tty).ok()?;
+    let mut raw = original.clone();
+    raw.local_flags
+        .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    // Let reads return after a tenth of a second without input
+    raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+    raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 1;
+    termios::tcsetattr(&tty, SetArg::TCSANOW, &raw).ok()?;
+
+    let mut response = Vec::new();
+    let mut answered = false;
+    if tty.write_all(b"\x1b]11;?\x07").is_ok() {
+        let start = Instant::now();
+        let mut byte = [0; 1];
+        // Read a byte at a time, so that nothing typed after the answer is consumed
+        while start.elapsed() < timeout {
+            match tty.read(&mut byte) {
+                Ok(0) => continue,
+                Ok(_) => response.push(byte[0]),
+                Err(_) => break,
+            }
+            // The answer ends with BEL or ST, like the query
+            if response.ends_with(b"\x07") || response.ends_with(b"\x1b\\") {
+                answered = true;
+                break;
+            }
+        }
+    }
+
+    // Without an answer in time, drop any input so that a late answer doesn't end up
+    // in the shell's line editor
+    let when = if answered {
+        SetArg::TCSANOW
+    } else {
+        SetArg::TCSAFLUSH
+    };
+    if let Err(e) = termios::tcsetattr(&tty, when, &original) {
+        log::warn!("Unable to restore the terminal settings: {e}");
+    }
+    parse_background_color(&String::from_utf8_lossy(&response))
+}
+
+#[cfg(windows)]
+pub fn query_background_color(_timeout: Duration) -> Option<(u8, u8, u8)> {
+    None
+}
+
+/// Parses an answer to the query like `ESC ] 11 ; rgb:ffff/ffff/ffff BEL`, where each
+/// channel has one to four hex digits
+fn parse_background_color(response: &str) -> Option<(u8, u8, u8)> {
+    let (_, color) = response.split_once("rgb:")?;
+    let color = color.trim_end_matches(['\x07', '\x1b', '\\']);
+
+    let mut channels = color.split('/').map(|channel| {
+        if !(1..=4).contains(&channel.len()) {
+            return None;
+        }
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1 << (4 * channel.len())) - 1;
+        u8::try_from(value * 255 / max).ok()
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_background_colors() {
+        assert_eq!(
+            parse_background_color("\x1b]11;rgb:ffff/ffff/ffff\x07"),
+            Some((255, 255, 255))
+        );
+        assert_eq!(
+            parse_background_color("\x1b]11;rgb:1e1e/2020/3030\x1b\\"),
+            Some((30, 32, 48))
+        );
+        assert_eq!(
+            parse_background_color("\x1b]11;rgb:f/80/000\x07"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(parse_background_color(""), None);
+        assert_eq!(parse_background_color("\x1b]11;rgb:ffff/ffff\x07"), None);
+        assert_eq!(parse_background_color("\x1b]11;rgb:fffff/0/0\x07"), None);
+    }
+}